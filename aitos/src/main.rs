@@ -5,9 +5,7 @@
 #![allow(incomplete_features)]
 #![allow(static_mut_refs)]
 
-#![allow(unused_variables, unused_mut, unreachable_code, unused_assignments)] // RMME: Debugging
-
-mod monitor;
+mod storage;
 
 use alloc::boxed::Box;
 use core::mem::MaybeUninit;
@@ -16,14 +14,19 @@ use core::mem::MaybeUninit;
 use defmt::{debug, error, info, warn};
 // use defmt::*;
 use defmt_rtt as _;
-use displaitor::{App, AudioID};
+use displaitor::{
+    AudioID, AudioOut, BufferMode, Controls, DisplayInfo, Event, HeapUsage, Input, PanelLayout,
+    Runtime, Screen, Settings, TrackingAlloc,
+};
 use embedded_alloc::LlffHeap as Heap;
 #[allow(unused_imports)]
 use embedded_graphics::{pixelcolor::Rgb565, prelude::*};
 #[allow(unused_imports)]
 use embedded_hal::digital::v2::{InputPin, OutputPin, ToggleableOutputPin};
+#[cfg(feature="audio")]
 use embedded_hal::PwmPin;
 use hub75_pio::{self, dma::DMAExt, lut::GammaLut};
+#[cfg(feature="audio")]
 use qoa_decoder::QoaDecoder;
 use rp2040_hal::gpio::FunctionPwm;
 use rp2040_hal::pwm;
@@ -36,7 +39,7 @@ use panic_probe as _;
 
 use bsp::entry;
 use bsp::hal::{
-    clocks::{init_clocks_and_plls, Clock},
+    clocks::init_clocks_and_plls,
     pac,
     sio::Sio,
     watchdog::Watchdog,
@@ -46,14 +49,20 @@ use rp_pico::{self as bsp};
 extern crate alloc;
 
 const COLOR_DEPTH: usize = 10;
-static mut DISPLAY_BUFFER: hub75_pio::DisplayMemory<64, 32, COLOR_DEPTH> =
-    hub75_pio::DisplayMemory::new();
+/// Size of the display buffer, the apps are laid out for it
+const PANEL: DisplayInfo = DisplayInfo::PANEL_64X32;
+static mut DISPLAY_BUFFER: hub75_pio::DisplayMemory<
+    { PANEL.size.width as usize },
+    { PANEL.size.height as usize },
+    COLOR_DEPTH,
+> = hub75_pio::DisplayMemory::new();
 
 // type AudioPwm = Pin<DynPinId, FunctionPwm, PullNone>;
 // type AudioPwm = rp2040_hal::pwm::Channel<rp2040_hal::pwm::Slice<rp2040_hal::pwm::Pwm0, rp2040_hal::pwm::FreeRunning>, rp2040_hal::pwm::A>;
 // type AudioPwm = rp2040_hal::pwm::Channel<>;
 // static mut PWM_AUDIO_CHANNEL: Option<&'static mut AudioPwm> = None;
-static mut AUDIO_ENABLE: bool = true;
+/// 0 (mute) to 255 (full)
+static mut AUDIO_VOLUME: u8 = 255;
 
 #[entry]
 fn main() -> ! {
@@ -62,9 +71,8 @@ fn main() -> ! {
 
     info!("Init peripherals ..");
     let mut pac = pac::Peripherals::take().unwrap();
-    let core = pac::CorePeripherals::take().unwrap();
     let mut watchdog = Watchdog::new(pac.WATCHDOG);
-    let mut sio = Sio::new(pac.SIO);
+    let sio = Sio::new(pac.SIO);
 
     // External high-speed crystal on the pico board is 12Mhz
     let external_xtal_freq_hz = 12_000_000u32;
@@ -80,9 +88,6 @@ fn main() -> ! {
     .ok()
     .unwrap();
 
-    #[allow(unused_variables, unused_mut)]
-    let mut delay = cortex_m::delay::Delay::new(core.SYST, clocks.system_clock.freq().to_Hz());
-
     let pins = bsp::Pins::new(
         pac.IO_BANK0,
         pac.PADS_BANK0,
@@ -195,7 +200,7 @@ fn main() -> ! {
     }
     // Prepare pin
     // GPIO27 is connected to PWM channel 5B
-    let mut _pin_audio_pwm = pins
+    let _pin_audio_pwm = pins
         .gpio27
         .into_pull_type::<PullNone>()
         .into_function::<FunctionPwm>()
@@ -203,16 +208,24 @@ fn main() -> ! {
 
     // --------------- MISC --------------------
     // let mut pin_led = pins.gpio27.into_push_pull_output();
-    let mut pin_led = pins.gpio28.into_push_pull_output();
+    let _pin_led = pins.gpio28.into_push_pull_output();
     let mut pin_ce_led_pwr = pins.gpio22.into_push_pull_output();
     let mut pin_ce_lvl_shft = pins.gpio19.into_push_pull_output();
     let _pin_i2c_pdc_sda = pins.gpio20.into_floating_input();
     let _pin_i2c_pdc_scl = pins.gpio21.into_floating_input();
-    fn calc_lut() -> GammaLut<COLOR_DEPTH, Rgb565, hub75_pio::lut::Init> {
+    fn calc_lut(gamma: f32) -> GammaLut<COLOR_DEPTH, Rgb565, hub75_pio::lut::Init> {
         let lut = GammaLut::new();
-        lut.init((1.0, 1.0, 1.0))
+        lut.init((gamma, gamma, gamma))
     }
 
+    info!("Load settings ..");
+    let mut flash_storage = storage::mount();
+    let settings = flash_storage.as_mut().and_then(storage::load_settings).unwrap_or_else(|| {
+        warn!("No valid settings stored, using the defaults");
+        Settings::default()
+    });
+    let boot_gamma_tenths = settings.gamma_tenths;
+
     // --------------- Control --------------------
     let pin_dpad_u = pins.gpio16.into_pull_up_input();
     let pin_dpad_d = pins.gpio17.into_pull_up_input();
@@ -224,10 +237,10 @@ fn main() -> ! {
     pin_ce_led_pwr.set_high().unwrap();
     pin_ce_lvl_shft.set_low().unwrap();
 
-    let lut = Box::new(calc_lut());
+    let lut = Box::new(calc_lut(settings.gamma()));
     let lut = Box::leak(lut);
     let benchmark = true;
-    let mut display = unsafe {
+    let panel_driver = unsafe {
         hub75_pio::Display::new(
             &mut DISPLAY_BUFFER,
             hub75_pins,
@@ -238,44 +251,29 @@ fn main() -> ! {
             lut,
         )
     };
-    // let mut display = display.transformed(Transform::Rotate180);
-
-    info!("Init splash screen & app ..");
-    let mut app_splash_screen = displaitor::startup_app();
-    let mut app = displaitor::main_app();
 
     // µs resolution
     let timer = Timer::new(pac.TIMER, &mut resets, &clocks);
-    let mut time_last_us = 0;
     unsafe { 
         TIMER = Some(timer.clone());
     }
 
-    let mut monitor = monitor::Monitor::new();
-
     #[cfg(feature="audio")]
     {
         info!("Initialize second core ..");
         static mut CORE1_STACK: multicore::Stack<6144> = multicore::Stack::new();
-        let mut mc = multicore::Multicore::new(&mut pac.PSM, &mut pac.PPB, &mut sio.fifo);
+        let mut fifo = sio.fifo;
+        let mut mc = multicore::Multicore::new(&mut pac.PSM, &mut pac.PPB, &mut fifo);
         let cores = mc.cores();
         let core1 = &mut cores[1];
-        let _test = core1.spawn(unsafe { &mut CORE1_STACK.mem }, core1_task);
+        match core1.spawn(unsafe { &mut CORE1_STACK.mem }, core1_task) {
+            Ok(()) => storage::core1_started(),
+            Err(_) => error!("Failed to spawn core 1, there is no sound"),
+        }
     }
 
-    audio_set(AudioID::MusicDepp);
-
-    info!("Splash screen");
-    while !app_splash_screen.close_request() {
-        pin_led.set_high().unwrap(); // High ~ Update phase
-
-        // Update time
-        let time_current_us = timer.get_counter().ticks();
-        let dt_us = time_current_us - time_last_us;
-        time_last_us = time_current_us;
-
-        // Read controls
-        let controls = displaitor::Controls::new(
+    let buttons = Buttons::new(move || {
+        Controls::new(
             pin_button_a.is_high().unwrap(),
             pin_button_b.is_high().unwrap(),
             pin_button_s.is_high().unwrap(),
@@ -283,85 +281,216 @@ fn main() -> ! {
             pin_dpad_d.is_high().unwrap(),
             pin_dpad_l.is_high().unwrap(),
             pin_dpad_r.is_high().unwrap(),
-        );
+        )
+    });
+    let screen = Hub75Screen {
+        commit: hub75_pio::Display::commit,
+        boot_gamma_tenths,
+    };
+    // The orientation is set from the settings
+    let mut runtime: Runtime<_, _, _, _, _, storage::FlashStorage> = Runtime::new(
+        panel_driver,
+        PanelLayout::single(PANEL.size),
+        screen,
+        buttons,
+        TimerClock(timer),
+        CoreAudio,
+    )
+    .with_log(log)
+    .with_heap_usage(|| HeapUsage {
+        used: HEAP.inner().used(),
+        free: HEAP.inner().free(),
+    });
+    if let Some(flash_storage) = flash_storage {
+        runtime = runtime.with_storage(flash_storage);
+    }
+    // After the storage, so it starts with the stored volume
+    runtime = runtime.with_status_bar();
 
-        // Update, Render & swap frame buffers
-        let update_result =
-            app_splash_screen.update(dt_us as i64, time_current_us as i64, &controls);
-        pin_led.set_low().unwrap(); // Low ~ Render & FB swap
+    info!("Init splash screen & app ..");
+    let app_splash_screen = displaitor::startup_app();
+    let settings = runtime.settings().clone();
+    let mut app = displaitor::main_app(&settings, runtime.storage_mut(), PANEL);
 
-        if update_result.visible_changes() {
-            app_splash_screen.render(&mut display);
-            display.commit();
+    info!("Splash screen");
+    let running = match app_splash_screen {
+        Ok(mut app_splash_screen) => runtime.run_app(&mut app_splash_screen),
+        Err(error) => {
+            warn!("No splash screen: {}", error);
+            true
         }
+    };
+    if running {
+        info!("Start loop");
+        runtime.run_app(&mut app);
+    }
+    // The buttons are always available and the navigator of the main app never closes its root,
+    // so this is only reached on a bug. Starting over beats a frozen badge.
+    error!("The main app closed, resetting");
+    cortex_m::peripheral::SCB::sys_reset()
+}
+
+/// Commits the frames to the panel driver.
+struct Hub75Screen<D> {
+    commit: fn(&mut D),
+    /// The LUT is handed to the display driver for good, so a new gamma is applied after a reset.
+    boot_gamma_tenths: u8,
+}
 
-        let _ = monitor.tick(time_current_us as u32);
+impl<D> Screen<D> for Hub75Screen<D> {
+    /// The driver hands out a blank buffer after every commit and has no way to keep the last
+    /// frame, so the badge doesn't benefit from dirty regions: every change is a full clear and
+    /// redraw. Only frames without any change are skipped.
+    fn buffer_mode(&self) -> BufferMode {
+        BufferMode::ClearedOnCommit
     }
 
+    fn present(&mut self, display: &mut D) {
+        (self.commit)(display);
+    }
 
-    // let mut app = Mutex::new(app);
-    // let app_copy = app.borrow(|app| app.clone());
-    info!("Start loop");
-    audio_reset();
-    // run_app_to_completion();
-    let mut button_s_history: u8 = 0;
-    loop {
-        pin_led.set_high().unwrap(); // High ~ Update phase
+    fn set_gamma_tenths(&mut self, gamma_tenths: u8) {
+        if gamma_tenths != self.boot_gamma_tenths {
+            info!("Gamma {} is applied after a reset", gamma_tenths);
+        }
+    }
+}
 
-        // Update time
-        let time_current_us = timer.get_counter().ticks();
-        let dt_us = time_current_us - time_last_us;
-        time_last_us = time_current_us;
+/// Reads the buttons. With the `debug-overlay` feature, pressing A, B and S together toggles the
+/// debug overlay.
+struct Buttons<F> {
+    read: F,
+    #[cfg(feature = "debug-overlay")]
+    show_debug_overlay: bool,
+    /// A, B and S were pressed together and not all of them are released yet
+    #[cfg(feature = "debug-overlay")]
+    chord_held: bool,
+}
 
-        // Read controls
-        let controls = displaitor::Controls::new(
-            pin_button_a.is_high().unwrap(),
-            pin_button_b.is_high().unwrap(),
-            pin_button_s.is_high().unwrap(),
-            pin_dpad_u.is_high().unwrap(),
-            pin_dpad_d.is_high().unwrap(),
-            pin_dpad_l.is_high().unwrap(),
-            pin_dpad_r.is_high().unwrap(),
-        );
-        if controls.buttons_s {
-            // info!("Button S    pressed!");
+impl<F: FnMut() -> Controls> Buttons<F> {
+    fn new(read: F) -> Self {
+        Self {
+            read,
+            #[cfg(feature = "debug-overlay")]
+            show_debug_overlay: false,
+            #[cfg(feature = "debug-overlay")]
+            chord_held: false,
         }
-        else {
-            warn!("Button S de-pressed!");
+    }
+
+    /// Toggles the overlay on the chord and hides A, B and S from the app, until all of them are
+    /// released. Otherwise releasing them would pause or close the app.
+    #[cfg(feature = "debug-overlay")]
+    fn take_chord(&mut self, controls: &mut Controls) {
+        let all_held = controls.buttons_a && controls.buttons_b && controls.buttons_s;
+        let any_held = controls.buttons_a || controls.buttons_b || controls.buttons_s;
+        if all_held && !self.chord_held {
+            self.show_debug_overlay = !self.show_debug_overlay;
+            self.chord_held = true;
+        } else if !any_held {
+            self.chord_held = false;
         }
-        button_s_history = (button_s_history << 1) | controls.buttons_s as u8;
-        if button_s_history == 0b1111_0000 {
-            let new = !unsafe{AUDIO_ENABLE};
-            unsafe{AUDIO_ENABLE = !new};
-            info!("Toggle audio: {}", new); 
+        if self.chord_held {
+            controls.buttons_a = false;
+            controls.buttons_b = false;
+            controls.buttons_s = false;
         }
+    }
+}
+
+impl<F: FnMut() -> Controls> Input for Buttons<F> {
+    fn poll(&mut self) -> Option<Controls> {
+        #[allow(unused_mut)]
+        let mut controls = (self.read)();
+        #[cfg(feature = "debug-overlay")]
+        self.take_chord(&mut controls);
+        // The buttons are always available, so the runtime never stops
+        Some(controls)
+    }
 
-        // Update, Render & swap frame buffers
-        let update_result = app.update(dt_us as i64, time_current_us as i64, &controls);
+    #[cfg(feature = "debug-overlay")]
+    fn show_debug_overlay(&self) -> bool {
+        self.show_debug_overlay
+    }
+}
 
-        // Update Sound Subsystem
-        if let Some(audio_id) = update_result.audio_queue_request() {
-            audio_set(audio_id);
-        }
+struct TimerClock(Timer);
 
-        // Update Display
-        pin_led.set_low().unwrap(); // Low ~ Render & FB swap
-        if update_result.visible_changes() {
-            app.render(&mut display);
-            display.commit();
-        }
+impl displaitor::Clock for TimerClock {
+    fn now_us(&mut self) -> u64 {
+        self.0.get_counter().ticks()
+    }
 
-        let _ = monitor.tick(time_current_us as u32);
-        cortex_m::asm::delay(400);
+    /// Busy waits, the loop has nothing else to do until the next update or button sample.
+    fn wait_us(&mut self, us: u64) {
+        let until = self.0.get_counter().ticks() + us;
+        while self.0.get_counter().ticks() < until {}
     }
 }
 
-fn run_app_to_completion() {
-    todo!("TODO: PTR: Dies.");
+/// Hands the requests to core 1, which plays them. Only a single channel is available, so sound
+/// effects replace the music.
+struct CoreAudio;
+
+impl AudioOut for CoreAudio {
+    fn play(&mut self, id: AudioID) {
+        audio_set(id);
+    }
+
+    fn stop(&mut self) {
+        audio_set(AudioID::Stop);
+    }
+
+    fn set_volume(&mut self, level: u8) {
+        unsafe { AUDIO_VOLUME = level };
+    }
 }
 
-fn audio_reset() {
-    let _ = unsafe{AUDIO_ID.take()};
+fn log(event: &Event) {
+    match event {
+        Event::Settings(settings) => debug!("Settings: {}", defmt::Debug2Format(settings)),
+        Event::Stored { key, len } => info!("Stored {} bytes for key {}", len, key),
+        Event::StoreFailed { key, error } => {
+            warn!("Failed to store key {}: {}", key, defmt::Debug2Format(error))
+        }
+        Event::NotStored { key } => warn!("No storage, key {} is not persisted", key),
+        Event::Notify(message) => info!("Notification: {}", message),
+        Event::Clock(clock) => debug!("Clock: {}", clock),
+        Event::Unhandled(command) => warn!("Unhandled command: {}", defmt::Debug2Format(command)),
+        Event::Dropped { commands } => warn!("{} commands did not fit and were dropped", commands),
+        Event::Stats(stats) => {
+            debug!(
+                "FPS: {:03}Hz | dt {:04}ms | updates: {:03}",
+                stats.renders_per_s(),
+                stats.period_us / 1000,
+                stats.updates
+            );
+            let heap = HEAP.stats();
+            debug!(
+                "Heap: {} bytes, peak {}, largest {}, {} allocations",
+                heap.current, heap.peak, heap.largest, heap.allocations
+            );
+            for app in HEAP.apps() {
+                debug!("Heap of {}: {} bytes, peak {}", app.name, app.stats.current, app.stats.peak);
+            }
+        }
+        Event::Profile(profiler) => {
+            for app in profiler.apps() {
+                let profile = &app.profile;
+                debug!(
+                    "{}: {} frames | avg/max µs update {}/{} render {}/{} present {}/{}",
+                    app.name.as_str(),
+                    profile.frame.count,
+                    profile.update.avg_us(),
+                    profile.update.max_us,
+                    profile.render.avg_us(),
+                    profile.render.max_us,
+                    profile.present.avg_us(),
+                    profile.present.max_us
+                );
+            }
+        }
+    }
 }
 
 fn audio_set(audio_id: AudioID) {
@@ -375,15 +504,10 @@ static mut PWM_AUDIO_CHANNEL: Option<&'static mut AudioPwm> = None;
 static mut TIMER: Option<Timer> = None;
 static mut AUDIO_ID: Option<AudioID> = None;
 
-// fn init_pwm(pac: rp2040_hal::pac::Peripherals, resets: &mut rp2040_hal::pac::RESETS) {
-// }
-
-// mod qoa;
-// pub use qoa::QoaDecoder;
-
 #[cfg(feature="audio")]
-fn core1_task() -> () {
+fn core1_task() {
     while unsafe { PWM_AUDIO_CHANNEL.is_none() || TIMER.is_none() } {
+        storage::park_if_requested();
         cortex_m::asm::dmb();
     }
 
@@ -391,16 +515,15 @@ fn core1_task() -> () {
     let timer = unsafe { TIMER.take().expect("Timer initialized") };
 
     play_audio(audio_pin,  &timer);
-    // loop {
-    //     cortex_m::asm::wfi();
-    // }
 }
 
 /// Converts a signed 16‑bit sample (range: –32768..32767) into a PWM duty cycle (0..max_duty).
+#[cfg(feature="audio")]
 fn sample_to_duty(sample: i16, max_duty: u16) -> u16 {
     (((sample as i32 + 32768) as u32 * (max_duty as u32)) / 65535) as u16
 }
 
+#[cfg(feature="audio")]
 struct CurrentAudio {
     id: AudioID,
     audio: QoaDecoder<'static>,
@@ -408,6 +531,7 @@ struct CurrentAudio {
     sample_period_us: u32,
 }
 
+#[cfg(feature="audio")]
 impl CurrentAudio {
     fn new(id: AudioID) -> Option<Self> {
         let audio = QoaDecoder::new(id.into_audio_file()?).expect("QOA is valid");
@@ -433,6 +557,7 @@ impl CurrentAudio {
 
 /// Plays the embedded QOA file on the provided PWM pin. This function never returns.
 /// It uses the cortex‑m asm delay (assuming a 125 MHz clock) to wait for the sample period.
+#[cfg(feature="audio")]
 pub fn play_audio<P>(pwm: &mut P, timer: &Timer) -> !
 where
     P: PwmPin<Duty = u16>,
@@ -445,28 +570,22 @@ where
     let mut sample_count = 0;
     let mut last_audio_id = None;
     loop {
+        storage::park_if_requested();
 
-        // Update audio queue request
-        let mut reset_audio = false;
+        // Update audio queue request. The audio playing already keeps playing, anything else
+        // replaces it. Nothing is requested while the audio is disabled.
         if let Some(audio) = unsafe {AUDIO_ID.take()} {
-            match &last_audio_id {
-                Some(last_audio) if &audio == last_audio => {
-                    reset_audio = true;
+            if last_audio_id != Some(audio) {
+                last_audio_id = Some(audio);
+
+                current_audio = CurrentAudio::new(audio);
+                if let Some( audio) = &current_audio {
+                    audio.print();
                 }
-                // If we are not playing anything, or something different
-                _ if unsafe{AUDIO_ENABLE} == true => {
-                    last_audio_id = Some(audio);
-
-                    current_audio = CurrentAudio::new(audio);
-                    if let Some( audio) = &current_audio {
-                        audio.print();
-                    }
-                    else 
-                    {
-                        info!("Stopping audio");
-                    }
+                else 
+                {
+                    info!("Stopping audio");
                 }
-                _ => { }
             }
         }
 
@@ -484,6 +603,7 @@ where
 
             if let Some(samples) = current_audio.audio.next_sample() {
                 let sample = samples; // [1];
+                let sample = (sample as i32 * unsafe { AUDIO_VOLUME } as i32 / 255) as i16;
 
                 let duty = sample_to_duty(sample, pwm.get_max_duty());
                 pwm.set_duty(duty);
@@ -516,20 +636,26 @@ where
     }
 }
 
+/// Tracks the usage, so we know how close the apps get to running out of memory
 #[global_allocator]
-static HEAP: Heap = Heap::empty();
+static HEAP: TrackingAlloc<Heap> = TrackingAlloc::new(Heap::empty());
 
 pub fn heap_init() {
     // Initialize the allocator BEFORE you use it
     use core::mem::MaybeUninit;
     const HEAP_SIZE_COL: usize = 3 * 2 * (1 << COLOR_DEPTH);
     const HEAP_SIZE_APP: usize = 8 * 1024;
+    // The two frames of a transition between a menu and an app, next to the app
+    const HEAP_SIZE_TRANSITION: usize = 2
+        * PANEL.size.width as usize
+        * PANEL.size.height as usize
+        * core::mem::size_of::<Rgb565>();
 
-    const HEAP_SIZE: usize = HEAP_SIZE_COL + HEAP_SIZE_APP;
+    const HEAP_SIZE: usize = HEAP_SIZE_COL + HEAP_SIZE_APP + HEAP_SIZE_TRANSITION;
 
     // #[link_section = ".heap"]
     static mut HEAP_MEM: [MaybeUninit<u8>; HEAP_SIZE] = [MaybeUninit::uninit(); HEAP_SIZE];
-    unsafe { HEAP.init(&raw mut HEAP_MEM as usize, HEAP_SIZE) };
+    unsafe { HEAP.inner().init(&raw mut HEAP_MEM as usize, HEAP_SIZE) };
 }
 
 // #[cfg(not(test))]
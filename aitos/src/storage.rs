@@ -0,0 +1,135 @@
+//! Keeps the persisted values in a reserved flash region, right after `FLASH` in `memory.x`.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+#[allow(unused_imports)]
+use defmt::{info, warn};
+use displaitor::{BlockDevice, LogStorage, Settings, Storage, SETTINGS_KEY};
+
+const XIP_BASE: u32 = 0x1000_0000;
+/// Offset from the start of the flash. Must be erase sector aligned.
+const STORAGE_OFFSET: u32 = 16000 * 1024;
+const SECTOR_SIZE: usize = 4096;
+const SECTOR_COUNT: usize = 16;
+/// Smallest unit which can be programmed
+const PAGE_SIZE: usize = 256;
+const _: () = assert!(STORAGE_OFFSET as usize % SECTOR_SIZE == 0);
+const _: () = assert!(STORAGE_OFFSET as usize + SECTOR_COUNT * SECTOR_SIZE <= 16 * 1024 * 1024);
+
+/// Set by core 0 while it writes the flash. Core 1 must not execute from flash meanwhile.
+static FLASH_WRITE_REQUEST: AtomicBool = AtomicBool::new(false);
+static CORE1_PARKED: AtomicBool = AtomicBool::new(false);
+/// Set by core 0 once it spawned core 1. Writes before, e.g. compacting while mounting, have no
+/// core to park.
+static CORE1_STARTED: AtomicBool = AtomicBool::new(false);
+
+pub type FlashStorage = LogStorage<Flash>;
+
+/// The storage region of the flash. Erasing and programming block for up to tens of milliseconds.
+/// Core 1 is parked in RAM meanwhile.
+pub struct Flash;
+
+impl BlockDevice for Flash {
+    type Error = core::convert::Infallible;
+
+    fn sector_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn sector_count(&self) -> usize {
+        SECTOR_COUNT
+    }
+
+    fn page_size(&self) -> usize {
+        PAGE_SIZE
+    }
+
+    fn read(&mut self, offset: usize, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        // The flash is memory mapped, so it can be read directly.
+        let region = unsafe {
+            core::slice::from_raw_parts(
+                (XIP_BASE + STORAGE_OFFSET) as *const u8,
+                SECTOR_COUNT * SECTOR_SIZE,
+            )
+        };
+        buffer.copy_from_slice(&region[offset..offset + buffer.len()]);
+        Ok(())
+    }
+
+    fn erase(&mut self, sector: usize) -> Result<(), Self::Error> {
+        let address = STORAGE_OFFSET + (sector * SECTOR_SIZE) as u32;
+        with_core1_parked(|| {
+            cortex_m::interrupt::free(|_| unsafe {
+                rp2040_flash::flash::flash_range_erase(address, SECTOR_SIZE as u32, true);
+            })
+        });
+        Ok(())
+    }
+
+    fn program(&mut self, offset: usize, data: &[u8]) -> Result<(), Self::Error> {
+        let address = STORAGE_OFFSET + offset as u32;
+        with_core1_parked(|| {
+            cortex_m::interrupt::free(|_| unsafe {
+                rp2040_flash::flash::flash_range_program(address, data, true);
+            })
+        });
+        Ok(())
+    }
+}
+
+/// `None`, if the region is in use by something else.
+pub fn mount() -> Option<FlashStorage> {
+    match LogStorage::mount(Flash) {
+        Ok(storage) => Some(storage),
+        Err(err) => {
+            warn!("Failed to mount the storage: {}", defmt::Debug2Format(&err));
+            None
+        }
+    }
+}
+
+/// The persisted settings, if a valid record is stored.
+pub fn load_settings(storage: &mut FlashStorage) -> Option<Settings> {
+    let mut bytes = [0; displaitor::MAX_VALUE_LEN];
+    let len = storage.read(SETTINGS_KEY, &mut bytes).ok()??;
+    Settings::from_bytes(&bytes[..len.min(bytes.len())])
+}
+
+/// Has to be called by core 0 right after it spawned core 1, which calls [`park_if_requested`]
+/// from then on.
+#[cfg(feature = "audio")]
+pub fn core1_started() {
+    CORE1_STARTED.store(true, Ordering::Release);
+}
+
+fn with_core1_parked(f: impl FnOnce()) {
+    let park = CORE1_STARTED.load(Ordering::Acquire);
+    if park {
+        FLASH_WRITE_REQUEST.store(true, Ordering::Release);
+        while !CORE1_PARKED.load(Ordering::Acquire) {}
+    }
+    f();
+    FLASH_WRITE_REQUEST.store(false, Ordering::Release);
+    if park {
+        // Wait for core 1 to leave the loop in RAM. Otherwise a write right after this one would
+        // still see it parked, while it already runs from flash again.
+        while CORE1_PARKED.load(Ordering::Acquire) {}
+    }
+}
+
+/// Has to be called regularly by core 1. Waits in RAM while core 0 writes the flash.
+#[cfg(feature = "audio")]
+pub fn park_if_requested() {
+    if FLASH_WRITE_REQUEST.load(Ordering::Acquire) {
+        cortex_m::interrupt::free(|_| park_in_ram());
+    }
+}
+
+#[cfg(feature = "audio")]
+#[inline(never)]
+#[link_section = ".data.ram_func"]
+fn park_in_ram() {
+    CORE1_PARKED.store(true, Ordering::Release);
+    while FLASH_WRITE_REQUEST.load(Ordering::Acquire) {}
+    CORE1_PARKED.store(false, Ordering::Release);
+}
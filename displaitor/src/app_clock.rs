@@ -0,0 +1,169 @@
+/// Speed of the wall clock
+pub const NORMAL_SPEED_PERCENT: u16 = 100;
+/// Fastest speed an [`AppClock`] runs at, ten times the wall clock
+pub const MAX_SPEED_PERCENT: u16 = 1_000;
+
+/// Debugging controls of the time of the running app, e.g. from hotkeys of the simulator.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, defmt::Format)]
+pub enum TimeControl {
+    TogglePause,
+    /// Pauses and runs a single update
+    Step,
+    /// Halves the speed
+    Slower,
+    /// Doubles the speed
+    Faster,
+    NormalSpeed,
+}
+
+/// Time as an app sees it. It starts at zero when the app is opened, stands still while the app
+/// is paused and can run slower or faster than the wall clock, e.g. in slow motion for debugging
+/// or fast-forward in tests.
+///
+/// The owner of the app passes the elapsed wall clock time to [`AppClock::advance`] and hands the
+/// returned time and [`AppClock::t_us`] to the app, so apps never see time which passed while
+/// they were paused or closed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, defmt::Format)]
+pub struct AppClock {
+    t_us: i64,
+    paused: bool,
+    speed_percent: u16,
+    /// Scaled time below 1 µs, so no time is lost in slow motion
+    remainder: i64,
+    /// Time passing while paused, see [`AppClock::step`]
+    step_us: i64,
+}
+
+impl AppClock {
+    pub const fn new() -> Self {
+        Self {
+            t_us: 0,
+            paused: false,
+            speed_percent: NORMAL_SPEED_PERCENT,
+            remainder: 0,
+            step_us: 0,
+        }
+    }
+
+    /// Starts over at zero and running, e.g. when the app is opened again. The speed is kept.
+    pub fn reset(&mut self) {
+        *self = Self {
+            speed_percent: self.speed_percent,
+            ..Self::new()
+        };
+    }
+
+    /// Time passed since the last reset
+    pub fn t_us(&self) -> i64 {
+        self.t_us
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.step_us = 0;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        match self.paused {
+            true => self.resume(),
+            false => self.pause(),
+        }
+    }
+
+    pub fn speed_percent(&self) -> u16 {
+        self.speed_percent
+    }
+
+    /// 50 runs at half, 200 at twice the speed of the wall clock. Limited to
+    /// [`MAX_SPEED_PERCENT`], 0 stands still like a pause.
+    pub fn set_speed_percent(&mut self, percent: u16) {
+        self.speed_percent = percent.min(MAX_SPEED_PERCENT);
+        self.remainder = 0;
+    }
+
+    /// Lets `us` pass with the next [`AppClock::advance`], even though the clock is paused. Steps
+    /// through a paused app a frame at a time.
+    pub fn step(&mut self, us: i64) {
+        if self.paused {
+            self.step_us += us.max(0);
+        }
+    }
+
+    /// Applies `control`, a step lets `tick_us` pass.
+    pub fn apply(&mut self, control: TimeControl, tick_us: i64) {
+        match control {
+            TimeControl::TogglePause => self.toggle_pause(),
+            TimeControl::Step => {
+                self.pause();
+                self.step(tick_us);
+            }
+            TimeControl::Slower => self.set_speed_percent((self.speed_percent / 2).max(1)),
+            TimeControl::Faster => self.set_speed_percent(self.speed_percent.saturating_mul(2)),
+            TimeControl::NormalSpeed => self.set_speed_percent(NORMAL_SPEED_PERCENT),
+        }
+    }
+
+    /// Adds `elapsed_us` of wall clock time and returns the time, which passed for the app.
+    pub fn advance(&mut self, elapsed_us: i64) -> i64 {
+        let dt_us = match self.paused {
+            true => core::mem::take(&mut self.step_us),
+            false => {
+                let scaled = elapsed_us.max(0) * self.speed_percent as i64 + self.remainder;
+                self.remainder = scaled % NORMAL_SPEED_PERCENT as i64;
+                scaled / NORMAL_SPEED_PERCENT as i64
+            }
+        };
+        self.t_us += dt_us;
+        dt_us
+    }
+}
+
+impl Default for AppClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scales_without_losing_time() {
+        let mut clock = AppClock::new();
+        clock.set_speed_percent(30);
+        let passed: i64 = (0..10).map(|_| clock.advance(10)).sum();
+        assert_eq!(passed, 30);
+        assert_eq!(clock.t_us(), 30);
+
+        clock.set_speed_percent(u16::MAX);
+        assert_eq!(clock.speed_percent(), MAX_SPEED_PERCENT);
+        assert_eq!(clock.advance(10), 100);
+    }
+
+    #[test]
+    fn steps_while_paused() {
+        let mut clock = AppClock::new();
+        assert_eq!(clock.advance(1_000), 1_000);
+        clock.pause();
+        assert_eq!(clock.advance(60_000_000), 0);
+        clock.step(20_000);
+        assert_eq!(clock.advance(1_000), 20_000);
+        assert_eq!(clock.advance(1_000), 0);
+        assert_eq!(clock.t_us(), 21_000);
+
+        clock.set_speed_percent(50);
+        clock.reset();
+        assert!(!clock.is_paused());
+        assert_eq!(clock.t_us(), 0);
+        assert_eq!(clock.advance(1_000), 500);
+    }
+}
@@ -0,0 +1,229 @@
+use embedded_graphics::prelude::*;
+
+use crate::{
+    trait_app::{Color, RenderStatus, UpdateResult},
+    App, AppClock, Controls, Transition,
+};
+
+/// What happened to the open entry in an update.
+pub(crate) enum ChildUpdate {
+    /// The result of its `update`
+    Updated(UpdateResult),
+    /// It requested to be closed and was closed, with the result of its `on_exit`
+    Closed(UpdateResult),
+}
+
+/// The entry a container opened and shows instead of itself.
+///
+/// Runs the lifecycle of the entries the same way in all containers: an entry is reset and entered,
+/// when it is opened, and left and torn down, when it is closed. The container keeps the entries
+/// and passes the open one in.
+///
+/// The open entry gets its own time, which starts at zero when it is opened or restarted,
+/// independent of the time the container is given. The heap it uses is attributed to its name,
+/// see [`crate::attribute_heap_to`].
+///
+/// With a transition, the container blends to the entry, when it is opened, and back, when the
+/// entry asks to be closed. Neither of them is updated while it runs.
+pub(crate) struct ActiveChild<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    index: Option<usize>,
+    clock: AppClock,
+    /// App the heap usage was attributed to before the entry was opened
+    heap_app: Option<&'static str>,
+    transition: Option<Transition<D, C>>,
+    /// The entry asked to be closed. It is closed, once the transition captured its view.
+    closing: bool,
+}
+
+impl<D, C> ActiveChild<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    pub fn new() -> Self {
+        Self {
+            index: None,
+            clock: AppClock::new(),
+            heap_app: None,
+            transition: None,
+            closing: false,
+        }
+    }
+
+    pub fn set_transition(&mut self, transition: Transition<D, C>) {
+        self.transition = Some(transition);
+    }
+
+    pub fn transition_running(&self) -> bool {
+        self.transition
+            .as_ref()
+            .is_some_and(|transition| transition.is_running())
+    }
+
+    /// Index of the open entry
+    pub fn index(&self) -> Option<usize> {
+        self.index
+    }
+
+    /// Follows the open entry, after the container moved it.
+    pub fn shift(&mut self, index: impl FnOnce(usize) -> usize) {
+        self.index = self.index.map(index);
+    }
+
+    /// Forgets the open entry without closing it, e.g. when the container starts over.
+    pub fn reset(&mut self) {
+        self.index = None;
+        self.stop_transition();
+    }
+
+    /// Marks the entry `index` named `name` as open, as it is. It is entered together with the
+    /// container.
+    pub fn preselect(&mut self, index: usize, name: &'static str) {
+        self.index = Some(index);
+        self.clock.reset();
+        self.heap_app = crate::attribute_heap_to(Some(name));
+    }
+
+    /// Resets and enters `app`, the entry `index` named `name`. Returns the result of its
+    /// `on_enter`.
+    pub fn open<A>(&mut self, index: usize, name: &'static str, app: &mut A) -> UpdateResult
+    where
+        A: App<Target = D, Color = C> + ?Sized,
+    {
+        self.heap_app = crate::attribute_heap_to(Some(name));
+        app.reset_state();
+        self.clock.reset();
+        let entered = app.on_enter();
+        self.index = Some(index);
+        if let Some(transition) = self.transition.as_mut() {
+            transition.request();
+        }
+        entered
+    }
+
+    /// Leaves and tears down `app`, the open entry, right away. Returns the result of its
+    /// `on_exit`.
+    pub fn close<A>(&mut self, app: &mut A) -> UpdateResult
+    where
+        A: App<Target = D, Color = C> + ?Sized,
+    {
+        self.stop_transition();
+        self.leave(app)
+    }
+
+    fn leave<A>(&mut self, app: &mut A) -> UpdateResult
+    where
+        A: App<Target = D, Color = C> + ?Sized,
+    {
+        let exited = app.on_exit();
+        app.teardown();
+        self.index = None;
+        crate::attribute_heap_to(self.heap_app.take());
+        exited
+    }
+
+    /// Updates `app`, the open entry, with its own time. `dt` is the time, which passed for the
+    /// container. If the entry requested to be closed, it is closed instead, after the transition
+    /// captured it.
+    pub fn update<A>(&mut self, app: &mut A, dt: i64, controls: &Controls) -> ChildUpdate
+    where
+        A: App<Target = D, Color = C> + ?Sized,
+    {
+        if self.update_transition(dt) {
+            if self.closing && self.transition.as_ref().is_some_and(|t| t.is_captured()) {
+                self.closing = false;
+                return ChildUpdate::Closed(self.leave(app));
+            }
+            return ChildUpdate::Updated(RenderStatus::VisibleChange.into());
+        }
+        if !self.closing && !app.close_request() {
+            let dt = self.clock.advance(dt);
+            return ChildUpdate::Updated(app.update(dt, self.clock.t_us(), controls));
+        }
+
+        let requested = !self.closing && self.transition.as_mut().is_some_and(|t| t.request());
+        if requested {
+            self.closing = true;
+            return ChildUpdate::Updated(RenderStatus::VisibleChange.into());
+        }
+        // Also if the transition couldn't capture the views
+        self.closing = false;
+        ChildUpdate::Closed(self.leave(app))
+    }
+
+    /// Advances the transition. Returns `true` while it runs, the container isn't updated then.
+    pub fn update_transition(&mut self, dt: i64) -> bool {
+        self.transition
+            .as_mut()
+            .is_some_and(|transition| transition.update(dt))
+    }
+
+    /// Draws the transition between the view of the container, drawn with `container`, and
+    /// `app`, the open entry. Returns `false`, if there is none running.
+    pub fn render_transition<A>(
+        &self,
+        target: &mut D,
+        app: Option<&A>,
+        container: impl FnOnce(&mut D),
+    ) -> bool
+    where
+        A: App<Target = D, Color = C> + ?Sized,
+    {
+        let Some(transition) = self.transition.as_ref() else {
+            return false;
+        };
+        let entry = |target: &mut D| {
+            if let Some(app) = app {
+                app.render(target);
+            }
+        };
+        match self.closing {
+            true => transition.render(target, entry, container),
+            false => transition.render(target, container, entry),
+        }
+    }
+
+    fn stop_transition(&mut self) {
+        self.closing = false;
+        if let Some(transition) = self.transition.as_mut() {
+            transition.stop();
+        }
+    }
+
+    /// Starts `app`, the open entry, over.
+    pub fn restart<A>(&mut self, app: &mut A) -> UpdateResult
+    where
+        A: App<Target = D, Color = C> + ?Sized,
+    {
+        self.stop_transition();
+        self.clock.reset();
+        app.restart().merge(RenderStatus::VisibleChange.into())
+    }
+
+    /// Closes the innermost app of `app`, the open entry. That is the entry itself, if it has
+    /// nothing open on its own.
+    pub fn close_inner<A>(&mut self, app: &mut A) -> UpdateResult
+    where
+        A: App<Target = D, Color = C> + ?Sized,
+    {
+        match app.close_inner() {
+            Some(closed) => closed,
+            None => self.close(app).merge(RenderStatus::VisibleChange.into()),
+        }
+    }
+}
+
+/// Runs the lifecycle `hook` on the open entry, if there is one.
+pub(crate) fn forward<A: ?Sized>(
+    app: Option<&mut A>,
+    hook: impl FnOnce(&mut A) -> UpdateResult,
+) -> UpdateResult {
+    match app {
+        Some(app) => hook(app),
+        None => RenderStatus::NoVisibleChange.into(),
+    }
+}
@@ -1,7 +1,6 @@
 /// Just dispalys an image
 use core::{marker::PhantomData, mem::MaybeUninit};
 
-use embedded_error_chain::ChainError;
 use embedded_graphics::{
     draw_target::ColorConverted,
     pixelcolor::{Rgb565, Rgb888},
@@ -10,7 +9,10 @@ use embedded_graphics::{
 };
 use tinyqoi::Qoi;
 
-use crate::{trait_app::{Color, RenderStatus, UpdateResult}, App, AudioID, Controls, KeyReleaseEvent};
+use crate::{error::decode_frames, registry::{AppInfo, AppMeta, Category}, trait_app::{Color, RenderStatus, UpdateResult}, App, AppError, AudioID, Command, Controls, KeyReleaseEvent, Pacing};
+
+/// Time each image is shown, 50 ms
+const FRAME_US: i64 = 50_000;
 
 #[derive(PartialEq, Debug)]
 pub struct Animation<D, C, const N: usize>
@@ -20,11 +22,9 @@ where
 {
     images: [Qoi<'static>; N],
     current_frame_index: usize,
-    current_frame_time: i64,
     background_music: AudioID,
 
     close_request: KeyReleaseEvent,
-    music_stop_send: bool,
     _marker: PhantomData<D>,
 }
 
@@ -33,22 +33,35 @@ where
     D: DrawTarget<Color = C>,
     C: PixelColor + RgbColor,
 {
+    /// Panics, if the frames aren't valid. See [`Animation::try_new`].
     pub fn new(qoi_data: [&'static [u8]; N], background_music: AudioID) -> Self {
-        let images = qoi_data.map(|data| Qoi::new(data).expect("Invalid QOI data"));
+        Self::try_new(qoi_data, background_music).expect("Invalid QOI data")
+    }
 
-        Self {
-            images,
+    /// All frames have to be of the same size.
+    pub fn try_new(
+        qoi_data: [&'static [u8]; N],
+        background_music: AudioID,
+    ) -> Result<Self, AppError> {
+        Ok(Self {
+            images: decode_frames(qoi_data)?,
             current_frame_index: 0,
-            current_frame_time: 0,
             background_music,
 
             close_request: KeyReleaseEvent::new(),
-            music_stop_send: false,
             _marker: Default::default(),
-        }
+        })
     }
 }
 
+impl<D, C, const N: usize> AppMeta for Animation<D, C, N>
+where
+    D: DrawTarget<Color = C>,
+    C: PixelColor + RgbColor,
+{
+    const INFO: AppInfo = AppInfo::new("animation", "Animation", Category::Animations).with_audio();
+}
+
 impl<D, C, const N: usize> App for Animation<D, C, N>
 where
     D: DrawTarget<Color = C>,
@@ -59,37 +72,22 @@ where
 
     fn reset_state(&mut self) {
         self.current_frame_index = 0;
-        self.current_frame_time = 0;
         self.close_request.reset();
-        self.music_stop_send = false;
     }
 
-    fn update(&mut self, dt: i64, t: i64, controls: &Controls) -> UpdateResult {
+    fn update(&mut self, _dt: i64, _t: i64, controls: &Controls) -> UpdateResult {
         self.close_request.update(controls.buttons_b);
 
-        let mut frame_changed = false;
-        if t - self.current_frame_time > 50_000 {
-            self.current_frame_time = t;
-            self.current_frame_index += 1;
-            self.current_frame_index %= N;
-            frame_changed = true;
-        }
-
-        let render_result = if frame_changed { RenderStatus::VisibleChange} else {RenderStatus::NoVisibleChange};
+        // Every update shows the next image
+        self.current_frame_index += 1;
+        self.current_frame_index %= N;
 
-        // If we should stop, then detect it here and send audio stop, so 
-        // the song doesn't play in the top-level widget until finished.
+        // Keep requesting the music, so it loops. Stopping is handled by `on_pause` / `on_exit`.
+        let result = UpdateResult::from(RenderStatus::VisibleChange);
         if self.close_request.fired() {
-            self.music_stop_send = true;
-            UpdateResult {
-                render_result,
-                audio_queue_request: Some(AudioID::Stop),
-            }
+            result
         } else {
-            UpdateResult {
-                render_result,
-                audio_queue_request: Some(self.background_music.clone()),
-            }
+            result.with_command(Command::play_music(self.background_music))
         }
     }
 
@@ -97,13 +95,35 @@ where
         let mut target_rgb888: ColorConverted<'_, _, Rgb888> = target.color_converted();
 
         let current_frame = &self.images[self.current_frame_index];
-        let _img = embedded_graphics::image::Image::new(current_frame, Point::zero())
+        let center = target_rgb888.bounding_box().center();
+        let _img = embedded_graphics::image::Image::with_center(current_frame, center)
             .draw(&mut target_rgb888);
     }
 
     fn teardown(&mut self) {}
 
     fn close_request(&self) -> bool {
-        self.music_stop_send
+        self.close_request.fired()
+    }
+
+    fn pacing(&self) -> Pacing {
+        Pacing::every(FRAME_US)
+    }
+
+    fn on_enter(&mut self) -> UpdateResult {
+        self.on_resume()
+    }
+
+    fn on_pause(&mut self) -> UpdateResult {
+        UpdateResult::from(RenderStatus::NoVisibleChange).with_command(Command::stop_music())
+    }
+
+    fn on_resume(&mut self) -> UpdateResult {
+        UpdateResult::from(RenderStatus::VisibleChange)
+            .with_command(Command::play_music(self.background_music))
+    }
+
+    fn on_exit(&mut self) -> UpdateResult {
+        self.on_pause()
     }
 }
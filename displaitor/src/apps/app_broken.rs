@@ -0,0 +1,82 @@
+use core::marker::PhantomData;
+
+use embedded_graphics::{
+    mono_font::{ascii::FONT_6X10, MonoTextStyle},
+    prelude::*,
+    text::{Baseline, Text},
+};
+
+use crate::{
+    trait_app::{Color, RenderStatus, UpdateResult},
+    App, AppError, Controls, KeyReleaseEvent, MenuTheme,
+};
+
+const LINE_HEIGHT: i32 = 10;
+
+/// Stands in for an app, which couldn't be built, e.g. because an asset is broken. Shows the name
+/// of the app and why, B closes it. Menus keep working instead of the firmware panicking at boot.
+pub struct BrokenApp<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    name: &'static str,
+    error: AppError,
+    close_request: KeyReleaseEvent,
+    _marker: PhantomData<D>,
+}
+
+impl<D, C> BrokenApp<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    pub fn new(name: &'static str, error: AppError) -> Self {
+        Self {
+            name,
+            error,
+            close_request: KeyReleaseEvent::new(),
+            _marker: Default::default(),
+        }
+    }
+
+    pub fn error(&self) -> AppError {
+        self.error
+    }
+}
+
+impl<D, C> App for BrokenApp<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    type Target = D;
+    type Color = C;
+
+    fn reset_state(&mut self) {
+        self.close_request.reset();
+    }
+
+    fn update(&mut self, _dt: i64, _t: i64, controls: &Controls) -> UpdateResult {
+        self.close_request.update(controls.buttons_b);
+        RenderStatus::NoVisibleChange.into()
+    }
+
+    fn render(&self, target: &mut Self::Target) {
+        let colors = MenuTheme::active().colors();
+        let lines = [
+            (self.name, colors.header),
+            ("is broken:", colors.text),
+            (self.error.message(), colors.disabled),
+        ];
+        for (i, (line, color)) in lines.into_iter().enumerate() {
+            let style = MonoTextStyle::new(&FONT_6X10, C::from(color));
+            let position = Point::new(1, i as i32 * LINE_HEIGHT);
+            let _ = Text::with_baseline(line, position, style, Baseline::Top).draw(target);
+        }
+    }
+
+    fn close_request(&self) -> bool {
+        self.close_request.fired()
+    }
+}
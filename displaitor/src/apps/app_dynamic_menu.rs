@@ -0,0 +1,665 @@
+use alloc::{boxed::Box, vec::Vec};
+
+use embedded_graphics::{prelude::*, primitives::Rectangle};
+
+use super::{
+    active_child::{forward, ActiveChild, ChildUpdate},
+    menu_list::{MenuList, RowKind},
+};
+use crate::{
+    trait_app::{AppBoxed, Color, RenderStatus, UpdateResult},
+    App, Capture, Command, Controls, DirtyRegions, DisplayInfo, KeyReleaseEvent, Pacing,
+    Transition, TransitionKind,
+};
+
+/// Size of the panel, if not set otherwise.
+const DEFAULT_VIEWPORT: Size = DisplayInfo::PANEL_64X32.size;
+
+/// What happens, when an entry of a [`DynamicMenu`] is selected.
+pub enum EntryKind<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    /// Opens the app inside the menu, like [`super::Menu`] does.
+    App(AppBoxed<D, C>),
+    /// Runs the closure and returns its result. The menu stays open.
+    Action(Box<dyn FnMut() -> UpdateResult>),
+    /// Emits the command. The menu stays open.
+    Command(Command),
+    /// Title of the following entries. Can't be selected.
+    Header,
+    /// Horizontal line. Can't be selected.
+    Separator,
+}
+
+pub struct DynamicEntry<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    pub name: &'static str,
+    pub kind: EntryKind<D, C>,
+    /// Disabled entries are shown greyed out and can't be selected.
+    pub enabled: bool,
+    /// Hidden entries are neither shown nor selectable.
+    pub visible: bool,
+}
+
+impl<D, C> DynamicEntry<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    fn new(name: &'static str, kind: EntryKind<D, C>) -> Self {
+        Self {
+            name,
+            kind,
+            enabled: true,
+            visible: true,
+        }
+    }
+
+    pub fn app(name: &'static str, app: AppBoxed<D, C>) -> Self {
+        Self::new(name, EntryKind::App(app))
+    }
+
+    pub fn action(name: &'static str, action: impl FnMut() -> UpdateResult + 'static) -> Self {
+        Self::new(name, EntryKind::Action(Box::new(action)))
+    }
+
+    pub fn command(name: &'static str, command: Command) -> Self {
+        Self::new(name, EntryKind::Command(command))
+    }
+
+    pub fn header(name: &'static str) -> Self {
+        Self::new(name, EntryKind::Header)
+    }
+
+    pub fn separator() -> Self {
+        Self::new("", EntryKind::Separator)
+    }
+
+    fn row_kind(&self) -> RowKind {
+        match self.kind {
+            EntryKind::Header => RowKind::Header,
+            EntryKind::Separator => RowKind::Separator,
+            _ if !self.enabled => RowKind::Disabled,
+            _ => RowKind::Entry,
+        }
+    }
+
+    fn selectable(&self) -> bool {
+        self.visible && self.row_kind() == RowKind::Entry
+    }
+
+    fn app_mut(&mut self) -> Option<&mut AppBoxed<D, C>> {
+        match &mut self.kind {
+            EntryKind::App(app) => Some(app),
+            _ => None,
+        }
+    }
+}
+
+/// A menu whose entries can be changed at runtime, e.g. for settings pages or generated lists.
+///
+/// Indices passed to the methods refer to all entries, including the hidden ones.
+pub struct DynamicMenu<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    entries: Vec<DynamicEntry<D, C>>,
+    list: MenuList,
+    /// Index into `entries` of the app currently shown
+    child: ActiveChild<D, C>,
+
+    nav_up_request: KeyReleaseEvent,
+    nav_down_request: KeyReleaseEvent,
+    selection_request: KeyReleaseEvent,
+    close_request: KeyReleaseEvent,
+
+    /// The whole list has to be drawn on the next update.
+    redraw: bool,
+    /// Result of an app closed outside of `update`
+    pending: Option<UpdateResult>,
+}
+
+impl<D, C> DynamicMenu<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            list: MenuList::new(DEFAULT_VIEWPORT),
+            child: ActiveChild::new(),
+
+            nav_up_request: KeyReleaseEvent::new(),
+            nav_down_request: KeyReleaseEvent::new(),
+            selection_request: KeyReleaseEvent::new(),
+            close_request: KeyReleaseEvent::new(),
+
+            redraw: true,
+            pending: None,
+        }
+    }
+
+    /// Size of the area the entries are drawn to. Defaults to a 64x32 panel.
+    pub fn with_viewport(mut self, viewport: Size) -> Self {
+        self.list.set_viewport(viewport);
+        self
+    }
+
+    /// Whether moving past the last entry selects the first one and vice versa. Enabled by default.
+    pub fn with_wrap_around(mut self, wrap_around: bool) -> Self {
+        self.list.set_wrap_around(wrap_around);
+        self
+    }
+
+    /// Blends between the list and an app, when it is opened or closes itself. The app isn't
+    /// updated, while the transition runs.
+    pub fn with_transition(mut self, kind: TransitionKind, duration_us: i64) -> Self
+    where
+        D: Capture,
+    {
+        self.child.set_transition(Transition::new(kind, duration_us));
+        self
+    }
+
+    pub fn with_entry(mut self, entry: DynamicEntry<D, C>) -> Self {
+        self.push(entry);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&DynamicEntry<D, C>> {
+        self.entries.get(index)
+    }
+
+    /// Index of the first entry called `name`.
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.name == name)
+    }
+
+    pub fn push(&mut self, entry: DynamicEntry<D, C>) {
+        self.insert(self.entries.len(), entry);
+    }
+
+    /// Inserts the entry at `index`, shifting all after it. Panics if `index > len`.
+    pub fn insert(&mut self, index: usize, entry: DynamicEntry<D, C>) {
+        let selected = self.selected_entry();
+        self.entries.insert(index, entry);
+        self.child
+            .shift(|active| if active >= index { active + 1 } else { active });
+        self.restore_selection(selected.map(|s| if s >= index { s + 1 } else { s }));
+    }
+
+    /// Removes the entry at `index`. If its app is shown, it is closed first.
+    pub fn remove(&mut self, index: usize) -> Option<DynamicEntry<D, C>> {
+        if index >= self.entries.len() {
+            return None;
+        }
+        match self.child.index() {
+            Some(active_index) if active_index == index => {
+                // Hand out the result of `on_exit` with the next update
+                let exited = self.close_active();
+                self.pending = merge(self.pending.take(), exited);
+            }
+            Some(active_index) if active_index > index => self.child.shift(|active| active - 1),
+            _ => {}
+        }
+
+        let selected = self.selected_entry();
+        let entry = self.entries.remove(index);
+        self.restore_selection(selected.map(|s| if s > index { s - 1 } else { s }));
+        Some(entry)
+    }
+
+    /// Moves the entry at `from` to `to`, shifting the ones in between.
+    pub fn move_entry(&mut self, from: usize, to: usize) {
+        if from >= self.entries.len() || to >= self.entries.len() {
+            return;
+        }
+        let shift = |i: usize| {
+            if i == from {
+                to
+            } else if from < i && i <= to {
+                i - 1
+            } else if to <= i && i < from {
+                i + 1
+            } else {
+                i
+            }
+        };
+        let selected = self.selected_entry();
+        let entry = self.entries.remove(from);
+        self.entries.insert(to, entry);
+        self.child.shift(shift);
+        self.restore_selection(selected.map(shift));
+    }
+
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) {
+        let selected = self.selected_entry();
+        if let Some(entry) = self.entries.get_mut(index) {
+            entry.enabled = enabled;
+            self.restore_selection(selected);
+        }
+    }
+
+    pub fn set_visible(&mut self, index: usize, visible: bool) {
+        let selected = self.selected_entry();
+        if let Some(entry) = self.entries.get_mut(index) {
+            entry.visible = visible;
+            self.restore_selection(selected);
+        }
+    }
+
+    pub fn set_name(&mut self, index: usize, name: &'static str) {
+        if let Some(entry) = self.entries.get_mut(index) {
+            entry.name = name;
+            self.redraw = true;
+        }
+    }
+
+    /// Selects the entry at `index`, if it can be selected.
+    pub fn pre_select_entry(&mut self, index: usize) -> bool {
+        match self.entries.get(index) {
+            Some(entry) if entry.selectable() => {
+                self.restore_selection(Some(index));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Index of the selected entry, if any entry can be selected.
+    pub fn selected_entry(&self) -> Option<usize> {
+        self.visible_entries()
+            .nth(self.list.selected)
+            .filter(|&i| self.entries[i].selectable())
+    }
+
+    fn visible_entries(&self) -> impl Iterator<Item = usize> + '_ {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.visible)
+            .map(|(i, _)| i)
+    }
+
+    fn visible_len(&self) -> usize {
+        self.visible_entries().count()
+    }
+
+    fn row_selectable(&self, row: usize) -> bool {
+        self.visible_entries()
+            .nth(row)
+            .is_some_and(|i| self.entries[i].selectable())
+    }
+
+    /// Selects the row of entry `index` after the entries changed. Falls back to the closest
+    /// selectable entry, if it is gone, hidden or disabled.
+    fn restore_selection(&mut self, index: Option<usize>) {
+        self.redraw = true;
+        let len = self.visible_len();
+        let row = index
+            .map(|index| self.visible_entries().take_while(|&i| i < index).count())
+            .unwrap_or(self.list.selected)
+            .min(len.saturating_sub(1));
+        self.list.select(row, len);
+        if !self.row_selectable(row) {
+            self.step_selection(true);
+            if !self.row_selectable(self.list.selected) {
+                self.step_selection(false);
+            }
+        }
+    }
+
+    /// Moves the selection to the next selectable row. Keeps the selection, if there is none.
+    fn step_selection(&mut self, forward: bool) {
+        let len = self.visible_len();
+        let before = self.list.selected;
+        for _ in 0..len {
+            let previous = self.list.selected;
+            if forward {
+                self.list.select_next(len);
+            } else {
+                self.list.select_previous(len);
+            }
+            if self.row_selectable(self.list.selected) {
+                return;
+            }
+            if previous == self.list.selected {
+                // Reached the end without wrap-around
+                break;
+            }
+        }
+        self.list.select(before, len);
+    }
+
+    fn close_active(&mut self) -> Option<UpdateResult> {
+        let app = self.entries[self.child.index()?].app_mut()?;
+        let exited = self.child.close(&mut **app);
+        self.redraw = true;
+        Some(exited)
+    }
+
+    fn active(&self) -> Option<&AppBoxed<D, C>> {
+        match &self.entries[self.child.index()?].kind {
+            EntryKind::App(app) => Some(app),
+            _ => None,
+        }
+    }
+
+    fn active_app(&mut self) -> Option<&mut AppBoxed<D, C>> {
+        self.entries[self.child.index()?].app_mut()
+    }
+
+    fn render_list(&self, target: &mut D) {
+        let rows = self
+            .entries
+            .iter()
+            .filter(|entry| entry.visible)
+            .map(|entry| (entry.name, entry.row_kind()));
+        self.list.render(target, rows, self.visible_len());
+    }
+
+    /// Runs the selected entry. Returns the result of the action, command or `on_enter`.
+    fn activate_selected(&mut self) -> Option<UpdateResult> {
+        let index = self.selected_entry()?;
+        let name = self.entries[index].name;
+        match &mut self.entries[index].kind {
+            EntryKind::App(app) => Some(self.child.open(index, name, &mut **app)),
+            EntryKind::Action(action) => Some(action()),
+            EntryKind::Command(command) => {
+                Some(UpdateResult::from(RenderStatus::NoVisibleChange).with_command(command.clone()))
+            }
+            EntryKind::Header | EntryKind::Separator => None,
+        }
+    }
+}
+
+fn merge(a: Option<UpdateResult>, b: Option<UpdateResult>) -> Option<UpdateResult> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.merge(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+impl<D, C> Default for DynamicMenu<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D, C> App for DynamicMenu<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    type Target = D;
+    type Color = C;
+
+    fn reset_state(&mut self) {
+        self.child.reset();
+        self.list.reset();
+        self.restore_selection(None);
+        self.nav_up_request.reset();
+        self.nav_down_request.reset();
+        self.selection_request.reset();
+        self.close_request.reset();
+    }
+
+    fn update(&mut self, dt: i64, _t: i64, controls: &Controls) -> UpdateResult {
+        // The transition after an app closed itself
+        if self.child.index().is_none() && self.child.update_transition(dt) {
+            return RenderStatus::VisibleChange.into();
+        }
+        let mut exited = self.pending.take();
+        if let Some(index) = self.child.index() {
+            if let Some(app) = self.entries[index].app_mut() {
+                match self.child.update(&mut **app, dt, controls) {
+                    ChildUpdate::Updated(update) => return update,
+                    ChildUpdate::Closed(closed) => {
+                        self.redraw = true;
+                        exited = merge(exited, Some(closed));
+                    }
+                }
+            }
+        }
+
+        self.nav_up_request.update(controls.dpad_up);
+        self.nav_down_request.update(controls.dpad_down);
+        self.selection_request.update(controls.buttons_a);
+        self.close_request.update(controls.buttons_b);
+
+        let selected_before = self.list.selected;
+        let scroll_before = self.list.scroll_offset();
+        let mut activated = None;
+        if self.nav_down_request.fired() {
+            self.step_selection(true);
+        } else if self.nav_up_request.fired() {
+            self.step_selection(false);
+        } else if self.selection_request.fired() {
+            activated = self.activate_selected();
+        }
+        let entered = self.child.index().is_some();
+
+        let len = self.visible_len();
+        let marquee_moved = match self.selected_entry() {
+            Some(index) => {
+                let name = self.entries[index].name;
+                self.list.update(dt, name, len)
+            }
+            None => false,
+        };
+
+        let render_result = if self.redraw
+            || exited.is_some()
+            || entered
+            || scroll_before != self.list.scroll_offset()
+        {
+            RenderStatus::VisibleChange
+        } else if selected_before != self.list.selected || marquee_moved {
+            let mut dirty = DirtyRegions::from(self.list.row_area(selected_before));
+            dirty.add(self.list.row_area(self.list.selected));
+            RenderStatus::Dirty(dirty)
+        } else {
+            RenderStatus::NoVisibleChange
+        };
+        self.redraw = false;
+
+        let mut result: UpdateResult = render_result.into();
+        for hook_result in [exited, activated].into_iter().flatten() {
+            result = result.merge(hook_result);
+        }
+        result
+    }
+
+    fn render_region(&self, target: &mut D, area: &Rectangle) {
+        match self.active() {
+            Some(app) if !self.child.transition_running() => app.render_region(target, area),
+            _ => self.render(target),
+        }
+    }
+
+    fn render(&self, target: &mut D) {
+        let app = self.active().map(|app| &**app);
+        if self.child.render_transition(target, app, |target| self.render_list(target)) {
+            return;
+        }
+        match self.active() {
+            Some(app) => app.render(target),
+            None => self.render_list(target),
+        }
+    }
+
+    fn teardown(&mut self) {
+        if let Some(app) = self.active_app() {
+            app.teardown();
+        }
+    }
+
+    fn close_request(&self) -> bool {
+        self.close_request.fired()
+    }
+
+    fn pacing(&self) -> Pacing {
+        match self.active() {
+            Some(app) if !self.child.transition_running() => app.pacing(),
+            _ => Pacing::DEFAULT,
+        }
+    }
+
+    fn active_app_name(&self) -> Option<&str> {
+        let entry = &self.entries[self.child.index()?];
+        match &entry.kind {
+            EntryKind::App(app) => Some(app.active_app_name().unwrap_or(entry.name)),
+            _ => None,
+        }
+    }
+
+    fn on_enter(&mut self) -> UpdateResult {
+        self.redraw = true;
+        forward(self.active_app(), |app| app.on_enter())
+    }
+
+    fn on_pause(&mut self) -> UpdateResult {
+        forward(self.active_app(), |app| app.on_pause())
+    }
+
+    fn on_resume(&mut self) -> UpdateResult {
+        self.redraw = true;
+        forward(self.active_app(), |app| app.on_resume())
+    }
+
+    fn restart(&mut self) -> UpdateResult {
+        let Some(index) = self.child.index() else {
+            return RenderStatus::NoVisibleChange.into();
+        };
+        match self.entries[index].app_mut() {
+            Some(app) => self.child.restart(&mut **app),
+            // The menu itself has nothing to start over
+            None => RenderStatus::NoVisibleChange.into(),
+        }
+    }
+
+    fn close_inner(&mut self) -> Option<UpdateResult> {
+        let app = self.entries[self.child.index()?].app_mut()?;
+        let closed = self.child.close_inner(&mut **app);
+        self.redraw = true;
+        Some(closed)
+    }
+
+    fn on_exit(&mut self) -> UpdateResult {
+        forward(self.active_app(), |app| app.on_exit())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{boxed::Box, rc::Rc};
+    use core::cell::Cell;
+
+    use embedded_graphics::{mock_display::MockDisplay, pixelcolor::Rgb565};
+
+    use super::*;
+
+    type TestMenu = DynamicMenu<MockDisplay<Rgb565>, Rgb565>;
+
+    /// Keeps the time of its last update
+    struct Timed(Rc<Cell<i64>>);
+
+    impl App for Timed {
+        type Target = MockDisplay<Rgb565>;
+        type Color = Rgb565;
+
+        fn reset_state(&mut self) {}
+
+        fn update(&mut self, _dt: i64, t: i64, _controls: &Controls) -> UpdateResult {
+            self.0.set(t);
+            RenderStatus::NoVisibleChange.into()
+        }
+
+        fn render(&self, _target: &mut Self::Target) {}
+    }
+
+    fn menu() -> TestMenu {
+        DynamicMenu::new()
+            .with_entry(DynamicEntry::header("Audio"))
+            .with_entry(DynamicEntry::command("Mute", Command::stop_music()))
+            .with_entry(DynamicEntry::separator())
+            .with_entry(DynamicEntry::command("Dim", Command::Brightness(16)))
+            .with_entry(DynamicEntry::command("Bright", Command::Brightness(255)))
+    }
+
+    #[test]
+    fn selection_skips_headers_and_disabled() {
+        let mut menu = menu();
+        assert_eq!(menu.selected_entry(), Some(1));
+
+        menu.step_selection(true);
+        assert_eq!(menu.selected_entry(), Some(3));
+
+        menu.set_enabled(4, false);
+        menu.step_selection(true);
+        assert_eq!(menu.selected_entry(), Some(1));
+
+        // Disabling the selection moves it to the next selectable entry
+        menu.set_enabled(1, false);
+        assert_eq!(menu.selected_entry(), Some(3));
+    }
+
+    #[test]
+    fn selection_follows_entry_on_changes() {
+        let mut menu = menu();
+        menu.pre_select_entry(3);
+
+        menu.insert(0, DynamicEntry::command("Recent", Command::Notify("recent")));
+        assert_eq!(menu.selected_entry(), Some(4));
+
+        menu.set_visible(0, false);
+        menu.set_visible(1, false);
+        assert_eq!(menu.selected_entry(), Some(4));
+
+        menu.move_entry(4, 0);
+        assert_eq!(menu.selected_entry(), Some(0));
+
+        let removed = menu.remove(0).unwrap();
+        assert_eq!(removed.name, "Dim");
+        assert_eq!(menu.position("Bright"), Some(4));
+        assert_eq!(menu.selected_entry(), Some(2));
+    }
+
+    #[test]
+    fn opened_apps_start_at_zero() {
+        let t = Rc::new(Cell::new(-1));
+        let timed = DynamicEntry::app("Timed", Box::new(Timed(t.clone())));
+        let mut menu = TestMenu::new().with_entry(timed);
+        let controls = Controls::new(false, false, false, false, false, false, false);
+        let _ = menu.update(20_000, 5_000_000, &controls);
+
+        let _ = menu.activate_selected();
+        let _ = menu.update(20_000, 5_020_000, &controls);
+        assert_eq!(t.get(), 20_000);
+
+        // Opened again, it starts over
+        let _ = menu.close_active();
+        let _ = menu.activate_selected();
+        let _ = menu.update(20_000, 5_040_000, &controls);
+        assert_eq!(t.get(), 20_000);
+    }
+}
@@ -0,0 +1,110 @@
+use core::marker::PhantomData;
+
+use alloc::vec::Vec;
+
+use embedded_graphics::prelude::*;
+
+use super::high_score_view::draw_table;
+use crate::{
+    high_scores::HighScores,
+    registry::{AppInfo, AppMeta, Category},
+    trait_app::{Color, RenderStatus, UpdateResult},
+    App, Controls, KeyReleaseEvent,
+};
+
+/// Shows the high score tables of the games. Left/right switches between the games.
+pub struct HighScoresApp<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    high_scores: HighScores,
+    /// Games with a high score table
+    games: Vec<AppInfo>,
+    selected: usize,
+
+    previous_request: KeyReleaseEvent,
+    next_request: KeyReleaseEvent,
+    close_request: KeyReleaseEvent,
+
+    /// The table has to be drawn on the next update.
+    redraw: bool,
+    _marker: PhantomData<D>,
+}
+
+impl<D, C> HighScoresApp<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    pub fn new(high_scores: HighScores, games: Vec<AppInfo>) -> Self {
+        Self {
+            high_scores,
+            games,
+            selected: 0,
+
+            previous_request: KeyReleaseEvent::new(),
+            next_request: KeyReleaseEvent::new(),
+            close_request: KeyReleaseEvent::new(),
+
+            redraw: true,
+            _marker: Default::default(),
+        }
+    }
+}
+
+impl<D, C> AppMeta for HighScoresApp<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    const INFO: AppInfo = AppInfo::new("high_scores", "Top 4", Category::Games);
+}
+
+impl<D, C> App for HighScoresApp<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    type Target = D;
+    type Color = C;
+
+    fn reset_state(&mut self) {
+        self.selected = 0;
+        self.previous_request.reset();
+        self.next_request.reset();
+        self.close_request.reset();
+        self.redraw = true;
+    }
+
+    fn update(&mut self, _dt: i64, _t: i64, controls: &Controls) -> UpdateResult {
+        self.previous_request.update(controls.dpad_left);
+        self.next_request.update(controls.dpad_right);
+        self.close_request.update(controls.buttons_b);
+
+        let len = self.games.len().max(1);
+        let selected_before = self.selected;
+        if self.next_request.fired() {
+            self.selected = (self.selected + 1) % len;
+        } else if self.previous_request.fired() {
+            self.selected = (self.selected + len - 1) % len;
+        }
+
+        let changed = self.redraw || selected_before != self.selected;
+        self.redraw = false;
+        match changed {
+            true => RenderStatus::VisibleChange.into(),
+            false => RenderStatus::NoVisibleChange.into(),
+        }
+    }
+
+    fn render(&self, target: &mut Self::Target) {
+        if let Some(game) = self.games.get(self.selected) {
+            draw_table(target, game.name, &self.high_scores.table(game.id), None);
+        }
+    }
+
+    fn close_request(&self) -> bool {
+        self.close_request.fired()
+    }
+}
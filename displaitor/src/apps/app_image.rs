@@ -1,7 +1,6 @@
 /// Just displays an image
 use core::marker::PhantomData;
 
-use embedded_error_chain::ChainError;
 use embedded_graphics::{
     draw_target::ColorConverted,
     pixelcolor::{Rgb565, Rgb888},
@@ -11,7 +10,7 @@ use embedded_graphics::{
 use heapless::Vec;
 use tinyqoi::Qoi;
 
-use crate::{trait_app::{Color, RenderStatus, UpdateResult}, App, Controls, KeyReleaseEvent};
+use crate::{error::decode_qoi, trait_app::{Color, RenderStatus, UpdateResult}, App, AppError, Controls, KeyReleaseEvent};
 
 #[derive(PartialEq, Debug)]
 pub struct Image<D, C>
@@ -30,12 +29,17 @@ where
     C: Color,
 {
     // TODO: Should take a const filepath of a .qoi
+    /// Panics, if `qoi_data` isn't a valid image. See [`Image::try_new`].
     pub fn new(qoi_data: &'static [u8]) -> Self {
-        Self {
-            image: Qoi::new(qoi_data).unwrap(),
+        Self::try_new(qoi_data).expect("Invalid QOI data")
+    }
+
+    pub fn try_new(qoi_data: &'static [u8]) -> Result<Self, AppError> {
+        Ok(Self {
+            image: decode_qoi(qoi_data)?,
             close_request: KeyReleaseEvent::new(),
             _marker: Default::default(),
-        }
+        })
     }
 }
 
@@ -59,7 +63,8 @@ where
     fn render(&self, target: &mut Self::Target) {
         let mut target_rgb888: ColorConverted<'_, _, Rgb888> = target.color_converted();
 
-        let _img = embedded_graphics::image::Image::new(&self.image, Point::zero())
+        let center = target_rgb888.bounding_box().center();
+        let _img = embedded_graphics::image::Image::with_center(&self.image, center)
             .draw(&mut target_rgb888);
     }
 
@@ -0,0 +1,428 @@
+use alloc::boxed::Box;
+
+use embedded_graphics::{
+    draw_target::ColorConverted,
+    mono_font::{ascii::FONT_6X10, MonoTextStyle},
+    pixelcolor::Rgb888,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+
+use super::{
+    active_child::{forward, ActiveChild, ChildUpdate},
+    MenuEntry,
+};
+use crate::{
+    settings::MenuTheme,
+    trait_app::{AppBoxed, Color, RenderStatus, UpdateResult},
+    App, Capture, Controls, DirtyRegions, DisplayInfo, KeyReleaseEvent, Pacing, Transition,
+    TransitionKind,
+};
+
+/// Size of the panel, if not set otherwise.
+const DEFAULT_VIEWPORT: Size = DisplayInfo::PANEL_64X32.size;
+
+const ICON_SIZE: i32 = 16;
+/// Distance between the left edges of two neighbouring icons
+const ICON_SPACING: i32 = ICON_SIZE + 8;
+const ICON_Y: i32 = 2;
+const CAPTION_Y: i32 = ICON_Y + ICON_SIZE + 2;
+
+/// Time to slide from one icon to the next
+const SLIDE_US: i64 = 150_000;
+/// Time the highlight stays in one colour
+const HIGHLIGHT_BLINK_US: i64 = 400_000;
+
+/// Colours of the tiles drawn for entries without an icon.
+const TILE_COLORS: [Rgb888; 6] = [
+    Rgb888::CSS_TEAL,
+    Rgb888::CSS_DARK_ORANGE,
+    Rgb888::CSS_ROYAL_BLUE,
+    Rgb888::CSS_CRIMSON,
+    Rgb888::CSS_SEA_GREEN,
+    Rgb888::CSS_GOLDENROD,
+];
+
+/// Shows the entries as a horizontal carousel of icons with the name of the selected one below.
+///
+/// Takes the same entries as [`super::Menu`] and can replace it. Left/right moves the selection,
+/// A opens the entry and B closes the launcher.
+pub struct Launcher<const MAX_ENTRIES: usize, D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    entries: [MenuEntry<D, C>; MAX_ENTRIES],
+    selected: usize,
+    child: ActiveChild<D, C>,
+    viewport: Size,
+    wrap_around: bool,
+
+    /// Horizontal position of the carousel in pixels. Slides towards the selected icon.
+    scroll_x: i32,
+    highlight_t_us: i64,
+
+    nav_left_request: KeyReleaseEvent,
+    nav_right_request: KeyReleaseEvent,
+    selection_request: KeyReleaseEvent,
+    close_request: KeyReleaseEvent,
+
+    /// The whole launcher has to be drawn on the next update.
+    redraw: bool,
+}
+
+impl<const MAX_ENTRIES: usize, D, C> Launcher<MAX_ENTRIES, D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    pub fn new(entries: [MenuEntry<D, C>; MAX_ENTRIES]) -> Self {
+        Self {
+            entries,
+            selected: 0,
+            child: ActiveChild::new(),
+            viewport: DEFAULT_VIEWPORT,
+            wrap_around: true,
+
+            scroll_x: 0,
+            highlight_t_us: 0,
+
+            nav_left_request: KeyReleaseEvent::new(),
+            nav_right_request: KeyReleaseEvent::new(),
+            selection_request: KeyReleaseEvent::new(),
+            close_request: KeyReleaseEvent::new(),
+
+            redraw: true,
+        }
+    }
+
+    /// Size of the area the launcher is drawn to. Defaults to a 64x32 panel.
+    pub fn with_viewport(mut self, viewport: Size) -> Self {
+        self.viewport = viewport;
+        self
+    }
+
+    /// Whether moving past the last entry selects the first one and vice versa. Enabled by default.
+    pub fn with_wrap_around(mut self, wrap_around: bool) -> Self {
+        self.wrap_around = wrap_around;
+        self
+    }
+
+    /// Blends between the carousel and an entry, when it is opened or closes itself. The entry
+    /// isn't updated, while the transition runs.
+    pub fn with_transition(mut self, kind: TransitionKind, duration_us: i64) -> Self
+    where
+        D: Capture,
+    {
+        self.child.set_transition(Transition::new(kind, duration_us));
+        self
+    }
+
+    pub fn pre_select_entry(&mut self, index: usize) -> bool {
+        if index < MAX_ENTRIES {
+            self.selected = index;
+            self.scroll_x = Self::target_scroll(index);
+            self.child.preselect(index, self.entries[index].name);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn active(&self) -> Option<&AppBoxed<D, C>> {
+        Some(&self.entries[self.child.index()?].app)
+    }
+
+    fn active_mut(&mut self) -> Option<&mut AppBoxed<D, C>> {
+        Some(&mut self.entries[self.child.index()?].app)
+    }
+
+    fn render_carousel(&self, target: &mut D) {
+        let center = self.center_icon_area();
+        for (i, entry) in self.entries.iter().enumerate() {
+            let x = center.top_left.x + Self::target_scroll(i) - self.scroll_x;
+            if x + ICON_SIZE <= 0 || x >= self.viewport.width as i32 {
+                continue;
+            }
+            let area = Rectangle::new(Point::new(x, ICON_Y), center.size);
+            Self::draw_icon(entry, area, target);
+        }
+
+        let _highlight = center
+            .offset(1)
+            .into_styled(PrimitiveStyle::with_stroke(self.highlight_color(), 1))
+            .draw(target);
+
+        let caption_style = TextStyleBuilder::new()
+            .alignment(Alignment::Center)
+            .baseline(Baseline::Top)
+            .build();
+        let _caption = Text::with_text_style(
+            self.entries[self.selected].name,
+            Point::new(self.viewport.width as i32 / 2, CAPTION_Y),
+            MonoTextStyle::new(&FONT_6X10, C::from(MenuTheme::active().colors().text)),
+            caption_style,
+        )
+        .draw(target);
+    }
+
+    fn target_scroll(index: usize) -> i32 {
+        index as i32 * ICON_SPACING
+    }
+
+    fn select(&mut self, index: usize) {
+        self.selected = index;
+        self.highlight_t_us = 0;
+    }
+
+    fn select_next(&mut self) {
+        if self.selected + 1 < MAX_ENTRIES {
+            self.select(self.selected + 1);
+        } else if self.wrap_around {
+            self.select(0);
+        }
+    }
+
+    fn select_previous(&mut self) {
+        if self.selected > 0 {
+            self.select(self.selected - 1);
+        } else if self.wrap_around {
+            self.select(MAX_ENTRIES.saturating_sub(1));
+        }
+    }
+
+    /// Moves the carousel towards the selection. Returns `true`, if it moved.
+    fn slide(&mut self, dt: i64) -> bool {
+        let target = Self::target_scroll(self.selected);
+        let distance = target - self.scroll_x;
+        if distance == 0 {
+            return false;
+        }
+        let step = ((ICON_SPACING as i64 * dt / SLIDE_US) as i32).max(1);
+        self.scroll_x += distance.signum() * step.min(distance.abs());
+        true
+    }
+
+    fn highlight_color(&self) -> C {
+        let colors = MenuTheme::active().colors();
+        if (self.highlight_t_us / HIGHLIGHT_BLINK_US) % 2 == 0 {
+            C::from(colors.selected)
+        } else {
+            C::from(colors.indicator)
+        }
+    }
+
+    /// Area of the icon in the centre, which the highlight surrounds.
+    fn center_icon_area(&self) -> Rectangle {
+        Rectangle::new(
+            Point::new((self.viewport.width as i32 - ICON_SIZE) / 2, ICON_Y),
+            Size::new(ICON_SIZE as u32, ICON_SIZE as u32),
+        )
+    }
+
+    fn draw_icon(entry: &MenuEntry<D, C>, area: Rectangle, target: &mut D) {
+        let mut target_rgb888: ColorConverted<'_, _, Rgb888> = target.color_converted();
+        let mut target_rgb888 = target_rgb888.clipped(&area);
+
+        if let Some(icon) = &entry.icon {
+            let _icon = embedded_graphics::image::Image::new(icon, area.top_left)
+                .draw(&mut target_rgb888);
+            return;
+        }
+
+        // Fallback tile with the initial of the name
+        let color_index = entry.name.bytes().map(usize::from).sum::<usize>() % TILE_COLORS.len();
+        let _tile = area
+            .into_styled(PrimitiveStyle::with_fill(TILE_COLORS[color_index]))
+            .draw(&mut target_rgb888);
+        let initial = entry.name.get(..1).unwrap_or("?");
+        let text_style = TextStyleBuilder::new()
+            .alignment(Alignment::Center)
+            .baseline(Baseline::Middle)
+            .build();
+        let _initial = Text::with_text_style(
+            initial,
+            area.center(),
+            MonoTextStyle::new(&FONT_6X10, Rgb888::BLACK),
+            text_style,
+        )
+        .draw(&mut target_rgb888);
+    }
+}
+
+impl<const MAX_ENTRIES: usize, D, C> App for Launcher<MAX_ENTRIES, D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    type Target = D;
+    type Color = C;
+
+    fn reset_state(&mut self) {
+        self.child.reset();
+        self.selected = 0;
+        self.scroll_x = 0;
+        self.highlight_t_us = 0;
+        self.nav_left_request.reset();
+        self.nav_right_request.reset();
+        self.selection_request.reset();
+        self.close_request.reset();
+        self.redraw = true;
+    }
+
+    fn update(&mut self, dt: i64, _t: i64, controls: &Controls) -> UpdateResult {
+        // The transition after an entry closed itself
+        if self.child.index().is_none() && self.child.update_transition(dt) {
+            return RenderStatus::VisibleChange.into();
+        }
+        let mut exited = None;
+        if let Some(index) = self.child.index() {
+            match self.child.update(&mut *self.entries[index].app, dt, controls) {
+                ChildUpdate::Updated(update) => return update,
+                ChildUpdate::Closed(closed) => exited = Some(closed),
+            }
+        }
+
+        self.nav_left_request.update(controls.dpad_left);
+        self.nav_right_request.update(controls.dpad_right);
+        self.selection_request.update(controls.buttons_a);
+        self.close_request.update(controls.buttons_b);
+
+        let mut entered = None;
+        if self.nav_right_request.fired() {
+            self.select_next();
+        } else if self.nav_left_request.fired() {
+            self.select_previous();
+        } else if self.selection_request.fired() {
+            let entry = &mut self.entries[self.selected];
+            entered = Some(self.child.open(self.selected, entry.name, &mut *entry.app));
+        }
+
+        let highlight_before = self.highlight_color();
+        self.highlight_t_us += dt;
+        let slid = self.slide(dt);
+
+        let render_result = if self.redraw || exited.is_some() || entered.is_some() || slid {
+            RenderStatus::VisibleChange
+        } else if highlight_before != self.highlight_color() {
+            RenderStatus::Dirty(DirtyRegions::from(self.center_icon_area().offset(1)))
+        } else {
+            RenderStatus::NoVisibleChange
+        };
+        self.redraw = false;
+
+        let mut result: UpdateResult = render_result.into();
+        for hook_result in [exited, entered].into_iter().flatten() {
+            result = result.merge(hook_result);
+        }
+        result
+    }
+
+    fn render_region(&self, target: &mut D, area: &Rectangle) {
+        match self.active() {
+            Some(app) if !self.child.transition_running() => app.render_region(target, area),
+            _ => self.render(target),
+        }
+    }
+
+    fn render(&self, target: &mut D) {
+        let app = self.active().map(|app| &**app);
+        if self.child.render_transition(target, app, |target| self.render_carousel(target)) {
+            return;
+        }
+        match self.active() {
+            // Until it is closed, e.g. an `OpenScene` below the scene it opened
+            Some(app) if !app.close_request() => app.render(target),
+            _ => self.render_carousel(target),
+        }
+    }
+
+    fn teardown(&mut self) {
+        if let Some(app) = self.active_mut() {
+            app.teardown();
+        }
+    }
+
+    fn close_request(&self) -> bool {
+        self.close_request.fired()
+    }
+
+    fn pacing(&self) -> Pacing {
+        match self.active() {
+            Some(app) if !self.child.transition_running() => app.pacing(),
+            _ => Pacing::DEFAULT,
+        }
+    }
+
+    fn active_app_name(&self) -> Option<&str> {
+        let entry = &self.entries[self.child.index()?];
+        Some(entry.app.active_app_name().unwrap_or(entry.name))
+    }
+
+    fn on_enter(&mut self) -> UpdateResult {
+        self.redraw = true;
+        forward(self.active_mut(), |app| app.on_enter())
+    }
+
+    fn on_pause(&mut self) -> UpdateResult {
+        forward(self.active_mut(), |app| app.on_pause())
+    }
+
+    fn on_resume(&mut self) -> UpdateResult {
+        self.redraw = true;
+        forward(self.active_mut(), |app| app.on_resume())
+    }
+
+    fn on_exit(&mut self) -> UpdateResult {
+        forward(self.active_mut(), |app| app.on_exit())
+    }
+
+    fn restart(&mut self) -> UpdateResult {
+        match self.child.index() {
+            Some(index) => self.child.restart(&mut *self.entries[index].app),
+            // The launcher itself has nothing to start over
+            None => RenderStatus::NoVisibleChange.into(),
+        }
+    }
+
+    fn close_inner(&mut self) -> Option<UpdateResult> {
+        let index = self.child.index()?;
+        let closed = self.child.close_inner(&mut *self.entries[index].app);
+        self.redraw = true;
+        Some(closed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::{mock_display::MockDisplay, pixelcolor::Rgb565};
+
+    use super::*;
+    use crate::apps::Dummy;
+
+    #[test]
+    fn carousel_slides_to_selection() {
+        let entries = ["A", "B", "C"].map(|name| MenuEntry::new(name, Box::new(Dummy::new())));
+        let mut launcher: Launcher<3, MockDisplay<Rgb565>, Rgb565> = Launcher::new(entries);
+        let right = Controls::new(false, false, false, false, false, false, true);
+        let idle = Controls::new(false, false, false, false, false, false, false);
+
+        launcher.update(0, 0, &idle);
+        launcher.update(0, 0, &right);
+        launcher.update(0, 0, &idle);
+        assert_eq!(launcher.selected, 1);
+
+        let mut frames = 0;
+        while launcher.update(20_000, 0, &idle).visible_changes() {
+            frames += 1;
+        }
+        assert_eq!(launcher.scroll_x, ICON_SPACING);
+        // 3 pixels per 20ms frame
+        assert_eq!(frames, 8);
+
+        let mut display = MockDisplay::new();
+        display.set_allow_overdraw(true);
+        launcher.render(&mut display);
+    }
+}
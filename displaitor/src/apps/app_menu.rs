@@ -1,18 +1,28 @@
-use core::fmt::Write;
 use alloc::boxed::Box;
 
+use tinyqoi::Qoi;
+
 use embedded_graphics::{
     mono_font::{ascii::FONT_6X10, MonoTextStyle},
     prelude::*,
+    primitives::Rectangle,
     text::{Baseline, Text},
 };
 
+use super::{
+    active_child::{forward, ActiveChild, ChildUpdate},
+    menu_list::{MenuList, RowKind},
+};
 use crate::{
-    string_buffer::{self, FixedBuffer},
-    trait_app::{Color, RenderStatus, UpdateResult},
-    App, Controls, KeyReleaseEvent,
+    error::decode_qoi,
+    trait_app::{AppBoxed, Color, RenderStatus, UpdateResult},
+    App, AppError, Capture, Controls, DirtyRegions, DisplayInfo, KeyReleaseEvent, Pacing,
+    Transition, TransitionKind,
 };
 
+/// Size of the panel, if not set otherwise.
+const DEFAULT_VIEWPORT: Size = DisplayInfo::PANEL_64X32.size;
+
 pub struct MenuEntry<D, C>
 where
     D: DrawTarget<Color = C>,
@@ -20,6 +30,28 @@ where
 {
     pub name: &'static str,
     pub app: Box<dyn App<Target = D, Color = C>>,
+    /// Shown by the [`super::Launcher`]. Entries without one get a tile with their initial.
+    pub icon: Option<Qoi<'static>>,
+}
+
+impl<D, C> MenuEntry<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    pub fn new(name: &'static str, app: Box<dyn App<Target = D, Color = C>>) -> Self {
+        Self {
+            name,
+            app,
+            icon: None,
+        }
+    }
+
+    /// Adds a 16x16 QOI icon. Fails, if it isn't a valid image.
+    pub fn try_with_icon(mut self, qoi_data: &'static [u8]) -> Result<Self, AppError> {
+        self.icon = Some(decode_qoi(qoi_data)?);
+        Ok(self)
+    }
 }
 
 pub struct Menu<const MAX_ENTRIES: usize, D, C>
@@ -28,14 +60,18 @@ where
     C: Color,
 {
     entries: [MenuEntry<D, C>; MAX_ENTRIES],
-    selected_index: usize,
-    active_index: Option<usize>,
+    list: MenuList,
+    child: ActiveChild<D, C>,
 
     nav_up_request: KeyReleaseEvent,
     nav_down_request: KeyReleaseEvent,
     selection_request: KeyReleaseEvent,
     special_request: KeyReleaseEvent,
     close_request: KeyReleaseEvent,
+
+    /// The whole list has to be drawn on the next update.
+    redraw: bool,
+    special_shown: bool,
 }
 
 impl<const MAX_ENTRIES: usize, D, C> Menu<MAX_ENTRIES, D, C>
@@ -46,65 +82,95 @@ where
     pub fn new(entries: [MenuEntry<D, C>; MAX_ENTRIES]) -> Self {
         Self {
             entries,
-            selected_index: 0,
-            active_index: None,
+            list: MenuList::new(DEFAULT_VIEWPORT),
+            child: ActiveChild::new(),
 
             nav_up_request: KeyReleaseEvent::new(),
             nav_down_request: KeyReleaseEvent::new(),
             selection_request: KeyReleaseEvent::new(),
             special_request: KeyReleaseEvent::new(),
             close_request: KeyReleaseEvent::new(),
+
+            redraw: true,
+            special_shown: false,
         }
     }
 
+    /// Size of the area the entries are drawn to. Defaults to a 64x32 panel.
+    pub fn with_viewport(mut self, viewport: Size) -> Self {
+        self.list.set_viewport(viewport);
+        self
+    }
+
+    /// Whether moving past the last entry selects the first one and vice versa. Enabled by default.
+    pub fn with_wrap_around(mut self, wrap_around: bool) -> Self {
+        self.list.set_wrap_around(wrap_around);
+        self
+    }
+
+    /// Blends between the list and an entry, when it is opened or closed. The entry isn't updated,
+    /// while the transition runs.
+    pub fn with_transition(mut self, kind: TransitionKind, duration_us: i64) -> Self
+    where
+        D: Capture,
+    {
+        self.child.set_transition(Transition::new(kind, duration_us));
+        self
+    }
+
     pub fn pre_select_entry(&mut self, index: usize) -> bool {
         if index < MAX_ENTRIES {
-            self.selected_index = index;
-            self.active_index = Some(index);
+            self.list.select(index, MAX_ENTRIES);
+            self.child.preselect(index, self.entries[index].name);
             true
         } else {
             false
         }
     }
 
-    fn select_next(&mut self) {
-        self.selected_index = (self.selected_index + 1) % MAX_ENTRIES;
-    }
-
-    fn select_previous(&mut self) {
-        self.selected_index = if self.selected_index == 0 {
-            MAX_ENTRIES - 1
-        } else {
-            self.selected_index - 1
+    /// Delegates the update call, if something is active. If the active app requested closure, it is
+    /// closed and the result of its `on_exit` is returned as `Err`.
+    fn update_process_active(
+        &mut self,
+        dt: i64,
+        controls: &Controls,
+    ) -> Result<UpdateResult, Option<UpdateResult>> {
+        let Some(active_index) = self.child.index() else {
+            return Err(None);
         };
-    }
-
-    /// Delegates the update call, if something is selected and returns `true`. If the
-    /// call wasn't delegated, it returns `false`.
-    fn update_process_active(&mut self, dt: i64, t: i64, controls: &Controls) -> Option<UpdateResult> {
-        if let Some(active_index) = self.active_index {
-            let active_app = &mut self.entries[active_index];
-            if !active_app.app.close_request() {
-                return Some(active_app.app.update(dt, t, controls));
-            }
-
-            // info!("App {} requested closure", active_app.name);
-            active_app.app.teardown();
-            self.active_index = None;
+        let app = &mut *self.entries[active_index].app;
+        match self.child.update(app, dt, controls) {
+            ChildUpdate::Updated(update) => Ok(update),
+            ChildUpdate::Closed(exited) => Err(Some(exited)),
         }
-
-        None
     }
 
-    fn update_process_menu_movement(&mut self, controls: &Controls) {
+    /// Returns the result of `on_enter`, if an entry was opened.
+    fn update_process_menu_movement(&mut self, controls: &Controls) -> Option<UpdateResult> {
         if self.nav_down_request.fired() {
-            self.select_next();
+            self.list.select_next(MAX_ENTRIES);
         } else if self.nav_up_request.fired() {
-            self.select_previous();
+            self.list.select_previous(MAX_ENTRIES);
         } else if self.selection_request.fired() {
-            self.active_index = Some(self.selected_index);
-            self.entries[self.selected_index].app.reset_state();
+            let selected = self.list.selected;
+            let entry = &mut self.entries[selected];
+            let entered = self.child.open(selected, entry.name, &mut *entry.app);
+            return Some(entered);
         }
+        None
+    }
+
+    fn active(&self) -> Option<&AppBoxed<D, C>> {
+        Some(&self.entries[self.child.index()?].app)
+    }
+
+    fn active_mut(&mut self) -> Option<&mut AppBoxed<D, C>> {
+        Some(&mut self.entries[self.child.index()?].app)
+    }
+
+    fn render_list(&self, target: &mut D) {
+        let rows = self.entries.iter().map(|entry| (entry.name, RowKind::Entry));
+        self.list.render(target, rows, MAX_ENTRIES);
     }
 }
 
@@ -117,20 +183,28 @@ where
     type Color = C;
 
     fn reset_state(&mut self) {
-        self.active_index = None;
-        self.selected_index = 0;
+        self.child.reset();
+        self.list.reset();
         self.nav_up_request.reset();
         self.nav_down_request.reset();
         self.selection_request.reset();
         self.special_request.reset();
         self.close_request.reset();
+        self.redraw = true;
+        self.special_shown = false;
     }
 
-    fn update(&mut self, dt: i64, t: i64, controls: &Controls) -> UpdateResult {
-        if let Some(update) = self.update_process_active(dt, t, controls) {
-            return update;
+    fn update(&mut self, dt: i64, _t: i64, controls: &Controls) -> UpdateResult {
+        // The transition after closing an entry
+        if self.child.index().is_none() && self.child.update_transition(dt) {
+            return RenderStatus::VisibleChange.into();
         }
 
+        let exited = match self.update_process_active(dt, controls) {
+            Ok(update) => return update,
+            Err(exited) => exited,
+        };
+
         // We are in the menu itself and don't delegate the call!
         self.nav_up_request.update(controls.dpad_up);
         self.nav_down_request.update(controls.dpad_down);
@@ -138,39 +212,62 @@ where
         self.special_request.update(controls.buttons_s);
         self.close_request.update(controls.buttons_b);
 
-        self.update_process_menu_movement(controls);
-        RenderStatus::VisibleChange.into()
+        let selected_before = self.list.selected;
+        let scroll_before = self.list.scroll_offset();
+        let entered = self.update_process_menu_movement(controls);
+        let special_shown = self.special_request.fired();
+        let marquee_moved =
+            self.list
+                .update(dt, self.entries[self.list.selected].name, MAX_ENTRIES);
+
+        let render_result = if self.redraw
+            || exited.is_some()
+            || entered.is_some()
+            || special_shown != self.special_shown
+            || scroll_before != self.list.scroll_offset()
+        {
+            RenderStatus::VisibleChange
+        } else if selected_before != self.list.selected || marquee_moved {
+            // Only the old and new selection changes
+            let mut dirty = DirtyRegions::from(self.list.row_area(selected_before));
+            dirty.add(self.list.row_area(self.list.selected));
+            RenderStatus::Dirty(dirty)
+        } else {
+            RenderStatus::NoVisibleChange
+        };
+        self.redraw = false;
+        self.special_shown = special_shown;
+
+        let mut result: UpdateResult = render_result.into();
+        for hook_result in [exited, entered].into_iter().flatten() {
+            result = result.merge(hook_result);
+        }
+        result
+    }
+
+    fn render_region(&self, target: &mut D, area: &Rectangle) {
+        if self.child.transition_running() {
+            return self.render(target);
+        }
+        match self.active() {
+            Some(app) => app.render_region(target, area),
+            None => self.render(target),
+        }
     }
 
     fn render(&self, target: &mut D) {
-        if let Some(active_index) = self.active_index {
-            self.entries[active_index].app.render(target);
+        let app = self.active().map(|app| &**app);
+        if self.child.render_transition(target, app, |target| self.render_list(target)) {
             return;
         }
-
-        let text_style = MonoTextStyle::new(&FONT_6X10, C::WHITE);
-        let text_style_active = MonoTextStyle::new(&FONT_6X10, C::MAGENTA);
-        let mut buffer = FixedBuffer::<32>::new();
-        for (i, entry) in self.entries.iter().enumerate() {
-            buffer.clear();
-
-            let y_offset = i as i32 * 11;
-            let (prefix, style) = if i == self.selected_index {
-                ("> ", text_style_active)
-            } else {
-                ("  ", text_style)
-            };
-
-            let _ = write!(buffer, "{}{}", prefix, entry.name);
-            let _entry_text = Text::with_baseline(
-                buffer.as_str(),
-                Point::new(0, y_offset),
-                style,
-                Baseline::Top,
-            )
-            .draw(target);
+        if let Some(app) = self.active() {
+            app.render(target);
+            return;
         }
 
+        self.render_list(target);
+
+        let text_style_active = MonoTextStyle::new(&FONT_6X10, C::MAGENTA);
         if self.special_request.fired() {
             // Remove this
             let _special_test = Text::with_baseline(
@@ -184,8 +281,8 @@ where
     }
 
     fn teardown(&mut self) {
-        if let Some(active_index) = self.active_index {
-            self.entries[active_index].app.teardown();
+        if let Some(app) = self.active_mut() {
+            app.teardown();
         } else {
             // println!("Menu closed!");
         }
@@ -194,4 +291,118 @@ where
     fn close_request(&self) -> bool {
         self.close_request.fired()
     }
+
+    fn pacing(&self) -> Pacing {
+        match self.active() {
+            Some(app) if !self.child.transition_running() => app.pacing(),
+            _ => Pacing::DEFAULT,
+        }
+    }
+
+    fn active_app_name(&self) -> Option<&str> {
+        let entry = &self.entries[self.child.index()?];
+        Some(entry.app.active_app_name().unwrap_or(entry.name))
+    }
+
+    fn on_enter(&mut self) -> UpdateResult {
+        self.redraw = true;
+        forward(self.active_mut(), |app| app.on_enter())
+    }
+
+    fn on_pause(&mut self) -> UpdateResult {
+        forward(self.active_mut(), |app| app.on_pause())
+    }
+
+    fn on_resume(&mut self) -> UpdateResult {
+        self.redraw = true;
+        forward(self.active_mut(), |app| app.on_resume())
+    }
+
+    fn on_exit(&mut self) -> UpdateResult {
+        forward(self.active_mut(), |app| app.on_exit())
+    }
+
+    fn restart(&mut self) -> UpdateResult {
+        match self.child.index() {
+            Some(index) => self.child.restart(&mut *self.entries[index].app),
+            // The menu itself has nothing to start over
+            None => RenderStatus::NoVisibleChange.into(),
+        }
+    }
+
+    fn close_inner(&mut self) -> Option<UpdateResult> {
+        let index = self.child.index()?;
+        let closed = self.child.close_inner(&mut *self.entries[index].app);
+        self.redraw = true;
+        Some(closed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::{mock_display::MockDisplay, pixelcolor::Rgb565};
+
+    use super::*;
+    use crate::{
+        apps::Dummy,
+        testing::{Button, Frame, Harness},
+        PanelLayout, Panels,
+    };
+
+    #[test]
+    fn opening_and_closing_plays_the_transition() {
+        let entries = ["A", "B"].map(|name| MenuEntry::new(name, Box::new(Dummy::new())));
+        let mut menu: Menu<2, Panels<Frame<64, 32>>, Rgb565> =
+            Menu::new(entries).with_transition(TransitionKind::Wipe, 100_000);
+        let mut target = Panels::new(Frame::new(), PanelLayout::single(Size::new(64, 32)));
+        let a = Controls::new(true, false, false, false, false, false, false);
+        let idle = Controls::default();
+
+        menu.update(0, 0, &idle);
+        menu.update(0, 0, &a);
+        menu.update(0, 0, &idle);
+        assert_eq!(menu.child.index(), Some(0));
+        assert!(menu.child.transition_running());
+
+        // The views are captured by the next render
+        menu.render(&mut target);
+        menu.update(50_000, 0, &idle);
+        menu.render(&mut target);
+        menu.update(50_000, 0, &idle);
+        assert!(!menu.child.transition_running());
+
+        // The dummy asks to be closed right away. It is closed, once its view is captured.
+        menu.update(0, 0, &idle);
+        assert_eq!(menu.child.index(), Some(0));
+        menu.render(&mut target);
+        menu.update(0, 0, &idle);
+        assert_eq!(menu.child.index(), None);
+        assert!(menu.child.transition_running());
+    }
+
+    #[test]
+    fn invalid_icons_are_rejected() {
+        let entry: MenuEntry<MockDisplay<Rgb565>, Rgb565> =
+            MenuEntry::new("A", Box::new(Dummy::new()));
+        assert_eq!(entry.try_with_icon(b"not an image").err(), Some(AppError::Decode));
+    }
+
+    #[test]
+    fn navigation_golden() {
+        let entries = ["Snake", "Pong", "Clock", "Settings"]
+            .map(|name| MenuEntry::new(name, Box::new(Dummy::new())));
+        let menu: Menu<4, Frame<64, 32>, Rgb565> = Menu::new(entries);
+        let mut harness = Harness::new(menu);
+        harness.assert_golden("menu_first");
+
+        harness.press(Button::Down);
+        harness.assert_golden("menu_second");
+        // The last entry scrolls the list
+        harness.press(Button::Down);
+        harness.press(Button::Down);
+        harness.assert_golden("menu_scrolled");
+        // Wraps around to the first entry
+        harness.press(Button::Down);
+        harness.assert_golden("menu_first");
+    }
 }
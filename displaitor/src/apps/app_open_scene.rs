@@ -0,0 +1,63 @@
+use core::marker::PhantomData;
+
+use embedded_graphics::prelude::*;
+
+use crate::{
+    trait_app::{Color, RenderStatus, UpdateResult},
+    App, Command, Controls, Navigation, SceneId,
+};
+
+/// Opens a scene of the surrounding [`crate::Navigator`] as soon as it is updated and closes
+/// itself afterwards. Allows a `Menu` entry to open a scene instead of nesting the app.
+pub struct OpenScene<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    scene: SceneId,
+    requested: bool,
+    _marker: PhantomData<D>,
+}
+
+impl<D, C> OpenScene<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    pub fn new(scene: SceneId) -> Self {
+        Self {
+            scene,
+            requested: false,
+            _marker: Default::default(),
+        }
+    }
+}
+
+impl<D, C> App for OpenScene<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    type Target = D;
+    type Color = C;
+
+    fn reset_state(&mut self) {
+        self.requested = false;
+    }
+
+    fn update(&mut self, _dt: i64, _t: i64, _controls: &Controls) -> UpdateResult {
+        if self.requested {
+            return RenderStatus::NoVisibleChange.into();
+        }
+
+        self.requested = true;
+        UpdateResult::from(RenderStatus::VisibleChange)
+            .with_command(Command::Navigate(Navigation::Push(self.scene)))
+    }
+
+    fn render(&self, _target: &mut Self::Target) {}
+
+    fn close_request(&self) -> bool {
+        self.requested
+    }
+}
@@ -0,0 +1,468 @@
+use core::fmt::Write;
+
+use embedded_graphics::{
+    mono_font::{ascii::FONT_4X6, MonoTextStyle},
+    prelude::*,
+    primitives::{PrimitiveStyle, PrimitiveStyleBuilder, Rectangle},
+    text::{Baseline, Text},
+};
+
+use crate::{
+    settings::{MenuTheme, Setting, Settings},
+    string_buffer::FixedBuffer,
+    trait_app::{RenderStatus, UpdateResult},
+    App, AppClock, Command, Controls, KeyReleaseEvent, Pacing,
+};
+
+const VOLUME_STEP: u8 = 16;
+const ROW_HEIGHT: i32 = 6;
+const PANEL_SIZE: Size = Size::new(44, 4 * ROW_HEIGHT as u32 + 4);
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Item {
+    Resume,
+    Restart,
+    Quit,
+    Volume,
+}
+
+const ITEMS: [Item; 4] = [Item::Resume, Item::Restart, Item::Quit, Item::Volume];
+
+/// Wraps the top-level app and pauses it, when S is pressed. The app isn't updated while paused,
+/// its last frame is shown dimmed behind a menu to resume, restart or quit it and to change the
+/// volume. A on the volume switches the sound off and on.
+///
+/// The time spent paused is hidden from the app: its [`AppClock`] stands still, so `t_us`
+/// continues where it stopped and the first update after resuming gets the `dt_us` of a single
+/// frame.
+pub struct PauseOverlay<A: App> {
+    app: A,
+    /// The volume is changed in a copy of the settings. Changes passing by from the app keep it up
+    /// to date.
+    settings: Settings,
+    paused: bool,
+    /// Time of the app, starting with `reset_state`
+    clock: AppClock,
+    selected: usize,
+
+    pause_request: KeyReleaseEvent,
+    nav_up_request: KeyReleaseEvent,
+    nav_down_request: KeyReleaseEvent,
+    decrease_request: KeyReleaseEvent,
+    increase_request: KeyReleaseEvent,
+    selection_request: KeyReleaseEvent,
+    resume_request: KeyReleaseEvent,
+
+    /// Volume changed since the settings were persisted
+    unsaved: bool,
+}
+
+impl<A> PauseOverlay<A>
+where
+    A: App,
+    A::Target: DrawTarget<Color = A::Color>,
+{
+    pub fn new(app: A, settings: Settings) -> Self {
+        Self {
+            app,
+            settings,
+            paused: false,
+            clock: AppClock::new(),
+            selected: 0,
+
+            pause_request: KeyReleaseEvent::new(),
+            nav_up_request: KeyReleaseEvent::new(),
+            nav_down_request: KeyReleaseEvent::new(),
+            decrease_request: KeyReleaseEvent::new(),
+            increase_request: KeyReleaseEvent::new(),
+            selection_request: KeyReleaseEvent::new(),
+            resume_request: KeyReleaseEvent::new(),
+
+            unsaved: false,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn app(&self) -> &A {
+        &self.app
+    }
+
+    fn pause(&mut self) -> UpdateResult {
+        self.paused = true;
+        self.clock.pause();
+        self.selected = 0;
+        self.nav_up_request.reset();
+        self.nav_down_request.reset();
+        self.decrease_request.reset();
+        self.increase_request.reset();
+        self.selection_request.reset();
+        self.resume_request.reset();
+        self.app
+            .on_pause()
+            .merge(RenderStatus::VisibleChange.into())
+    }
+
+    /// Leaves the pause with the result of the chosen action.
+    fn unpause(&mut self, result: UpdateResult) -> UpdateResult {
+        self.paused = false;
+        self.clock.resume();
+        self.pause_request.reset();
+        let result = result.merge(RenderStatus::VisibleChange.into());
+        if !self.unsaved {
+            return result;
+        }
+        self.unsaved = false;
+        result.with_command(Command::SaveSettings)
+    }
+
+    fn update_paused(&mut self, controls: &Controls) -> UpdateResult {
+        self.nav_up_request.update(controls.dpad_up);
+        self.nav_down_request.update(controls.dpad_down);
+        self.decrease_request.update(controls.dpad_left);
+        self.increase_request.update(controls.dpad_right);
+        self.selection_request.update(controls.buttons_a);
+        self.resume_request
+            .update(controls.buttons_b || controls.buttons_s);
+
+        let item = ITEMS[self.selected];
+        if self.resume_request.fired() {
+            let resumed = self.app.on_resume();
+            return self.unpause(resumed);
+        } else if self.selection_request.fired() {
+            match item {
+                Item::Resume => {
+                    let resumed = self.app.on_resume();
+                    return self.unpause(resumed);
+                }
+                Item::Restart => {
+                    let restarted = self.app.restart();
+                    self.clock.reset();
+                    return self.unpause(restarted);
+                }
+                Item::Quit => {
+                    let closed = match self.app.close_inner() {
+                        Some(closed) => closed,
+                        // Nothing to quit, e.g. in the launcher
+                        None => self.app.on_resume(),
+                    };
+                    return self.unpause(closed);
+                }
+                Item::Volume => {
+                    let enabled = !self.settings.audio_enabled;
+                    return self.change(Setting::AudioEnabled(enabled));
+                }
+            }
+        } else if self.nav_down_request.fired() {
+            self.selected = (self.selected + 1) % ITEMS.len();
+        } else if self.nav_up_request.fired() {
+            self.selected = (self.selected + ITEMS.len() - 1) % ITEMS.len();
+        } else if item == Item::Volume
+            && (self.decrease_request.fired() || self.increase_request.fired())
+        {
+            let volume = match self.increase_request.fired() {
+                true => self.settings.volume.saturating_add(VOLUME_STEP),
+                false => self.settings.volume.saturating_sub(VOLUME_STEP),
+            };
+            if volume != self.settings.volume {
+                return self.change(Setting::Volume(volume));
+            }
+            return RenderStatus::NoVisibleChange.into();
+        } else {
+            return RenderStatus::NoVisibleChange.into();
+        }
+        RenderStatus::VisibleChange.into()
+    }
+
+    /// Applies the change right away, the settings are persisted when the pause ends.
+    fn change(&mut self, setting: Setting) -> UpdateResult {
+        self.settings.apply(setting);
+        self.unsaved = true;
+        UpdateResult::from(RenderStatus::VisibleChange).with_command(Command::Setting(setting))
+    }
+
+    fn render_menu(&self, target: &mut A::Target) {
+        let colors = MenuTheme::active().colors();
+        let bounds = target.bounding_box();
+
+        // Every other pixel is blacked out, so the frame is dimmed to half
+        let black = A::Color::BLACK;
+        let pixels = bounds
+            .points()
+            .filter(|point| (point.x + point.y) % 2 == 0)
+            .map(|point| Pixel(point, black));
+        let _dimmed = target.draw_iter(pixels);
+
+        let panel = Rectangle::with_center(bounds.center(), PANEL_SIZE);
+        let panel_style = PrimitiveStyleBuilder::new()
+            .fill_color(black)
+            .stroke_color(A::Color::from(colors.indicator))
+            .stroke_width(1)
+            .build();
+        let _panel = panel.into_styled(panel_style).draw(target);
+
+        let text_style = MonoTextStyle::new(&FONT_4X6, A::Color::from(colors.text));
+        let selected_style = MonoTextStyle::new(&FONT_4X6, A::Color::from(colors.selected));
+        let mut label = FixedBuffer::<16>::new();
+        for (i, item) in ITEMS.iter().enumerate() {
+            label.clear();
+            let _ = match item {
+                Item::Resume => write!(label, "Resume"),
+                Item::Restart => write!(label, "Restart"),
+                Item::Quit => write!(label, "Quit"),
+                Item::Volume if !self.settings.audio_enabled => write!(label, "Vol off"),
+                Item::Volume => write!(label, "Vol {}%", percent(self.settings.volume)),
+            };
+            let (prefix, style) = match i == self.selected {
+                true => (">", selected_style),
+                false => (" ", text_style),
+            };
+            let position = panel.top_left + Point::new(2, 2 + i as i32 * ROW_HEIGHT);
+            let _prefix = Text::with_baseline(prefix, position, style, Baseline::Top).draw(target);
+            let _label = Text::with_baseline(
+                label.as_str(),
+                position + Point::new(6, 0),
+                style,
+                Baseline::Top,
+            )
+            .draw(target);
+        }
+    }
+}
+
+fn percent(value: u8) -> u32 {
+    (value as u32 * 100 + 127) / 255
+}
+
+impl<A> App for PauseOverlay<A>
+where
+    A: App,
+    A::Target: DrawTarget<Color = A::Color>,
+{
+    type Target = A::Target;
+    type Color = A::Color;
+
+    fn reset_state(&mut self) {
+        self.paused = false;
+        self.clock.reset();
+        self.pause_request.reset();
+        self.app.reset_state();
+    }
+
+    fn update(&mut self, dt_us: i64, _t_us: i64, controls: &Controls) -> UpdateResult {
+        if self.paused {
+            return self.update_paused(controls);
+        }
+
+        self.pause_request.update(controls.buttons_s);
+        if self.pause_request.fired() {
+            return self.pause();
+        }
+
+        let dt_us = self.clock.advance(dt_us);
+        let result = self.app.update(dt_us, self.clock.t_us(), controls);
+        for command in result.commands() {
+            if let Command::Setting(setting) = command {
+                self.settings.apply(*setting);
+            }
+        }
+        result
+    }
+
+    fn render(&self, target: &mut Self::Target) {
+        self.app.render(target);
+        if self.paused {
+            self.render_menu(target);
+        }
+    }
+
+    fn render_region(&self, target: &mut Self::Target, area: &Rectangle) {
+        match self.paused {
+            true => self.render(target),
+            false => self.app.render_region(target, area),
+        }
+    }
+
+    fn teardown(&mut self) {
+        self.app.teardown();
+    }
+
+    fn close_request(&self) -> bool {
+        self.app.close_request()
+    }
+
+    fn pacing(&self) -> Pacing {
+        match self.paused {
+            true => Pacing::DEFAULT,
+            false => self.app.pacing(),
+        }
+    }
+
+    fn active_app_name(&self) -> Option<&str> {
+        self.app.active_app_name()
+    }
+
+    fn on_enter(&mut self) -> UpdateResult {
+        self.app.on_enter()
+    }
+
+    fn on_pause(&mut self) -> UpdateResult {
+        self.app.on_pause()
+    }
+
+    fn on_resume(&mut self) -> UpdateResult {
+        self.app.on_resume()
+    }
+
+    fn on_exit(&mut self) -> UpdateResult {
+        self.app.on_exit()
+    }
+
+    fn restart(&mut self) -> UpdateResult {
+        self.app.restart()
+    }
+
+    fn close_inner(&mut self) -> Option<UpdateResult> {
+        self.app.close_inner()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{rc::Rc, vec::Vec};
+    use core::cell::RefCell;
+
+    use embedded_graphics::{mock_display::MockDisplay, pixelcolor::Rgb565};
+
+    use super::*;
+
+    type Log = Rc<RefCell<Vec<(&'static str, i64, i64)>>>;
+
+    /// Records its calls as (name, dt, t).
+    struct Recorder {
+        log: Log,
+    }
+
+    impl App for Recorder {
+        type Target = MockDisplay<Rgb565>;
+        type Color = Rgb565;
+
+        fn reset_state(&mut self) {
+            self.log.borrow_mut().push(("reset_state", 0, 0));
+        }
+
+        fn update(&mut self, dt_us: i64, t_us: i64, _controls: &Controls) -> UpdateResult {
+            self.log.borrow_mut().push(("update", dt_us, t_us));
+            RenderStatus::NoVisibleChange.into()
+        }
+
+        fn render(&self, _target: &mut Self::Target) {}
+
+        fn on_pause(&mut self) -> UpdateResult {
+            self.log.borrow_mut().push(("on_pause", 0, 0));
+            RenderStatus::NoVisibleChange.into()
+        }
+
+        fn on_resume(&mut self) -> UpdateResult {
+            self.log.borrow_mut().push(("on_resume", 0, 0));
+            RenderStatus::NoVisibleChange.into()
+        }
+    }
+
+    fn overlay() -> (PauseOverlay<Recorder>, Log) {
+        let log = Log::default();
+        let recorder = Recorder { log: log.clone() };
+        (PauseOverlay::new(recorder, Settings::default()), log)
+    }
+
+    /// Runs three frames of `FRAME_US`, pressing and releasing a button.
+    fn press(
+        overlay: &mut PauseOverlay<Recorder>,
+        t_us: &mut i64,
+        button: fn(&mut Controls),
+    ) -> UpdateResult {
+        const FRAME_US: i64 = 10_000;
+        let mut pressed = Controls::default();
+        button(&mut pressed);
+        let mut result = None;
+        for controls in [Controls::default(), pressed, Controls::default()] {
+            *t_us += FRAME_US;
+            result = Some(overlay.update(FRAME_US, *t_us, &controls));
+        }
+        result.unwrap()
+    }
+
+    #[test]
+    fn paused_time_is_not_delivered() {
+        let (mut overlay, log) = overlay();
+        let mut t_us = 0;
+        let _ = press(&mut overlay, &mut t_us, |c| c.buttons_s = true);
+        assert!(overlay.is_paused());
+        let updates_before = log.borrow().len();
+
+        // A long pause
+        let _ = overlay.update(60_000_000, t_us + 60_000_000, &Controls::default());
+        t_us += 60_000_000;
+        assert_eq!(log.borrow().len(), updates_before);
+
+        let _ = press(&mut overlay, &mut t_us, |c| c.buttons_a = true);
+        assert!(!overlay.is_paused());
+        assert_eq!(log.borrow().last().unwrap().0, "on_resume");
+
+        let _ = overlay.update(10_000, t_us + 10_000, &Controls::default());
+        let (name, dt, t) = *log.borrow().last().unwrap();
+        assert_eq!((name, dt), ("update", 10_000));
+        // The two frames before the pause and the one after it
+        assert_eq!(t, 30_000);
+    }
+
+    #[test]
+    fn volume_changes_are_applied_and_persisted() {
+        let (mut overlay, log) = overlay();
+        let mut t_us = 0;
+        let _ = press(&mut overlay, &mut t_us, |c| c.buttons_s = true);
+        let _ = press(&mut overlay, &mut t_us, |c| c.dpad_up = true);
+        let changed = press(&mut overlay, &mut t_us, |c| c.dpad_left = true);
+        assert_eq!(
+            changed.commands(),
+            [Command::Setting(Setting::Volume(255 - VOLUME_STEP))]
+        );
+
+        let mut display = MockDisplay::new();
+        display.set_allow_overdraw(true);
+        overlay.render(&mut display);
+
+        let resumed = press(&mut overlay, &mut t_us, |c| c.buttons_b = true);
+        assert_eq!(resumed.commands(), [Command::SaveSettings]);
+        assert!(log.borrow().iter().any(|(name, _, _)| *name == "on_pause"));
+    }
+
+    #[test]
+    fn the_sound_is_switched_off_and_on() {
+        let (mut overlay, _log) = overlay();
+        let mut t_us = 0;
+        let _ = press(&mut overlay, &mut t_us, |c| c.buttons_s = true);
+        let _ = press(&mut overlay, &mut t_us, |c| c.dpad_up = true);
+        for audio_enabled in [false, true] {
+            let changed = press(&mut overlay, &mut t_us, |c| c.buttons_a = true);
+            assert_eq!(
+                changed.commands(),
+                [Command::Setting(Setting::AudioEnabled(audio_enabled))]
+            );
+            assert!(overlay.is_paused());
+        }
+    }
+
+    #[test]
+    fn restart_starts_the_app_over() {
+        let (mut overlay, log) = overlay();
+        let mut t_us = 0;
+        let _ = press(&mut overlay, &mut t_us, |c| c.buttons_s = true);
+        let _ = press(&mut overlay, &mut t_us, |c| c.dpad_down = true);
+        let _ = press(&mut overlay, &mut t_us, |c| c.buttons_a = true);
+        assert!(!overlay.is_paused());
+        assert_eq!(log.borrow().last().unwrap().0, "reset_state");
+    }
+}
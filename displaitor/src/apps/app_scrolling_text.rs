@@ -1,10 +1,13 @@
 use core::marker::PhantomData;
 use embedded_graphics::prelude::*;
+use crate::display::DisplayInfo;
+use crate::registry::{AppInfo, AppMeta, Category};
 use crate::trait_app::{RenderStatus, UpdateResult};
 // use embedded_graphics::mono_font::{ascii::FONT_6X10, MonoTextStyle};
-use crate::{App, Color, Controls, KeyReleaseEvent};
+use crate::{App, Color, Controls, KeyReleaseEvent, Pacing};
 use embedded_graphics::mono_font::MonoTextStyle;
 use embedded_graphics::pixelcolor::{Rgb565, Rgb888};
+use embedded_graphics::primitives::Rectangle;
 use embedded_graphics::text::{Baseline, Text};
 use tinyrand::{Rand, Seeded, StdRand};
 
@@ -14,7 +17,9 @@ use tinyrand::{Rand, Seeded, StdRand};
 // use embedded_graphics::mono_font::ascii::FONT_10X20 as FONT;
 use embedded_graphics::mono_font::iso_8859_10::FONT_10X20 as FONT;
 const FONT_WIDTH: i32 = 10;
-const FONT_VERT_OFFSET: i32 = 6;
+const FONT_HEIGHT: u32 = 20;
+/// Time between two steps of the text, 30 ms
+const TICK_US: i64 = 30_000;
 
 /// A scrolling text application that continuously scrolls a random sentence followed immediately
 /// by another random sentence. On every update call the text is shifted one pixel to the left.
@@ -26,6 +31,8 @@ where
     C: Color,
 {
     messages: [&'static str; N],
+    /// Top of the line, it is centred vertically
+    line_y: i32,
     index_current: usize,
     index_next: usize,
     /// The current horizontal offset (in pixels) for the current message.
@@ -34,7 +41,6 @@ where
     next_color: C,
     prng: StdRand,
 
-    last_update: i64,
     close_request: KeyReleaseEvent,
 
     _marker: PhantomData<D>,
@@ -55,10 +61,11 @@ where
 {
     /// Create a new scrolling text app.
     ///
+    /// - `display` is the panel the line is centred on.
     /// - `messages` is a fixed-size array of sentences.
     /// - The PRNG is seeded with a fixed value (change as needed).
     /// - The current and next message indices (and colors) are chosen at random.
-    pub fn new(messages: [&'static str; N]) -> Self {
+    pub fn new(display: DisplayInfo, messages: [&'static str; N]) -> Self {
         let mut prng = StdRand::seed(0xDEAD_BEEF);
         let index_current = 0; // prng.next_lim_usize(N);
         let index_next = 1;
@@ -69,6 +76,7 @@ where
 
         Self {
             messages,
+            line_y: (display.height() - FONT_HEIGHT as i32) / 2,
             index_current,
             index_next,
             line_buffer_offset: 0,
@@ -76,7 +84,6 @@ where
             next_color,
             prng,
 
-            last_update: 0,
             close_request: KeyReleaseEvent::new(),
 
             _marker: PhantomData,
@@ -84,6 +91,14 @@ where
     }
 }
 
+impl<D, C, const N: usize> AppMeta for ScrollingText<D, C, N>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    const INFO: AppInfo = AppInfo::new("scrolling_text", "Names", Category::Messages);
+}
+
 impl<D, C, const N: usize> App for ScrollingText<D, C, N>
 where
     D: DrawTarget<Color = C>,
@@ -102,16 +117,9 @@ where
         self.close_request.reset();
     }
 
-    fn update(&mut self, _dt_us: i64, t_us: i64, controls: &Controls) -> UpdateResult {
+    fn update(&mut self, _dt_us: i64, _t_us: i64, controls: &Controls) -> UpdateResult {
         self.close_request.update(controls.buttons_b);
 
-        // Time gate
-        const MIN_UPDATE_DT_US: i64 = 30_000;
-        if t_us - self.last_update < MIN_UPDATE_DT_US {
-            return RenderStatus::NoVisibleChange.into();
-        }
-        self.last_update = t_us;
-
         // Shift the text one pixel left per update.
         self.line_buffer_offset += 1;
         // Assume each character is 6 pixels wide.
@@ -128,7 +136,12 @@ where
             self.next_color = get_random_color(&mut self.prng);
         }
 
-        RenderStatus::VisibleChange.into()
+        // Only the band with the text moves
+        let band = Rectangle::new(
+            Point::new(0, self.line_y),
+            Size::new(u16::MAX as u32, FONT_HEIGHT),
+        );
+        RenderStatus::dirty(band).into()
     }
 
     fn render(&self, target: &mut Self::Target) {
@@ -142,7 +155,7 @@ where
         // Draw the current message.
         let _ = Text::with_baseline(
             current,
-            Point::new(x_offset, FONT_VERT_OFFSET),
+            Point::new(x_offset, self.line_y),
             style_current,
             Baseline::Top,
         )
@@ -153,7 +166,7 @@ where
         let next_x_offset = x_offset + current_width;
         let _ = Text::with_baseline(
             next,
-            Point::new(next_x_offset, FONT_VERT_OFFSET),
+            Point::new(next_x_offset, self.line_y),
             style_next,
             Baseline::Top,
         )
@@ -165,4 +178,54 @@ where
     fn close_request(&self) -> bool {
         self.close_request.fired()
     }
+
+    fn pacing(&self) -> Pacing {
+        Pacing::every(TICK_US)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{Frame, Harness};
+
+    /// Replaces the randomly chosen messages and colours.
+    fn choose<const H: usize>(
+        text: &mut ScrollingText<Frame<64, H>, Rgb565, 2>,
+        current: usize,
+        next: usize,
+    ) {
+        text.index_current = current;
+        text.index_next = next;
+        text.current_color = Rgb565::CSS_LIME_GREEN;
+        text.next_color = Rgb565::CSS_ORANGE;
+    }
+
+    #[test]
+    fn wrap_golden() {
+        let text = ScrollingText::new(DisplayInfo::PANEL_64X32, ["HELLO", "WORLD"]);
+        let mut harness = Harness::<_, 64, 32>::new(text);
+        choose(harness.app_mut(), 0, 1);
+        harness.assert_golden("scrolling_text_start");
+
+        // A pixel every 30 ms, two every three frames
+        harness.wait(3 * 12);
+        assert_eq!(harness.app().line_buffer_offset, 24);
+        harness.assert_golden("scrolling_text_half");
+
+        // "HELLO" has scrolled off, "WORLD" is the current message
+        harness.wait(3 * 13);
+        assert_eq!(harness.app().index_current, 1);
+        assert_eq!(harness.app().line_buffer_offset, 0);
+        choose(harness.app_mut(), 1, 0);
+        harness.assert_golden("scrolling_text_wrapped");
+    }
+
+    #[test]
+    fn square_panel_golden() {
+        let text = ScrollingText::new(DisplayInfo::PANEL_64X64, ["HELLO", "WORLD"]);
+        let mut harness = Harness::<_, 64, 64>::new(text);
+        choose(harness.app_mut(), 0, 1);
+        harness.assert_golden("scrolling_text_64x64");
+    }
 }
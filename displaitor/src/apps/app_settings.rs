@@ -0,0 +1,380 @@
+use core::{fmt::Write, marker::PhantomData};
+
+use alloc::vec::Vec;
+
+use embedded_graphics::prelude::*;
+
+use super::menu_list::{MenuList, RowKind};
+use crate::{
+    registry::{AppInfo, AppMeta, Category},
+    settings::{MenuTheme, Setting, Settings},
+    string_buffer::FixedBuffer,
+    trait_app::{Color, RenderStatus, UpdateResult},
+    App, Command, Controls, DirtyRegions, DisplayInfo, KeyReleaseEvent, Orientation,
+};
+
+/// Size of the panel, if not set otherwise.
+const DEFAULT_VIEWPORT: Size = DisplayInfo::PANEL_64X32.size;
+
+/// Lowest brightness, so the panel can't be turned off completely.
+const BRIGHTNESS_MIN: u8 = 16;
+const BRIGHTNESS_STEP: u8 = 16;
+const VOLUME_STEP: u8 = 16;
+const GAMMA_TENTHS_RANGE: (u8, u8) = (10, 30);
+/// Selectable screensaver timeouts in seconds. 0 disables it.
+const SCREENSAVER_TIMEOUTS_S: [u16; 6] = [0, 30, 60, 120, 300, 600];
+
+/// Rows of the settings app, in the order shown.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Item {
+    Brightness,
+    Gamma,
+    Volume,
+    Audio,
+    Screensaver,
+    Theme,
+    Orientation,
+    StartApp,
+}
+
+const ITEMS: [Item; 8] = [
+    Item::Brightness,
+    Item::Gamma,
+    Item::Volume,
+    Item::Audio,
+    Item::Screensaver,
+    Item::Theme,
+    Item::Orientation,
+    Item::StartApp,
+];
+
+type Label = FixedBuffer<32>;
+
+/// Lets the user change the [`Settings`]. Up/down selects a setting and left/right changes it.
+///
+/// Every change is sent as [`Command::Setting`], so the top-level loop applies it right away. It
+/// persists the settings on [`Command::SaveSettings`], sent when the app is closed.
+pub struct SettingsApp<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    settings: Settings,
+    /// Apps which can be opened on start
+    start_apps: Vec<AppInfo>,
+    list: MenuList,
+
+    nav_up_request: KeyReleaseEvent,
+    nav_down_request: KeyReleaseEvent,
+    decrease_request: KeyReleaseEvent,
+    increase_request: KeyReleaseEvent,
+    close_request: KeyReleaseEvent,
+
+    /// The whole list has to be drawn on the next update.
+    redraw: bool,
+    /// Settings changed since they were persisted
+    unsaved: bool,
+    _marker: PhantomData<D>,
+}
+
+impl<D, C> SettingsApp<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    pub fn new(settings: Settings, start_apps: Vec<AppInfo>) -> Self {
+        Self {
+            settings,
+            start_apps,
+            list: MenuList::new(DEFAULT_VIEWPORT),
+
+            nav_up_request: KeyReleaseEvent::new(),
+            nav_down_request: KeyReleaseEvent::new(),
+            decrease_request: KeyReleaseEvent::new(),
+            increase_request: KeyReleaseEvent::new(),
+            close_request: KeyReleaseEvent::new(),
+
+            redraw: true,
+            unsaved: false,
+            _marker: Default::default(),
+        }
+    }
+
+    /// Size of the area the settings are drawn to. Defaults to a 64x32 panel.
+    pub fn with_viewport(mut self, viewport: Size) -> Self {
+        self.list.set_viewport(viewport);
+        self
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    fn label(&self, item: Item) -> Label {
+        let settings = &self.settings;
+        let mut label = Label::new();
+        let _ = match item {
+            Item::Brightness => write!(label, "Light {}%", percent(settings.brightness)),
+            Item::Gamma => write!(
+                label,
+                "Gamma {}.{}",
+                settings.gamma_tenths / 10,
+                settings.gamma_tenths % 10
+            ),
+            Item::Volume => write!(label, "Vol {}%", percent(settings.volume)),
+            Item::Audio if settings.audio_enabled => write!(label, "Sound on"),
+            Item::Audio => write!(label, "Sound off"),
+            Item::Screensaver => match settings.screensaver_timeout_s {
+                0 => write!(label, "Saver off"),
+                s if s % 60 == 0 => write!(label, "Saver {}m", s / 60),
+                s => write!(label, "Saver {}s", s),
+            },
+            Item::Theme => write!(label, "Theme {}", settings.theme.name()),
+            Item::Orientation => write!(label, "{}", settings.orientation.name()),
+            Item::StartApp => {
+                let name = settings
+                    .start_app()
+                    .and_then(|id| self.start_apps.iter().find(|info| info.id == id))
+                    .map_or("Menu", |info| info.name);
+                write!(label, "Start {}", name)
+            }
+        };
+        label
+    }
+
+    /// Changes the setting one step up or down. Returns the change, if there was one.
+    fn change(&mut self, item: Item, increase: bool) -> Option<Setting> {
+        let settings = &self.settings;
+        let setting = match item {
+            Item::Brightness => Setting::Brightness(
+                step(settings.brightness, BRIGHTNESS_STEP, increase).max(BRIGHTNESS_MIN),
+            ),
+            Item::Gamma => {
+                let (min, max) = GAMMA_TENTHS_RANGE;
+                let gamma_tenths = match increase {
+                    true => settings.gamma_tenths.saturating_add(1),
+                    false => settings.gamma_tenths.saturating_sub(1),
+                };
+                Setting::GammaTenths(gamma_tenths.clamp(min, max))
+            }
+            Item::Volume => Setting::Volume(step(settings.volume, VOLUME_STEP, increase)),
+            Item::Audio => Setting::AudioEnabled(!settings.audio_enabled),
+            Item::Screensaver => Setting::ScreensaverTimeout(cycle(
+                &SCREENSAVER_TIMEOUTS_S,
+                &settings.screensaver_timeout_s,
+                increase,
+            )),
+            Item::Theme => Setting::Theme(cycle(&MenuTheme::ALL, &settings.theme, increase)),
+            Item::Orientation => Setting::Orientation(cycle(
+                &Orientation::SAME_SIZE,
+                &settings.orientation,
+                increase,
+            )),
+            Item::StartApp => {
+                // `None` (the launcher) comes first, then the apps
+                let len = self.start_apps.len() + 1;
+                let current = settings
+                    .start_app()
+                    .and_then(|id| self.start_apps.iter().position(|info| info.id == id))
+                    .map_or(0, |index| index + 1);
+                let next = match increase {
+                    true => (current + 1) % len,
+                    false => (current + len - 1) % len,
+                };
+                Setting::StartApp(next.checked_sub(1).map(|index| self.start_apps[index].id))
+            }
+        };
+        let before = self.settings.clone();
+        self.settings.apply(setting);
+        (self.settings != before).then_some(setting)
+    }
+}
+
+fn percent(value: u8) -> u32 {
+    (value as u32 * 100 + 127) / 255
+}
+
+/// Steps towards 0 or 255, ending exactly on both.
+fn step(value: u8, step: u8, increase: bool) -> u8 {
+    match increase {
+        true => value.saturating_add(step),
+        false => value.saturating_sub(step),
+    }
+}
+
+/// The value after (or before) `current` in `options`, wrapping around. Starts with the first
+/// option, if `current` isn't one of them.
+fn cycle<T: PartialEq + Clone>(options: &[T], current: &T, forward: bool) -> T {
+    let len = options.len();
+    let next = match options.iter().position(|option| option == current) {
+        Some(index) if forward => (index + 1) % len,
+        Some(index) => (index + len - 1) % len,
+        None => 0,
+    };
+    options[next].clone()
+}
+
+impl<D, C> AppMeta for SettingsApp<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    const INFO: AppInfo = AppInfo::new("settings", "Settings", Category::System);
+}
+
+impl<D, C> App for SettingsApp<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    type Target = D;
+    type Color = C;
+
+    fn reset_state(&mut self) {
+        // The settings are kept, they are the current state of the device.
+        self.list.reset();
+        self.nav_up_request.reset();
+        self.nav_down_request.reset();
+        self.decrease_request.reset();
+        self.increase_request.reset();
+        self.close_request.reset();
+        self.redraw = true;
+    }
+
+    fn update(&mut self, dt: i64, _t: i64, controls: &Controls) -> UpdateResult {
+        self.nav_up_request.update(controls.dpad_up);
+        self.nav_down_request.update(controls.dpad_down);
+        self.decrease_request.update(controls.dpad_left);
+        self.increase_request.update(controls.dpad_right);
+        self.close_request.update(controls.buttons_b);
+
+        let selected_before = self.list.selected;
+        let scroll_before = self.list.scroll_offset();
+        let item = ITEMS[self.list.selected];
+        let mut changed = None;
+        if self.nav_down_request.fired() {
+            self.list.select_next(ITEMS.len());
+        } else if self.nav_up_request.fired() {
+            self.list.select_previous(ITEMS.len());
+        } else if self.decrease_request.fired() {
+            changed = self.change(item, false);
+        } else if self.increase_request.fired() {
+            changed = self.change(item, true);
+        }
+        self.unsaved |= changed.is_some();
+
+        let label = self.label(ITEMS[self.list.selected]);
+        let marquee_moved = self.list.update(dt, label.as_str(), ITEMS.len());
+
+        let render_result = if self.redraw || scroll_before != self.list.scroll_offset() {
+            RenderStatus::VisibleChange
+        } else if selected_before != self.list.selected || marquee_moved || changed.is_some() {
+            let mut dirty = DirtyRegions::from(self.list.row_area(selected_before));
+            dirty.add(self.list.row_area(self.list.selected));
+            RenderStatus::Dirty(dirty)
+        } else {
+            RenderStatus::NoVisibleChange
+        };
+        self.redraw = false;
+
+        let result: UpdateResult = render_result.into();
+        match changed {
+            Some(setting) => result.with_command(Command::Setting(setting)),
+            None => result,
+        }
+    }
+
+    fn render(&self, target: &mut Self::Target) {
+        let labels = ITEMS.map(|item| self.label(item));
+        let rows = labels.iter().map(|label| (label.as_str(), RowKind::Entry));
+        self.list.render(target, rows, labels.len());
+    }
+
+    fn close_request(&self) -> bool {
+        self.close_request.fired()
+    }
+
+    fn on_exit(&mut self) -> UpdateResult {
+        let result: UpdateResult = RenderStatus::NoVisibleChange.into();
+        if !self.unsaved {
+            return result;
+        }
+        self.unsaved = false;
+        result.with_command(Command::SaveSettings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::{mock_display::MockDisplay, pixelcolor::Rgb565};
+
+    use super::*;
+
+    type TestSettings = SettingsApp<MockDisplay<Rgb565>, Rgb565>;
+
+    fn app() -> TestSettings {
+        let apps = vec![
+            AppInfo::new("pong", "Pong", Category::Games),
+            AppInfo::new("snake", "Snake", Category::Games),
+        ];
+        SettingsApp::new(Settings::default(), apps)
+    }
+
+    #[test]
+    fn values_stay_in_range() {
+        let mut app = app();
+        for _ in 0..20 {
+            app.change(Item::Brightness, false);
+            app.change(Item::Gamma, true);
+        }
+        assert_eq!(app.settings().brightness, BRIGHTNESS_MIN);
+        assert_eq!(app.settings().gamma_tenths, GAMMA_TENTHS_RANGE.1);
+        assert_eq!(app.label(Item::Gamma).as_str(), "Gamma 3.0");
+
+        assert_eq!(app.change(Item::Volume, true), None);
+        assert_eq!(
+            app.change(Item::Screensaver, false),
+            Some(Setting::ScreensaverTimeout(600))
+        );
+        assert_eq!(app.label(Item::Screensaver).as_str(), "Saver 10m");
+    }
+
+    #[test]
+    fn start_app_cycles_through_launcher_and_apps() {
+        let mut app = app();
+        assert_eq!(app.label(Item::StartApp).as_str(), "Start Menu");
+        app.change(Item::StartApp, false);
+        assert_eq!(app.settings().start_app(), Some("snake"));
+        app.change(Item::StartApp, true);
+        app.change(Item::StartApp, true);
+        assert_eq!(app.label(Item::StartApp).as_str(), "Start Pong");
+    }
+
+    /// Presses and releases a button. Returns the result of the last update.
+    fn press(app: &mut TestSettings, button: fn(&mut Controls)) -> UpdateResult {
+        let mut pressed = Controls::default();
+        button(&mut pressed);
+        let _ = app.update(0, 0, &Controls::default());
+        let _ = app.update(0, 0, &pressed);
+        app.update(0, 0, &Controls::default())
+    }
+
+    #[test]
+    fn changes_are_applied_and_persisted_on_exit() {
+        let mut app = app();
+        assert!(app.on_exit().commands().is_empty());
+
+        let _ = press(&mut app, |c| c.dpad_down = true);
+        let _ = press(&mut app, |c| c.dpad_down = true);
+        let _ = press(&mut app, |c| c.dpad_down = true);
+        let changed = press(&mut app, |c| c.dpad_right = true);
+        assert_eq!(
+            changed.commands(),
+            [Command::Setting(Setting::AudioEnabled(false))]
+        );
+        assert!(!app.settings().audio_enabled);
+
+        assert_eq!(app.on_exit().commands(), [Command::SaveSettings]);
+        assert!(app.on_exit().commands().is_empty());
+    }
+}
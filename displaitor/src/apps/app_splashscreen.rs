@@ -1,7 +1,6 @@
 /// Just dispalys an image
 use core::marker::PhantomData;
 
-use embedded_error_chain::ChainError;
 use embedded_graphics::{
     draw_target::ColorConverted,
     pixelcolor::{Rgb565, Rgb888},
@@ -12,8 +11,9 @@ use heapless::Vec;
 use tinyqoi::Qoi;
 
 use crate::{
+    error::decode_frames,
     trait_app::{Color, RenderStatus, UpdateResult},
-    App, Controls, KeyReleaseEvent,
+    App, AppError, AudioID, Command, Controls, KeyReleaseEvent,
 };
 
 pub struct SplashScreen<D, C>
@@ -28,6 +28,7 @@ where
     last_time_us: i64,
     current_frame: usize,
     time_over: bool,
+    music: Option<AudioID>,
     _marker: PhantomData<D>,
 }
 
@@ -37,21 +38,32 @@ where
     C: Color,
 {
     // TODO: Should take a const filepath of a .qoi
+    /// Panics, if the images aren't valid. See [`SplashScreen::try_new`].
     pub fn new(qoi_data: [&'static [u8]; 2]) -> Self {
-        Self {
-            image: [
-                Qoi::new(qoi_data[0]).unwrap(),
-                Qoi::new(qoi_data[1]).unwrap(),
-            ],
+        Self::try_new(qoi_data).expect("Invalid QOI data")
+    }
+
+    /// Both images have to be of the same size.
+    pub fn try_new(qoi_data: [&'static [u8]; 2]) -> Result<Self, AppError> {
+        Ok(Self {
+            image: decode_frames(qoi_data)?,
 
             close_request: KeyReleaseEvent::new(),
             close_after_us: 4_000_000,
             last_time_us: 0,
             current_frame: 0,
             time_over: false,
+            music: None,
 
             _marker: Default::default(),
-        }
+        })
+    }
+
+    /// Plays `music`, while it is shown. Like the music of any app, it is silent, if the sound is
+    /// switched off.
+    pub fn with_music(mut self, music: AudioID) -> Self {
+        self.music = Some(music);
+        self
     }
 }
 
@@ -83,13 +95,74 @@ where
         let mut target_rgb888: ColorConverted<'_, _, Rgb888> = target.color_converted();
 
         let image = &self.image[self.current_frame];
-        let _img = embedded_graphics::image::Image::with_center(image, Point::new(32, 16))
-            .draw(&mut target_rgb888);
+        let center = target_rgb888.bounding_box().center();
+        let _img =
+            embedded_graphics::image::Image::with_center(image, center).draw(&mut target_rgb888);
     }
 
     fn teardown(&mut self) {}
 
+    fn on_enter(&mut self) -> UpdateResult {
+        let entered = UpdateResult::from(RenderStatus::VisibleChange);
+        match self.music {
+            Some(music) => entered.with_command(Command::play_music(music)),
+            None => entered,
+        }
+    }
+
+    fn on_exit(&mut self) -> UpdateResult {
+        let exited = UpdateResult::from(RenderStatus::NoVisibleChange);
+        match self.music {
+            Some(_) => exited.with_command(Command::stop_music()),
+            None => exited,
+        }
+    }
+
     fn close_request(&self) -> bool {
         self.time_over || self.close_request.fired()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{Frame, Harness};
+
+    /// 8x4 QOI images of a single colour
+    const RED: &[u8] = &[
+        b'q', b'o', b'i', b'f', 0, 0, 0, 8, 0, 0, 0, 4, 3, 0, 0xFE, 0xFF, 0, 0, 0xC0 | 30, 0, 0, 0,
+        0, 0, 0, 0, 1,
+    ];
+    const BLUE: &[u8] = &[
+        b'q', b'o', b'i', b'f', 0, 0, 0, 8, 0, 0, 0, 4, 3, 0, 0xFE, 0, 0, 0xFF, 0xC0 | 30, 0, 0, 0,
+        0, 0, 0, 0, 1,
+    ];
+
+    #[test]
+    fn timing_golden() {
+        let splash: SplashScreen<Frame<64, 32>, Rgb565> = SplashScreen::new([RED, BLUE]);
+        let mut harness = Harness::new(splash);
+        harness.wait(1);
+        harness.assert_golden("splash_first");
+
+        // The second image is shown after half of the 4 s
+        harness.wait(98);
+        harness.assert_golden("splash_first");
+        harness.wait(1);
+        harness.assert_golden("splash_second");
+
+        harness.wait(100);
+        assert!(!harness.app().close_request());
+        harness.wait(1);
+        assert!(harness.app().close_request());
+    }
+
+    #[test]
+    fn plays_the_music_while_shown() {
+        let mut splash: SplashScreen<Frame<64, 32>, Rgb565> =
+            SplashScreen::new([RED, BLUE]).with_music(AudioID::MusicDepp);
+        let entered = splash.on_enter();
+        assert_eq!(entered.commands(), [Command::play_music(AudioID::MusicDepp)]);
+        assert_eq!(splash.on_exit().commands(), [Command::stop_music()]);
+    }
+}
@@ -0,0 +1,208 @@
+use core::fmt::Write;
+
+use embedded_graphics::{
+    mono_font::{
+        ascii::{FONT_4X6, FONT_6X10},
+        MonoTextStyle,
+    },
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+    text::{Baseline, Text},
+};
+
+use crate::{
+    high_scores::{HighScoreTable, Initials},
+    settings::MenuTheme,
+    string_buffer::FixedBuffer,
+    trait_app::Color,
+    Controls, KeyReleaseEvent,
+};
+
+/// Height of a row of the table, the title included
+const ROW_HEIGHT: i32 = 6;
+/// Letters of the name entry, in the order they are cycled through
+const LETTERS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ ";
+const LETTER_SPACING: i32 = 10;
+
+/// Draws `title` and the table below it. The entry at `highlight` is drawn in the selection colour.
+pub(crate) fn draw_table<D, C>(
+    target: &mut D,
+    title: &str,
+    table: &HighScoreTable,
+    highlight: Option<usize>,
+) where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    let colors = MenuTheme::active().colors();
+    let header_style = MonoTextStyle::new(&FONT_4X6, C::from(colors.header));
+    let text_style = MonoTextStyle::new(&FONT_4X6, C::from(colors.text));
+    let highlight_style = MonoTextStyle::new(&FONT_4X6, C::from(colors.selected));
+    let disabled_style = MonoTextStyle::new(&FONT_4X6, C::from(colors.disabled));
+
+    let _title =
+        Text::with_baseline(title, Point::zero(), header_style, Baseline::Top).draw(target);
+    if table.entries().is_empty() {
+        let _empty = Text::with_baseline(
+            "No scores yet",
+            Point::new(0, 2 * ROW_HEIGHT),
+            disabled_style,
+            Baseline::Top,
+        )
+        .draw(target);
+        return;
+    }
+
+    let mut buffer = FixedBuffer::<32>::new();
+    for (rank, entry) in table.entries().iter().enumerate() {
+        buffer.clear();
+        let _ = write!(buffer, "{} {} {:>7}", rank + 1, entry.name(), entry.score);
+        let style = match highlight == Some(rank) {
+            true => highlight_style,
+            false => text_style,
+        };
+        let position = Point::new(0, (rank as i32 + 1) * ROW_HEIGHT);
+        let _row =
+            Text::with_baseline(buffer.as_str(), position, style, Baseline::Top).draw(target);
+    }
+}
+
+/// Arcade-style entry of three letters. Up/down changes the letter at the cursor, left/right moves
+/// the cursor and A confirms.
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) struct NameEntry {
+    /// Indices into [`LETTERS`]
+    letters: [usize; 3],
+    cursor: usize,
+
+    next_letter_request: KeyReleaseEvent,
+    previous_letter_request: KeyReleaseEvent,
+    left_request: KeyReleaseEvent,
+    right_request: KeyReleaseEvent,
+    confirm_request: KeyReleaseEvent,
+}
+
+impl NameEntry {
+    pub fn new() -> Self {
+        Self {
+            letters: [0; 3],
+            cursor: 0,
+
+            next_letter_request: KeyReleaseEvent::new(),
+            previous_letter_request: KeyReleaseEvent::new(),
+            left_request: KeyReleaseEvent::new(),
+            right_request: KeyReleaseEvent::new(),
+            confirm_request: KeyReleaseEvent::new(),
+        }
+    }
+
+    /// Starts over with the last name entered, so repeated entries are quick.
+    pub fn reset(&mut self) {
+        self.cursor = 0;
+        self.next_letter_request.reset();
+        self.previous_letter_request.reset();
+        self.left_request.reset();
+        self.right_request.reset();
+        self.confirm_request.reset();
+    }
+
+    pub fn name(&self) -> Initials {
+        self.letters.map(|letter| LETTERS[letter])
+    }
+
+    /// Returns the name once it is confirmed and whether anything visible changed.
+    pub fn update(&mut self, controls: &Controls) -> (Option<Initials>, bool) {
+        self.next_letter_request.update(controls.dpad_up);
+        self.previous_letter_request.update(controls.dpad_down);
+        self.left_request.update(controls.dpad_left);
+        self.right_request.update(controls.dpad_right);
+        self.confirm_request.update(controls.buttons_a);
+
+        let letter = &mut self.letters[self.cursor];
+        if self.confirm_request.fired() {
+            return (Some(self.name()), false);
+        } else if self.next_letter_request.fired() {
+            *letter = (*letter + 1) % LETTERS.len();
+        } else if self.previous_letter_request.fired() {
+            *letter = (*letter + LETTERS.len() - 1) % LETTERS.len();
+        } else if self.left_request.fired() {
+            self.cursor = self.cursor.saturating_sub(1);
+        } else if self.right_request.fired() {
+            self.cursor = (self.cursor + 1).min(self.letters.len() - 1);
+        } else {
+            return (None, false);
+        }
+        (None, true)
+    }
+
+    /// Draws the score and the name below it, with the cursor underlined.
+    pub fn render<D, C>(&self, target: &mut D, score: u32)
+    where
+        D: DrawTarget<Color = C>,
+        C: Color,
+    {
+        let colors = MenuTheme::active().colors();
+        let header_style = MonoTextStyle::new(&FONT_4X6, C::from(colors.header));
+        let hint_style = MonoTextStyle::new(&FONT_4X6, C::from(colors.disabled));
+        let letter_style = MonoTextStyle::new(&FONT_6X10, C::from(colors.text));
+        let cursor_style = MonoTextStyle::new(&FONT_6X10, C::from(colors.selected));
+
+        let mut buffer = FixedBuffer::<32>::new();
+        let _ = write!(buffer, "HI-SCORE {}", score);
+        let _score =
+            Text::with_baseline(buffer.as_str(), Point::zero(), header_style, Baseline::Top)
+                .draw(target);
+
+        let x_start = (target.bounding_box().size.width as i32 - 3 * LETTER_SPACING) / 2 + 2;
+        for (i, letter) in self.name().iter().enumerate() {
+            let x = x_start + i as i32 * LETTER_SPACING;
+            let style = match i == self.cursor {
+                true => cursor_style,
+                false => letter_style,
+            };
+            buffer.clear();
+            let _ = write!(buffer, "{}", *letter as char);
+            let _letter =
+                Text::with_baseline(buffer.as_str(), Point::new(x, 9), style, Baseline::Top)
+                    .draw(target);
+            if i == self.cursor {
+                let _cursor = Rectangle::new(Point::new(x, 20), Size::new(6, 1))
+                    .into_styled(PrimitiveStyle::with_fill(C::from(colors.selected)))
+                    .draw(target);
+            }
+        }
+
+        let _hint =
+            Text::with_baseline("A: OK", Point::new(0, 26), hint_style, Baseline::Top).draw(target);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Presses and releases a button. Returns the result of the last update.
+    fn press(entry: &mut NameEntry, button: fn(&mut Controls)) -> (Option<Initials>, bool) {
+        let mut pressed = Controls::default();
+        button(&mut pressed);
+        let _ = entry.update(&Controls::default());
+        let _ = entry.update(&pressed);
+        entry.update(&Controls::default())
+    }
+
+    #[test]
+    fn letters_are_entered_with_the_dpad() {
+        let mut entry = NameEntry::new();
+        assert_eq!(press(&mut entry, |c| c.dpad_down = true), (None, true));
+        let _ = press(&mut entry, |c| c.dpad_right = true);
+        let _ = press(&mut entry, |c| c.dpad_up = true);
+        let _ = press(&mut entry, |c| c.dpad_right = true);
+        let _ = press(&mut entry, |c| c.dpad_right = true);
+        let _ = press(&mut entry, |c| c.dpad_down = true);
+        let _ = press(&mut entry, |c| c.dpad_down = true);
+        assert_eq!(
+            press(&mut entry, |c| c.buttons_a = true),
+            (Some(*b" BZ"), false)
+        );
+    }
+}
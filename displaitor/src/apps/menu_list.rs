@@ -0,0 +1,278 @@
+use core::fmt::Write;
+
+use embedded_graphics::{
+    mono_font::{ascii::FONT_6X10, MonoTextStyle},
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle, Triangle},
+    text::{Baseline, Text},
+};
+
+use crate::{settings::MenuTheme, string_buffer::FixedBuffer, trait_app::Color};
+
+pub(crate) const ROW_HEIGHT: i32 = 11;
+const FONT_WIDTH: i32 = 6;
+const FONT_HEIGHT: i32 = 10;
+const PREFIX_WIDTH: i32 = 2 * FONT_WIDTH;
+const INDICATOR_WIDTH: i32 = 5;
+
+/// Time per pixel of marquee movement
+const MARQUEE_STEP_US: i64 = 50_000;
+/// Steps to wait at the start and the end of a name
+const MARQUEE_PAUSE_STEPS: i64 = 20;
+
+/// How a row of the list is drawn.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum RowKind {
+    Entry,
+    Disabled,
+    Header,
+    Separator,
+}
+
+/// Selection, scrolling and drawing of a vertical list of names, shared by the menus.
+///
+/// Only the rows fitting in the viewport are drawn. The view scrolls, so the selection stays
+/// visible, and names of the selection wider than the viewport scroll horizontally (marquee).
+pub(crate) struct MenuList {
+    pub selected: usize,
+    scroll_offset: usize,
+    wrap_around: bool,
+    viewport: Size,
+    marquee_t_us: i64,
+}
+
+impl MenuList {
+    pub fn new(viewport: Size) -> Self {
+        Self {
+            selected: 0,
+            scroll_offset: 0,
+            wrap_around: true,
+            viewport,
+            marquee_t_us: 0,
+        }
+    }
+
+    pub fn set_wrap_around(&mut self, wrap_around: bool) {
+        self.wrap_around = wrap_around;
+    }
+
+    pub fn viewport(&self) -> Size {
+        self.viewport
+    }
+
+    pub fn set_viewport(&mut self, viewport: Size) {
+        self.viewport = viewport;
+        self.scroll_offset = 0;
+    }
+
+    pub fn reset(&mut self) {
+        self.selected = 0;
+        self.scroll_offset = 0;
+        self.marquee_t_us = 0;
+    }
+
+    /// Number of rows, which fit completely into the viewport.
+    pub fn visible_rows(&self) -> usize {
+        ((self.viewport.height as i32 - FONT_HEIGHT) / ROW_HEIGHT + 1).max(1) as usize
+    }
+
+    pub fn scroll_offset(&self) -> usize {
+        self.scroll_offset
+    }
+
+    pub fn select(&mut self, index: usize, len: usize) {
+        self.selected = index;
+        self.marquee_t_us = 0;
+        self.scroll_to_selection(len);
+    }
+
+    pub fn select_next(&mut self, len: usize) {
+        let next = if self.selected + 1 < len {
+            self.selected + 1
+        } else if self.wrap_around {
+            0
+        } else {
+            self.selected
+        };
+        self.select(next, len);
+    }
+
+    pub fn select_previous(&mut self, len: usize) {
+        let previous = if self.selected > 0 {
+            self.selected - 1
+        } else if self.wrap_around {
+            len.saturating_sub(1)
+        } else {
+            self.selected
+        };
+        self.select(previous, len);
+    }
+
+    fn scroll_to_selection(&mut self, len: usize) {
+        let rows = self.visible_rows();
+        if self.selected < self.scroll_offset {
+            self.scroll_offset = self.selected;
+        } else if self.selected >= self.scroll_offset + rows {
+            self.scroll_offset = self.selected + 1 - rows;
+        }
+        self.scroll_offset = self.scroll_offset.min(len.saturating_sub(rows));
+    }
+
+    /// Advances the marquee of the selected entry. Returns `true`, if it moved.
+    pub fn update(&mut self, dt_us: i64, selected_name: &str, len: usize) -> bool {
+        let before = self.marquee_offset(selected_name, len);
+        self.marquee_t_us += dt_us;
+        before != self.marquee_offset(selected_name, len)
+    }
+
+    /// Screen area of the entry at `index`. It might be outside of the viewport.
+    pub fn row_area(&self, index: usize) -> Rectangle {
+        let row = index as i32 - self.scroll_offset as i32;
+        Rectangle::new(
+            Point::new(0, row * ROW_HEIGHT),
+            Size::new(self.viewport.width, ROW_HEIGHT as u32),
+        )
+    }
+
+    fn scrollable(&self, len: usize) -> bool {
+        len > self.visible_rows()
+    }
+
+    fn name_width(&self, len: usize) -> i32 {
+        let indicator = if self.scrollable(len) { INDICATOR_WIDTH } else { 0 };
+        self.viewport.width as i32 - PREFIX_WIDTH - indicator
+    }
+
+    fn marquee_offset(&self, name: &str, len: usize) -> i32 {
+        let overflow = (name.len() as i32 * FONT_WIDTH - self.name_width(len)) as i64;
+        if overflow <= 0 {
+            return 0;
+        }
+        let step = (self.marquee_t_us / MARQUEE_STEP_US) % (overflow + 2 * MARQUEE_PAUSE_STEPS);
+        (step - MARQUEE_PAUSE_STEPS).clamp(0, overflow) as i32
+    }
+
+    /// Draws the `len` rows, without collecting them first.
+    pub fn render<'a, D, C>(
+        &self,
+        target: &mut D,
+        rows: impl Iterator<Item = (&'a str, RowKind)>,
+        len: usize,
+    ) where
+        D: DrawTarget<Color = C>,
+        C: Color,
+    {
+        let colors = MenuTheme::active().colors();
+        let text_style = MonoTextStyle::new(&FONT_6X10, C::from(colors.text));
+        let text_style_active = MonoTextStyle::new(&FONT_6X10, C::from(colors.selected));
+        let text_style_disabled = MonoTextStyle::new(&FONT_6X10, C::from(colors.disabled));
+        let text_style_header = MonoTextStyle::new(&FONT_6X10, C::from(colors.header));
+        let name_width = self.name_width(len);
+        let mut buffer = FixedBuffer::<32>::new();
+
+        let visible = rows
+            .enumerate()
+            .skip(self.scroll_offset)
+            .take(self.visible_rows());
+        for (i, (name, kind)) in visible {
+            let y_offset = self.row_area(i).top_left.y;
+            let (prefix, style, marquee_offset) = match kind {
+                RowKind::Separator => {
+                    let y = y_offset + ROW_HEIGHT / 2;
+                    let _line = Rectangle::new(
+                        Point::new(FONT_WIDTH, y),
+                        Size::new(self.viewport.width.saturating_sub(2 * FONT_WIDTH as u32), 1),
+                    )
+                    .into_styled(PrimitiveStyle::with_fill(C::from(colors.disabled)))
+                    .draw(target);
+                    continue;
+                }
+                RowKind::Header => ("", text_style_header, 0),
+                _ if i == self.selected => {
+                    ("> ", text_style_active, self.marquee_offset(name, len))
+                }
+                RowKind::Disabled => ("  ", text_style_disabled, 0),
+                RowKind::Entry => ("  ", text_style, 0),
+            };
+            // Headers start at the very left, entries after the prefix
+            let name_x = if prefix.is_empty() { 0 } else { PREFIX_WIDTH };
+
+            let _prefix = Text::with_baseline(prefix, Point::new(0, y_offset), style, Baseline::Top)
+                .draw(target);
+
+            buffer.clear();
+            let _ = write!(buffer, "{}", name);
+            let name_area = Rectangle::new(
+                Point::new(name_x, y_offset),
+                Size::new((name_width + PREFIX_WIDTH - name_x).max(0) as u32, ROW_HEIGHT as u32),
+            );
+            let _entry_text = Text::with_baseline(
+                buffer.as_str(),
+                Point::new(name_x - marquee_offset, y_offset),
+                style,
+                Baseline::Top,
+            )
+            .draw(&mut target.clipped(&name_area));
+        }
+
+        // Scroll indicators
+        if self.scrollable(len) {
+            let style = PrimitiveStyle::with_fill(C::from(colors.indicator));
+            let x = self.viewport.width as i32 - INDICATOR_WIDTH;
+            if self.scroll_offset > 0 {
+                let _up = Triangle::new(Point::new(x, 3), Point::new(x + 4, 3), Point::new(x + 2, 1))
+                    .into_styled(style)
+                    .draw(target);
+            }
+            if self.scroll_offset + self.visible_rows() < len {
+                let y = self.viewport.height as i32 - 2;
+                let _down = Triangle::new(
+                    Point::new(x, y - 2),
+                    Point::new(x + 4, y - 2),
+                    Point::new(x + 2, y),
+                )
+                .into_styled(style)
+                .draw(target);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selection_stays_visible() {
+        let mut list = MenuList::new(Size::new(64, 32));
+        assert_eq!(list.visible_rows(), 3);
+
+        for _ in 0..4 {
+            list.select_next(5);
+        }
+        assert_eq!((list.selected, list.scroll_offset()), (4, 2));
+
+        list.select_next(5);
+        assert_eq!((list.selected, list.scroll_offset()), (0, 0));
+        list.select_previous(5);
+        assert_eq!((list.selected, list.scroll_offset()), (4, 2));
+
+        list.set_wrap_around(false);
+        list.select_next(5);
+        assert_eq!(list.selected, 4);
+    }
+
+    #[test]
+    fn marquee_only_for_long_names() {
+        let mut list = MenuList::new(Size::new(64, 32));
+        assert!(!list.update(10_000_000, "Pong", 2));
+
+        let long = "A really long name";
+        let mut moved = false;
+        for _ in 0..40 {
+            moved |= list.update(MARQUEE_STEP_US, long, 2);
+        }
+        assert!(moved);
+        assert!(list.marquee_offset(long, 2) > 0);
+    }
+}
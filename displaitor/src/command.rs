@@ -0,0 +1,71 @@
+use alloc::boxed::Box;
+
+use crate::{AudioID, Navigation, Setting, Toast};
+
+/// Maximum number of commands a single `UpdateResult` can carry. Further commands are dropped.
+pub const MAX_COMMANDS: usize = 8;
+/// Maximum size of an encoded record, e.g. of the settings or a high score table.
+pub const PERSIST_VALUE_MAX: usize = 48;
+
+pub type Commands = heapless::Vec<Command, MAX_COMMANDS>;
+pub type PersistValue = heapless::Vec<u8, PERSIST_VALUE_MAX>;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, defmt::Format)]
+pub enum AudioChannel {
+    /// Looping background music
+    Music,
+    /// Short sound effects
+    Sfx,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, defmt::Format)]
+pub enum AudioCommand {
+    Play { channel: AudioChannel, id: AudioID },
+    Stop { channel: AudioChannel },
+    /// Volume from 0 (mute) to 255 (full)
+    Volume { channel: AudioChannel, level: u8 },
+}
+
+/// Side effect requested by an app. Commands are collected in the `UpdateResult` and bubble up
+/// through the containers to the top-level loop, which executes the ones it supports. Containers
+/// may consume commands on the way (e.g. the `Navigator` handles `Navigate`).
+#[derive(Clone, PartialEq, Debug)]
+pub enum Command {
+    Audio(AudioCommand),
+    Navigate(Navigation),
+    /// Brightness from 0 (off) to 255 (full)
+    Brightness(u8),
+    /// Store `value` under `key`, so it survives a reset. Boxed, so the rarely sent value doesn't
+    /// make every command as large.
+    Persist { key: u16, value: Box<[u8]> },
+    /// Short message for the user, shown as a [`Toast`] with the info icon
+    Notify(&'static str),
+    /// Shows a toast over the running app
+    Toast(Toast),
+    /// Change a single setting, e.g. brightness, volume or theme. Sent by the settings app.
+    Setting(Setting),
+    /// Persist the current settings of the top-level loop.
+    SaveSettings,
+}
+
+impl Command {
+    pub fn play_music(id: AudioID) -> Self {
+        Command::Audio(AudioCommand::Play {
+            channel: AudioChannel::Music,
+            id,
+        })
+    }
+
+    pub fn play_sfx(id: AudioID) -> Self {
+        Command::Audio(AudioCommand::Play {
+            channel: AudioChannel::Sfx,
+            id,
+        })
+    }
+
+    pub fn stop_music() -> Self {
+        Command::Audio(AudioCommand::Stop {
+            channel: AudioChannel::Music,
+        })
+    }
+}
@@ -1,4 +1,5 @@
 // TODO: init with pins and then provide .update()
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
 pub struct Controls {
     pub buttons_a: bool,
     pub buttons_b: bool,
@@ -0,0 +1,150 @@
+use core::fmt::Write;
+
+use embedded_graphics::{
+    mono_font::{ascii::FONT_4X6, MonoTextStyle},
+    prelude::*,
+    primitives::Rectangle,
+    text::{Baseline, Text},
+};
+
+use crate::{
+    string_buffer::FixedBuffer, trait_app::Color, AudioID, Controls, FrameTimes, HeapUsage,
+    Settings, Stats,
+};
+
+const CHAR_WIDTH: i32 = 4;
+const LINE_HEIGHT: i32 = 6;
+const LINES: i32 = 5;
+/// Widest line, longer ones are cut off
+const MAX_CHARS: i32 = 16;
+
+/// Internal state of the runtime, drawn as tiny text over the active app. Only compiled with the
+/// `debug-overlay` feature.
+///
+/// The lines are: renders per second and frame time, heap usage, name of the active app, volume
+/// and the sound playing, and the buttons, which are lit while pressed.
+pub struct DebugOverlay<'a> {
+    /// Counters of the last full period
+    pub stats: Stats,
+    pub frame: FrameTimes,
+    pub heap: Option<HeapUsage>,
+    pub app: Option<&'a str>,
+    pub playing: Option<AudioID>,
+    pub settings: &'a Settings,
+    pub controls: Controls,
+}
+
+impl DebugOverlay<'_> {
+    /// Where the overlay is drawn to on a screen of `size`: the top left corner
+    pub fn area(size: Size) -> Rectangle {
+        let size = Size::new(
+            ((MAX_CHARS * CHAR_WIDTH) as u32).min(size.width),
+            ((LINES * LINE_HEIGHT) as u32).min(size.height),
+        );
+        Rectangle::new(Point::zero(), size)
+    }
+
+    pub fn draw<D, C>(&self, target: &mut D)
+    where
+        D: DrawTarget<Color = C>,
+        C: Color,
+    {
+        let area = Self::area(target.bounding_box().size);
+        let _ = target.fill_solid(&area, C::BLACK);
+        let mut target = target.clipped(&area);
+        let style = MonoTextStyle::new(&FONT_4X6, C::WHITE);
+
+        let mut lines = [(); LINES as usize - 1].map(|_| FixedBuffer::<32>::new());
+        let frame_us = self.frame.total_us();
+        let _ = write!(
+            lines[0],
+            "{}fps {}.{}ms",
+            self.stats.renders_per_s(),
+            frame_us / 1000,
+            frame_us % 1000 / 100
+        );
+        let _ = match self.heap {
+            Some(heap) => write!(lines[1], "heap {}/{}", heap.used, heap.used + heap.free),
+            None => write!(lines[1], "heap ?"),
+        };
+        let _ = write!(lines[2], "{}", self.app.unwrap_or("-"));
+        let _ = match (self.settings.audio_enabled, self.playing) {
+            (false, _) => write!(lines[3], "muted"),
+            (true, Some(id)) => write!(lines[3], "v{} {:?}", self.settings.volume, id),
+            (true, None) => write!(lines[3], "v{} -", self.settings.volume),
+        };
+        for (i, line) in lines.iter().enumerate() {
+            let position = Point::new(0, i as i32 * LINE_HEIGHT);
+            let _ = Text::with_baseline(line.as_str(), position, style, Baseline::Top)
+                .draw(&mut target);
+        }
+
+        let controls = &self.controls;
+        let buttons = [
+            ("U", controls.dpad_up),
+            ("D", controls.dpad_down),
+            ("L", controls.dpad_left),
+            ("R", controls.dpad_right),
+            ("A", controls.buttons_a),
+            ("B", controls.buttons_b),
+            ("S", controls.buttons_s),
+        ];
+        for (i, (label, pressed)) in buttons.into_iter().enumerate() {
+            let color = match pressed {
+                true => C::CSS_LIME,
+                false => C::CSS_DIM_GRAY,
+            };
+            let position = Point::new(i as i32 * CHAR_WIDTH, (LINES - 1) * LINE_HEIGHT);
+            let _ = Text::with_baseline(
+                label,
+                position,
+                MonoTextStyle::new(&FONT_4X6, color),
+                Baseline::Top,
+            )
+            .draw(&mut target);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::pixelcolor::Rgb565;
+
+    use super::*;
+    use crate::FrameBuffer;
+
+    #[test]
+    fn draws_into_its_area() {
+        let settings = Settings::default();
+        let overlay = DebugOverlay {
+            stats: Stats::default(),
+            frame: FrameTimes::default(),
+            heap: None,
+            app: Some("pong"),
+            playing: Some(AudioID::Ping),
+            settings: &settings,
+            controls: Controls {
+                buttons_a: true,
+                ..Default::default()
+            },
+        };
+        let mut frame = FrameBuffer::<64, 32, Rgb565>::new();
+        let _ = frame.clear(Rgb565::BLUE);
+        overlay.draw(&mut frame);
+
+        let area = DebugOverlay::area(Size::new(64, 32));
+        assert_eq!(area.size, Size::new(64, 30));
+        let lit = |left: i32, top: i32, width: u32, height: u32, color: Rgb565| {
+            Rectangle::new(Point::new(left, top), Size::new(width, height))
+                .points()
+                .filter(|point| frame.pixel(*point) == Some(color))
+                .count()
+        };
+        // Nothing is drawn below the overlay
+        assert_eq!(lit(0, 30, 64, 2, Rgb565::BLUE), 64 * 2);
+        // Only A is pressed
+        assert!(lit(16, 24, 4, 6, Rgb565::CSS_LIME) > 0);
+        assert_eq!(lit(0, 24, 16, 6, Rgb565::CSS_LIME), 0);
+        assert_eq!(lit(20, 24, 8, 6, Rgb565::CSS_LIME), 0);
+    }
+}
@@ -0,0 +1,163 @@
+use embedded_graphics::{
+    pixelcolor::Rgb888,
+    prelude::*,
+    primitives::Rectangle,
+};
+
+use crate::{trait_app::Color, Capture, TransitionFrame};
+
+/// Wraps a target and scales the colour of everything drawn to it by a brightness.
+///
+/// Used by the top-level loops to apply the brightness setting to all apps without touching them.
+/// Captured views aren't dimmed, only what a transition draws of them.
+pub struct Dimmed<D> {
+    target: D,
+    /// From 0 (off) to 255 (unchanged)
+    brightness: u8,
+    /// Drawing is redirected into a frame, see [`Capture`]
+    capturing: bool,
+}
+
+impl<D> Dimmed<D> {
+    pub fn new(target: D) -> Self {
+        Self {
+            target,
+            brightness: 255,
+            capturing: false,
+        }
+    }
+
+    pub fn brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    /// Only affects what is drawn afterwards.
+    pub fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness;
+    }
+
+    pub fn inner(&self) -> &D {
+        &self.target
+    }
+
+    pub fn inner_mut(&mut self) -> &mut D {
+        &mut self.target
+    }
+
+    pub fn into_inner(self) -> D {
+        self.target
+    }
+
+    /// Brightness applied to what is drawn
+    fn applied(&self) -> u8 {
+        match self.capturing {
+            true => 255,
+            false => self.brightness,
+        }
+    }
+}
+
+fn dim<C: Color>(color: C, brightness: u8) -> C {
+    if brightness == 255 {
+        return color;
+    }
+    // Scales to 8 bit and by the brightness in one step
+    let scale = |value: u8, max: u8| (value as u32 * brightness as u32 / max as u32) as u8;
+    C::from(Rgb888::new(
+        scale(color.r(), C::MAX_R),
+        scale(color.g(), C::MAX_G),
+        scale(color.b(), C::MAX_B),
+    ))
+}
+
+impl<D: Dimensions> Dimensions for Dimmed<D> {
+    fn bounding_box(&self) -> Rectangle {
+        self.target.bounding_box()
+    }
+}
+
+impl<D, C> DrawTarget for Dimmed<D>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    type Color = C;
+    type Error = D::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let brightness = self.applied();
+        self.target.draw_iter(
+            pixels
+                .into_iter()
+                .map(|Pixel(point, color)| Pixel(point, dim(color, brightness))),
+        )
+    }
+
+    fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Self::Color>,
+    {
+        let brightness = self.applied();
+        self.target.fill_contiguous(
+            area,
+            colors.into_iter().map(|color| dim(color, brightness)),
+        )
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        self.target.fill_solid(area, dim(color, self.applied()))
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.target.clear(dim(color, self.applied()))
+    }
+}
+
+impl<D, C> Capture for Dimmed<D>
+where
+    D: Capture<Color = C>,
+    C: Color,
+{
+    fn redirect(&mut self, frame: Option<TransitionFrame<C>>) -> Option<TransitionFrame<C>> {
+        self.capturing = frame.is_some();
+        self.target.redirect(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::{mock_display::MockDisplay, pixelcolor::Rgb565};
+
+    use super::*;
+
+    #[test]
+    fn scales_colors() {
+        assert_eq!(dim(Rgb565::WHITE, 255), Rgb565::WHITE);
+        assert_eq!(dim(Rgb565::WHITE, 0), Rgb565::BLACK);
+        let half = dim(Rgb565::new(31, 63, 0), 128);
+        assert_eq!((half.r(), half.g(), half.b()), (16, 32, 0));
+
+        let mut display = Dimmed::new(MockDisplay::<Rgb565>::new());
+        display.set_brightness(0);
+        Pixel(Point::new(1, 1), Rgb565::RED).draw(&mut display).unwrap();
+        assert_eq!(display.inner().get_pixel(Point::new(1, 1)), Some(Rgb565::BLACK));
+    }
+
+    #[test]
+    fn captures_undimmed() {
+        let layout = crate::PanelLayout::single(Size::new(8, 4));
+        let panels = crate::Panels::new(crate::FrameBuffer::<8, 4, Rgb565>::new(), layout);
+        let mut display = Dimmed::new(panels);
+        display.set_brightness(0);
+
+        let frame = TransitionFrame::try_new(Size::new(8, 4)).unwrap();
+        assert!(display.redirect(Some(frame)).is_none());
+        display.clear(Rgb565::RED).unwrap();
+        let frame = display.redirect(None).unwrap();
+        assert_eq!(frame.pixel(Point::new(7, 3)), Some(Rgb565::RED));
+        assert_eq!(display.inner().inner().pixel(Point::new(7, 3)), Some(Rgb565::BLACK));
+    }
+}
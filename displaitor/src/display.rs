@@ -0,0 +1,45 @@
+use embedded_graphics::{prelude::*, primitives::Rectangle};
+
+/// Resolution of the panel the apps are drawn to.
+///
+/// Apps, which need the size outside of `render` (game logic, dirty regions), get it when they are
+/// built. The others lay themselves out relative to `DrawTarget::bounding_box`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DisplayInfo {
+    pub size: Size,
+}
+
+impl DisplayInfo {
+    /// A single HUB75 panel as used by the badge
+    pub const PANEL_64X32: Self = Self::new(64, 32);
+    pub const PANEL_64X64: Self = Self::new(64, 64);
+    pub const PANEL_128X32: Self = Self::new(128, 32);
+
+    pub const fn new(width: u32, height: u32) -> Self {
+        Self {
+            size: Size::new(width, height),
+        }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.size.width as i32
+    }
+
+    pub fn height(&self) -> i32 {
+        self.size.height as i32
+    }
+
+    pub fn bounding_box(&self) -> Rectangle {
+        Rectangle::new(Point::zero(), self.size)
+    }
+
+    pub fn center(&self) -> Point {
+        self.bounding_box().center()
+    }
+}
+
+impl Default for DisplayInfo {
+    fn default() -> Self {
+        Self::PANEL_64X32
+    }
+}
@@ -0,0 +1,97 @@
+use alloc::boxed::Box;
+
+use embedded_graphics::prelude::*;
+use tinyqoi::Qoi;
+
+use crate::{
+    trait_app::{AppBoxed, Color},
+    App,
+};
+
+/// Why an app couldn't be built.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, defmt::Format)]
+pub enum AppError {
+    /// An asset isn't valid, e.g. an image which isn't QOI
+    Decode,
+    /// An asset has a size the app can't show, e.g. frames of an animation which differ in size
+    UnsupportedSize,
+    /// The heap has no room for the app
+    OutOfMemory,
+}
+
+impl AppError {
+    /// Short enough for a line on the panel
+    pub fn message(&self) -> &'static str {
+        match self {
+            AppError::Decode => "bad asset",
+            AppError::UnsupportedSize => "bad size",
+            AppError::OutOfMemory => "no memory",
+        }
+    }
+}
+
+/// Moves `app` to the heap, without aborting if there is no room for it.
+pub fn try_box<A, D, C>(app: A) -> Result<AppBoxed<D, C>, AppError>
+where
+    A: App<Target = D, Color = C> + 'static,
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    match Box::try_new(app) {
+        Ok(app) => Ok(app),
+        Err(_) => Err(AppError::OutOfMemory),
+    }
+}
+
+/// Decodes the header of a QOI image, which must not be empty.
+pub(crate) fn decode_qoi(data: &'static [u8]) -> Result<Qoi<'static>, AppError> {
+    let image = Qoi::new(data).map_err(|_| AppError::Decode)?;
+    match image.size().width == 0 || image.size().height == 0 {
+        true => Err(AppError::UnsupportedSize),
+        false => Ok(image),
+    }
+}
+
+/// Decodes the frames of an animation, which all have to be of the same size.
+pub(crate) fn decode_frames<const N: usize>(
+    data: [&'static [u8]; N],
+) -> Result<[Qoi<'static>; N], AppError> {
+    let frames = data.map(decode_qoi);
+    if let Some(error) = frames.iter().find_map(|frame| frame.as_ref().err()) {
+        return Err(*error);
+    }
+    let frames = frames.map(|frame| frame.expect("all frames are decoded"));
+    match frames
+        .windows(2)
+        .all(|pair| pair[0].size() == pair[1].size())
+    {
+        true => Ok(frames),
+        false => Err(AppError::UnsupportedSize),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Header of a QOI image without any pixels
+    fn qoi(width: u32, height: u32) -> &'static [u8] {
+        let mut data = alloc::vec::Vec::from(*b"qoif");
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&[3, 0]);
+        data.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+        data.leak()
+    }
+
+    #[test]
+    fn rejects_invalid_frames() {
+        assert_eq!(decode_qoi(b"not an image").err(), Some(AppError::Decode));
+        assert_eq!(decode_qoi(qoi(0, 8)).err(), Some(AppError::UnsupportedSize));
+        assert!(decode_frames([qoi(8, 8), qoi(8, 8)]).is_ok());
+        assert_eq!(
+            decode_frames([qoi(8, 8), qoi(8, 4)]).err(),
+            Some(AppError::UnsupportedSize)
+        );
+    }
+}
@@ -0,0 +1,293 @@
+use alloc::{vec, vec::Vec};
+use core::convert::Infallible;
+
+use embedded_graphics::{pixelcolor::Rgb888, prelude::*, primitives::Rectangle};
+
+use crate::renderer::DirtyRegions;
+
+/// A `W`x`H` frame in RAM, which can be drawn to like a display.
+///
+/// Frames can be copied onto another target, compared with each other and exported, so they are
+/// the base for composing views off-screen and for snapshot tests. The pixels are stored row by
+/// row on the heap, fills write whole row slices at once.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FrameBuffer<const W: usize, const H: usize, C> {
+    pixels: Vec<C>,
+}
+
+impl<const W: usize, const H: usize, C> FrameBuffer<W, H, C>
+where
+    C: PixelColor + RgbColor,
+{
+    const SIZE: Size = Size::new(W as u32, H as u32);
+
+    /// A black frame.
+    pub fn new() -> Self {
+        Self::filled(C::BLACK)
+    }
+
+    pub fn filled(color: C) -> Self {
+        Self {
+            pixels: vec![color; W * H],
+        }
+    }
+
+    /// `None` outside of the frame.
+    pub fn pixel(&self, point: Point) -> Option<C> {
+        index::<W, H>(point).map(|index| self.pixels[index])
+    }
+
+    /// Does nothing outside of the frame.
+    pub fn set_pixel(&mut self, point: Point, color: C) {
+        if let Some(index) = index::<W, H>(point) {
+            self.pixels[index] = color;
+        }
+    }
+
+    /// All pixels, row by row.
+    pub fn pixels(&self) -> &[C] {
+        &self.pixels
+    }
+
+    pub fn row(&self, y: usize) -> &[C] {
+        &self.pixels[y * W..(y + 1) * W]
+    }
+
+    /// Draws the whole frame onto `target` at `top_left`.
+    pub fn blit<D>(&self, target: &mut D, top_left: Point) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = C>,
+    {
+        let area = Rectangle::new(top_left, Self::SIZE);
+        target.fill_contiguous(&area, self.pixels.iter().copied())
+    }
+
+    /// Draws `area` of the frame onto the same area of `target`.
+    pub fn blit_region<D>(&self, target: &mut D, area: &Rectangle) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = C>,
+    {
+        let area = area.intersection(&self.bounding_box());
+        let colors = area.rows().flat_map(|y| {
+            let row = self.row(y as usize);
+            area.columns().map(move |x| row[x as usize])
+        });
+        target.fill_contiguous(&area, colors)
+    }
+
+    /// Areas in which `other` differs from this frame. Changed rows next to each other are
+    /// combined into one region spanning their changed columns.
+    pub fn diff(&self, other: &Self) -> DirtyRegions {
+        let mut regions = DirtyRegions::new();
+        // Rows and columns of the band of changed rows being collected
+        let mut band: Option<(usize, usize, usize, usize)> = None;
+        for y in 0..H {
+            let (row, other_row) = (self.row(y), other.row(y));
+            let first = row.iter().zip(other_row).position(|(a, b)| a != b);
+            let last = row.iter().zip(other_row).rposition(|(a, b)| a != b);
+            band = match (band, first.zip(last)) {
+                (None, None) => None,
+                (None, Some((first, last))) => Some((y, y, first, last)),
+                (Some((top, _, left, right)), Some((first, last))) => {
+                    Some((top, y, left.min(first), right.max(last)))
+                }
+                (Some(band), None) => {
+                    regions.add(band_area(band));
+                    None
+                }
+            };
+        }
+        if let Some(band) = band {
+            regions.add(band_area(band));
+        }
+        regions
+    }
+
+    /// The frame as 8 bit RGB triplets, row by row, e.g. for image files or a host display.
+    pub fn to_rgb888_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(W * H * 3);
+        for color in &self.pixels {
+            let color = to_rgb888(*color);
+            bytes.extend_from_slice(&[color.r(), color.g(), color.b()]);
+        }
+        bytes
+    }
+}
+
+impl<const W: usize, const H: usize, C> Default for FrameBuffer<W, H, C>
+where
+    C: PixelColor + RgbColor,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const W: usize, const H: usize, C: PixelColor> OriginDimensions for FrameBuffer<W, H, C> {
+    fn size(&self) -> Size {
+        Size::new(W as u32, H as u32)
+    }
+}
+
+impl<const W: usize, const H: usize, C> DrawTarget for FrameBuffer<W, H, C>
+where
+    C: PixelColor + RgbColor,
+{
+    type Color = C;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            self.set_pixel(point, color);
+        }
+        Ok(())
+    }
+
+    fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Self::Color>,
+    {
+        let clipped = area.intersection(&self.bounding_box());
+        if clipped != *area {
+            // Only partly visible, the colours of the hidden pixels have to be skipped.
+            return self.draw_iter(
+                area.points()
+                    .zip(colors)
+                    .map(|(point, color)| Pixel(point, color)),
+            );
+        }
+
+        let mut colors = colors.into_iter();
+        let (left, width) = (area.top_left.x as usize, area.size.width as usize);
+        for y in area.rows() {
+            let start = y as usize * W + left;
+            for (pixel, color) in self.pixels[start..start + width]
+                .iter_mut()
+                .zip(&mut colors)
+            {
+                *pixel = color;
+            }
+        }
+        Ok(())
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        let area = area.intersection(&self.bounding_box());
+        let (left, width) = (area.top_left.x as usize, area.size.width as usize);
+        for y in area.rows() {
+            let start = y as usize * W + left;
+            self.pixels[start..start + width].fill(color);
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.pixels.fill(color);
+        Ok(())
+    }
+}
+
+fn index<const W: usize, const H: usize>(point: Point) -> Option<usize> {
+    let (x, y) = (
+        usize::try_from(point.x).ok()?,
+        usize::try_from(point.y).ok()?,
+    );
+    (x < W && y < H).then_some(y * W + x)
+}
+
+/// Rows `top..=bottom` and columns `left..=right`
+fn band_area((top, bottom, left, right): (usize, usize, usize, usize)) -> Rectangle {
+    Rectangle::with_corners(
+        Point::new(left as i32, top as i32),
+        Point::new(right as i32, bottom as i32),
+    )
+}
+
+/// Scales the channels of `color` to 8 bit.
+fn to_rgb888<C: RgbColor>(color: C) -> Rgb888 {
+    let scale = |value: u8, max: u8| (value as u32 * 255 / max as u32) as u8;
+    Rgb888::new(
+        scale(color.r(), C::MAX_R),
+        scale(color.g(), C::MAX_G),
+        scale(color.b(), C::MAX_B),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::{
+        mock_display::MockDisplay,
+        pixelcolor::Rgb565,
+        primitives::{Line, PrimitiveStyle},
+    };
+
+    use super::*;
+
+    type Frame = FrameBuffer<8, 4, Rgb565>;
+
+    #[test]
+    fn fills_are_clipped_to_the_frame() {
+        let mut frame = Frame::new();
+        let _ = frame.fill_solid(
+            &Rectangle::new(Point::new(6, -1), Size::new(4, 3)),
+            Rgb565::RED,
+        );
+        let colors = [Rgb565::GREEN, Rgb565::BLUE].into_iter().cycle();
+        let _ = frame.fill_contiguous(&Rectangle::new(Point::new(-1, 2), Size::new(2, 2)), colors);
+
+        assert_eq!(frame.pixel(Point::new(7, 1)), Some(Rgb565::RED));
+        assert_eq!(frame.pixel(Point::new(5, 1)), Some(Rgb565::BLACK));
+        assert_eq!(frame.pixel(Point::new(7, 2)), Some(Rgb565::BLACK));
+        assert_eq!(frame.pixel(Point::new(0, 2)), Some(Rgb565::BLUE));
+        assert_eq!(frame.pixel(Point::new(0, 3)), Some(Rgb565::BLUE));
+        assert_eq!(frame.pixel(Point::new(8, 0)), None);
+    }
+
+    #[test]
+    fn blits_what_was_drawn() {
+        let mut frame = Frame::new();
+        let _ = Line::new(Point::new(0, 0), Point::new(7, 3))
+            .into_styled(PrimitiveStyle::with_stroke(Rgb565::WHITE, 1))
+            .draw(&mut frame);
+
+        let mut display = MockDisplay::new();
+        frame.blit(&mut display, Point::zero()).unwrap();
+        for point in frame.bounding_box().points() {
+            assert_eq!(display.get_pixel(point), frame.pixel(point));
+        }
+
+        let mut region = MockDisplay::new();
+        let area = Rectangle::new(Point::new(2, 1), Size::new(3, 2));
+        frame.blit_region(&mut region, &area).unwrap();
+        assert_eq!(region.affected_area(), area);
+    }
+
+    #[test]
+    fn diff_finds_changed_bands() {
+        let frame = Frame::new();
+        let mut changed = frame.clone();
+        assert!(frame.diff(&changed).is_empty());
+
+        changed.set_pixel(Point::new(1, 0), Rgb565::RED);
+        changed.set_pixel(Point::new(4, 1), Rgb565::RED);
+        changed.set_pixel(Point::new(6, 3), Rgb565::RED);
+        let regions: Vec<Rectangle> = frame.diff(&changed).iter().copied().collect();
+        assert_eq!(
+            regions,
+            [
+                Rectangle::with_corners(Point::new(1, 0), Point::new(4, 1)),
+                Rectangle::new(Point::new(6, 3), Size::new(1, 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn exports_rgb888() {
+        let mut frame = FrameBuffer::<2, 1, Rgb565>::new();
+        frame.set_pixel(Point::new(1, 0), Rgb565::new(31, 0, 16));
+        assert_eq!(frame.to_rgb888_bytes(), [0, 0, 0, 255, 0, 131]);
+    }
+}
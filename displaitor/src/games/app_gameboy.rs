@@ -1 +1,7 @@
+use crate::registry::{AppInfo, AppMeta, Category};
+
 pub struct GameBoy {}
+
+impl AppMeta for GameBoy {
+    const INFO: AppInfo = AppInfo::new("gameboy", "GameBoy", Category::Games).with_audio();
+}
@@ -7,11 +7,20 @@ use embedded_graphics::prelude::*;
 use embedded_graphics::primitives::{PrimitiveStyle, Rectangle};
 use embedded_graphics::text::Text;
 
+use super::game_over::GameOver;
+use crate::display::DisplayInfo;
+use crate::high_scores::HighScores;
+use crate::registry::{AppInfo, AppMeta, Category};
 use crate::string_buffer::FixedBuffer;
 use crate::trait_app::{Color, RenderStatus, UpdateResult};
-use crate::{string_buffer, App, AudioID, Controls, KeyReleaseEvent};
+use crate::{string_buffer, App, AudioID, Command, Controls, KeyReleaseEvent, Pacing};
+
+/// Time between two steps of the game, 20 ms
+const TICK_US: i64 = 20_000;
+
+/// The game ends, when the 'AI' reached this many points. The points of the player are the score.
+const POINTS_TO_LOSE: i32 = 5;
 
-// TODO: Make screen size a parameter of the App struct.
 #[derive(Clone, PartialEq, Debug)]
 pub struct Pong<D, C>
 where
@@ -30,8 +39,8 @@ where
     score1: i32,
     score2: i32,
 
-    last_update: i64,
     dead: bool,
+    game_over: GameOver,
     close_request: KeyReleaseEvent,
 
     _marker: PhantomData<D>,
@@ -42,27 +51,59 @@ where
     D: DrawTarget<Color = C>,
     C: PixelColor + RgbColor,
 {
-    pub fn new(screen_width: u32, screen_height: u32) -> Self {
+    /// The court covers the whole display.
+    pub fn new(display: DisplayInfo) -> Self {
+        let paddle_height = 10;
         Self {
-            ball_pos: Point::new(screen_width as i32 / 2, screen_height as i32 / 2),
+            ball_pos: Point::new(display.width() / 2, display.height() / 2),
             ball_velocity: Point::new(1, 1),
-            paddle1_pos: screen_height as i32 / 2 - 20,
-            paddle2_pos: screen_height as i32 / 2 - 20,
-            paddle_height: 10,
+            paddle1_pos: (display.height() - paddle_height) / 2,
+            paddle2_pos: (display.height() - paddle_height) / 2,
+            paddle_height,
             paddle_width: 3,
             ball_size: 3,
-            screen_width: screen_width as i32,
-            screen_height: screen_height as i32,
+            screen_width: display.width(),
+            screen_height: display.height(),
             score1: 0,
             score2: 0,
 
-            last_update: 0,
             dead: false,
+            game_over: GameOver::new(Self::INFO),
             close_request: KeyReleaseEvent::new(),
 
             _marker: Default::default(),
         }
     }
+
+    /// Enters the points of the player into the shared high score table.
+    pub fn with_high_scores(mut self, high_scores: HighScores) -> Self {
+        self.game_over.set_high_scores(high_scores);
+        self
+    }
+
+    /// Starts a new match after the game over screen.
+    fn restart(&mut self) {
+        self.ball_pos = self.center();
+        self.ball_velocity = Point::new(1, 1);
+        self.score1 = 0;
+        self.score2 = 0;
+        self.dead = false;
+        self.game_over.reset();
+    }
+
+    fn center(&self) -> Point {
+        Point::new(self.screen_width / 2, self.screen_height / 2)
+    }
+}
+
+impl<D, C> AppMeta for Pong<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: PixelColor + RgbColor,
+{
+    const INFO: AppInfo = AppInfo::new("pong", "Pong", Category::Games)
+        .with_audio()
+        .with_high_scores();
 }
 
 impl<D, C> App for Pong<D, C>
@@ -75,20 +116,22 @@ where
 
     fn reset_state(&mut self) {
         self.dead = false;
-        self.last_update = 0;
+        self.game_over.reset();
         self.close_request.reset();
     }
 
-    fn update(&mut self, dt_us: i64, t_us: i64, controls: &Controls) -> UpdateResult {
+    fn update(&mut self, _dt_us: i64, _t_us: i64, controls: &Controls) -> UpdateResult {
         // Kill game with 'B'
         self.close_request.update(controls.buttons_b);
 
-        // Time gate
-        const MIN_UPDATE_DT_US: i64 = 20 * 1000; // 20 ms
-        if t_us - self.last_update < MIN_UPDATE_DT_US {
-            return RenderStatus::NoVisibleChange.into();
+        if self.game_over.is_active() {
+            let result = self.game_over.update(controls);
+            if self.game_over.restart_requested() {
+                self.restart();
+                return RenderStatus::VisibleChange.into();
+            }
+            return result;
         }
-        self.last_update = t_us;
 
         // Update paddles based on controls
         const MOVEMENT_SPEED: i32 = 2;
@@ -121,7 +164,7 @@ where
                 audio_id = Some(AudioID::Ping);
             } else {
                 self.score2 += 1;
-                self.ball_pos = Point::new(self.screen_width / 2, self.screen_height / 2);
+                self.ball_pos = self.center();
             }
         }
 
@@ -133,7 +176,7 @@ where
                 audio_id = Some(AudioID::Pong);
             } else {
                 self.score1 += 1;
-                self.ball_pos = Point::new(self.screen_width / 2, self.screen_height / 2);
+                self.ball_pos = self.center();
             }
         }
 
@@ -144,13 +187,25 @@ where
             self.paddle2_pos = (self.paddle2_pos - 2).max(0);
         }
 
-        UpdateResult {
-            render_result: RenderStatus::VisibleChange,
-            audio_queue_request: audio_id,
+        let mut result = UpdateResult::from(RenderStatus::VisibleChange);
+        if self.score2 >= POINTS_TO_LOSE {
+            self.dead = true;
+            self.game_over.start(self.score1 as u32);
+            result.push_command(Command::play_sfx(AudioID::GameOver));
+            return result;
         }
+        if let Some(audio_id) = audio_id {
+            result.push_command(Command::play_sfx(audio_id));
+        }
+        result
     }
 
     fn render(&self, target: &mut Self::Target) {
+        if self.game_over.is_active() {
+            self.game_over.render(target);
+            return;
+        }
+
         // Clear the screen
         let _background = Rectangle::new(
             Point::zero(),
@@ -205,4 +260,43 @@ where
     fn close_request(&self) -> bool {
         self.close_request.fired()
     }
+
+    fn pacing(&self) -> Pacing {
+        Pacing::every(TICK_US)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::pixelcolor::Rgb565;
+
+    use super::*;
+    use crate::testing::{Frame, Harness};
+
+    #[test]
+    fn scoring_golden() {
+        let mut harness =
+            Harness::new(Pong::<Frame<64, 32>, Rgb565>::new(DisplayInfo::PANEL_64X32));
+        harness.assert_golden("pong_start");
+
+        // The player doesn't move, so the 'AI' scores
+        while harness.app().score2 == 0 {
+            harness.wait(1);
+        }
+        harness.assert_golden("pong_point_lost");
+
+        while !harness.app().game_over.is_active() {
+            harness.wait(1);
+        }
+        assert_eq!(harness.app().score2, POINTS_TO_LOSE);
+        harness.assert_golden("pong_game_over");
+    }
+
+    #[test]
+    fn wide_panel_golden() {
+        let pong = Pong::<Frame<128, 32>, Rgb565>::new(DisplayInfo::PANEL_128X32);
+        let mut harness = Harness::new(pong);
+        harness.wait(10);
+        harness.assert_golden("pong_128x32");
+    }
 }
@@ -10,17 +10,25 @@ use embedded_graphics::{
 use heapless::Vec;
 use tinyrand::{Rand, Seeded, StdRand};
 
+use super::game_over::GameOver;
 use crate::{
+    display::DisplayInfo,
+    high_scores::HighScores,
+    registry::{AppInfo, AppMeta, Category},
     string_buffer::{self, FixedBuffer},
     trait_app::{Color, RenderStatus, UpdateResult},
-    App, Controls, KeyReleaseEvent,
+    App, Controls, DirtyRegions, KeyReleaseEvent, Pacing,
 };
 
-pub struct Snake<const SCR_W: u32, const SCR_H: u32, const MAX_LEN: usize, D, C>
+/// Time between two steps of the snake, 60 ms
+const TICK_US: i64 = 60_000;
+
+pub struct Snake<const MAX_LEN: usize, D, C>
 where
     D: DrawTarget<Color = C>,
     C: PixelColor + RgbColor,
 {
+    display: DisplayInfo,
     body: Vec<Point, MAX_LEN>,
     dir: Direction,
     food: Option<Point>,
@@ -29,9 +37,9 @@ where
     prng: StdRand,
 
     dead: bool,
+    game_over: GameOver,
     close_request: KeyReleaseEvent,
     time: i32,
-    last_update: i64,
 
     _marker: PhantomData<D>,
 }
@@ -44,36 +52,43 @@ pub enum Direction {
     Right,
 }
 
-impl<const SCR_W: u32, const SCR_H: u32, const MAX_LEN: usize, D, C>
-    Snake<SCR_W, SCR_H, MAX_LEN, D, C>
+impl<const MAX_LEN: usize, D, C> Snake<MAX_LEN, D, C>
 where
     D: DrawTarget<Color = C>,
     C: PixelColor + RgbColor,
 {
-    pub fn new() -> Self {
+    /// A pixel of the display is a cell of the field.
+    pub fn new(display: DisplayInfo) -> Self {
         let mut prng = StdRand::seed(0xDEAD_BEEF);
         let mut body = Vec::new();
-        body.push(Point::new(SCR_W as i32 / 2, SCR_H as i32 / 2))
+        body.push(Point::new(display.width() / 2, display.height() / 2))
             .unwrap(); // Start with one segment
 
         Self {
+            display,
             body,
             dir: Direction::Right,
-            food: Some(random_position::<SCR_W, SCR_H>(&mut prng)),
+            food: Some(random_position(display, &mut prng)),
             grow: false,
 
             time: 0,
             prng,
             dead: false,
+            game_over: GameOver::new(Self::INFO),
             close_request: KeyReleaseEvent::new(),
-            last_update: 0,
 
             _marker: Default::default(),
         }
     }
 
+    /// Enters the length reached into the shared high score table.
+    pub fn with_high_scores(mut self, high_scores: HighScores) -> Self {
+        self.game_over.set_high_scores(high_scores);
+        self
+    }
+
     fn spawn_food(&mut self) {
-        self.food = Some(random_position::<SCR_W, SCR_H>(&mut self.prng));
+        self.food = Some(random_position(self.display, &mut self.prng));
     }
 
     fn move_snake(&mut self) {
@@ -110,12 +125,34 @@ where
 
     fn check_bounds(&self) -> bool {
         let head = self.body[0];
-        head.x < 0 || head.y < 0 || head.x >= SCR_W as i32 || head.y >= SCR_H as i32
+        !self.display.bounding_box().contains(head)
+    }
+
+    fn stats_text<R>(&self, color: C, f: impl FnOnce(Text<'_, MonoTextStyle<'_, C>>) -> R) -> R {
+        let mut text_buffer = FixedBuffer::<32>::new();
+        let score_style = MonoTextStyle::new(&FONT_6X9, color);
+        let _ = write!(&mut text_buffer, "Len: {}", self.body.len());
+        f(Text::new(
+            text_buffer.as_str(),
+            Point::new(10, 10),
+            score_style,
+        ))
     }
 }
 
-impl<const SCR_W: u32, const SCR_H: u32, const MAX_LEN: usize, D, C> App
-    for Snake<SCR_W, SCR_H, MAX_LEN, D, C>
+fn pixel(position: Point) -> Rectangle {
+    Rectangle::new(position, Size::new(1, 1))
+}
+
+impl<const MAX_LEN: usize, D, C> AppMeta for Snake<MAX_LEN, D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: PixelColor + RgbColor,
+{
+    const INFO: AppInfo = AppInfo::new("snake", "Schnek", Category::Games).with_high_scores();
+}
+
+impl<const MAX_LEN: usize, D, C> App for Snake<MAX_LEN, D, C>
 where
     D: DrawTarget<Color = C>,
     C: Color,
@@ -125,23 +162,35 @@ where
 
     fn reset_state(&mut self) {
         let mut body = Vec::new();
-        body.push(Point::new(SCR_W as i32 / 2, SCR_H as i32 / 2))
-            .unwrap(); // Start with one segment
+        body.push(Point::new(
+            self.display.width() / 2,
+            self.display.height() / 2,
+        ))
+        .unwrap(); // Start with one segment
 
         self.body = body;
-        self.food = Some(random_position::<SCR_W, SCR_H>(&mut self.prng));
+        self.food = Some(random_position(self.display, &mut self.prng));
         self.dir = Direction::Right;
         self.grow = false;
 
         self.dead = false;
+        self.game_over.reset();
         self.close_request.reset();
-        self.last_update = 0;
     }
 
-    fn update(&mut self, dt: i64, t: i64, controls: &Controls) -> UpdateResult {
+    fn update(&mut self, _dt: i64, _t: i64, controls: &Controls) -> UpdateResult {
         // Kill game with 'B'
         self.close_request.update(controls.buttons_b);
 
+        if self.game_over.is_active() {
+            let result = self.game_over.update(controls);
+            if self.game_over.restart_requested() {
+                self.reset_state();
+                return RenderStatus::VisibleChange.into();
+            }
+            return result;
+        }
+
         if controls.dpad_up && self.dir != Direction::Down {
             self.dir = Direction::Up;
         } else if controls.dpad_down && self.dir != Direction::Up {
@@ -152,12 +201,15 @@ where
             self.dir = Direction::Right;
         }
 
-        // Time gate
-        const MIN_UPDATE_DT_US: i64 = 60 * 1000; // 100 ms
-        if t - self.last_update < MIN_UPDATE_DT_US {
-            return RenderStatus::NoVisibleChange.into();
+        // Only a few pixels change per step: The head moves, the old head gets the body color,
+        // the tail and food may disappear.
+        let mut dirty = DirtyRegions::new();
+        let len_before = self.body.len();
+        dirty.add(pixel(self.body[0]));
+        dirty.add(pixel(*self.body.last().unwrap()));
+        if let Some(food) = self.food {
+            dirty.add(pixel(food));
         }
-        self.last_update = t;
 
         // Move the snake
         self.move_snake();
@@ -165,6 +217,8 @@ where
         // Check for collisions
         if self.check_collision() || self.check_bounds() {
             self.dead = true;
+            self.game_over.start(self.body.len() as u32);
+            return RenderStatus::VisibleChange.into();
         }
 
         // Spawn new food if needed
@@ -172,40 +226,55 @@ where
             self.spawn_food();
         }
 
-        RenderStatus::VisibleChange.into()
+        dirty.add(pixel(self.body[0]));
+        if let Some(food) = self.food {
+            dirty.add(pixel(food));
+        }
+        if self.body.len() != len_before {
+            dirty.add(self.stats_text(C::BLUE, |text| text.bounding_box()));
+        }
+        RenderStatus::Dirty(dirty).into()
     }
 
     fn render(&self, target: &mut Self::Target) {
+        self.render_region(target, &self.display.bounding_box());
+    }
+
+    fn render_region(&self, target: &mut Self::Target, area: &Rectangle) {
+        if self.game_over.is_active() {
+            self.game_over.render(target);
+            return;
+        }
+
         // Draw some stats
-        let mut text_buffer = FixedBuffer::<32>::new();
         let gray = C::BLUE; // 0x404040.try_into().unwrap();
-        let score_style = MonoTextStyle::new(&FONT_6X9, gray);
-
-        text_buffer.clear();
-        let _ = write!(&mut text_buffer, "Len: {}", self.body.len());
-        let _score = Text::new(text_buffer.as_str(), Point::new(10, 10), score_style).draw(target);
+        self.stats_text(gray, |text| {
+            if !text.bounding_box().intersection(area).is_zero_sized() {
+                let _score = text.draw(target);
+            }
+        });
 
         // Draw the snake
+        // The body colors alternate by position, so a segment keeps its color while moving.
         let snake_style_head = PrimitiveStyle::with_fill(C::CSS_GRAY);
         let snake_style_pri = PrimitiveStyle::with_fill(C::CSS_GREEN_YELLOW);
         let snake_style_sec = PrimitiveStyle::with_fill(C::GREEN);
         for (i, &segment) in self.body.iter().enumerate() {
+            if !area.contains(segment) {
+                continue;
+            }
             let style = match i {
                 0 => snake_style_head,
-                _ if i % 2 == 1 => snake_style_sec,
+                _ if (segment.x + segment.y) % 2 != 0 => snake_style_sec,
                 _ => snake_style_pri,
             };
-            let _segment = Rectangle::new(segment, Size::new(1, 1))
-                .into_styled(style)
-                .draw(target);
+            let _segment = pixel(segment).into_styled(style).draw(target);
         }
 
         // Draw the food
-        if let Some(food_pos) = self.food {
+        if let Some(food_pos) = self.food.filter(|food| area.contains(*food)) {
             let food_style = PrimitiveStyle::with_fill(C::RED);
-            let _food = Rectangle::new(food_pos, Size::new(1, 1))
-                .into_styled(food_style)
-                .draw(target);
+            let _food = pixel(food_pos).into_styled(food_style).draw(target);
         }
     }
 
@@ -214,10 +283,53 @@ where
     fn close_request(&self) -> bool {
         self.close_request.fired()
     }
+
+    fn pacing(&self) -> Pacing {
+        Pacing::every(TICK_US)
+    }
 }
 
-fn random_position<const SCR_W: u32, const SCR_H: u32>(prng: &mut StdRand) -> Point {
-    let x = prng.next_lim_u32(SCR_H) as i32;
-    let y = prng.next_lim_u32(SCR_W) as i32;
+fn random_position(display: DisplayInfo, prng: &mut StdRand) -> Point {
+    let x = prng.next_lim_u32(display.size.width) as i32;
+    let y = prng.next_lim_u32(display.size.height) as i32;
     Point { x, y }
 }
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::pixelcolor::Rgb565;
+
+    use super::*;
+    use crate::testing::{Button, Frame, Harness};
+
+    type TestSnake = Snake<64, Frame<64, 32>, Rgb565>;
+
+    /// Waits until `done` holds, at most a second.
+    fn wait_until(harness: &mut Harness<TestSnake, 64, 32>, done: fn(&TestSnake) -> bool) {
+        for _ in 0..50 {
+            if done(harness.app()) {
+                return;
+            }
+            harness.wait(1);
+        }
+        panic!("Timed out");
+    }
+
+    #[test]
+    fn growth_golden() {
+        let mut harness = Harness::new(TestSnake::new(DisplayInfo::PANEL_64X32));
+        // The food is placed by hand, so the frames don't depend on the random numbers
+        harness.app_mut().food = Some(Point::new(36, 16));
+        harness.assert_golden("snake_start");
+
+        // The head starts at (32, 16) heading right
+        wait_until(&mut harness, |snake| snake.body.len() == 2);
+        harness.app_mut().food = Some(Point::new(38, 20));
+        harness.press(Button::Down);
+        wait_until(&mut harness, |snake| snake.body[0].y == 20);
+        harness.press(Button::Right);
+        wait_until(&mut harness, |snake| snake.body.len() == 3);
+        harness.app_mut().food = Some(Point::new(20, 8));
+        harness.assert_golden("snake_grown");
+    }
+}
@@ -1 +1,7 @@
+use crate::registry::{AppInfo, AppMeta, Category};
+
 pub struct SpaceInvader {}
+
+impl AppMeta for SpaceInvader {
+    const INFO: AppInfo = AppInfo::new("space_invader", "Invaders", Category::Games).with_audio();
+}
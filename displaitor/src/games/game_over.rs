@@ -0,0 +1,153 @@
+use embedded_graphics::prelude::*;
+
+use crate::{
+    apps::{draw_table, NameEntry},
+    high_scores::HighScores,
+    registry::AppInfo,
+    trait_app::{Color, RenderStatus, UpdateResult},
+    Controls, KeyReleaseEvent,
+};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum State {
+    Hidden,
+    NameEntry {
+        score: u32,
+    },
+    /// Rank of the score just entered, if any
+    Table {
+        highlight: Option<usize>,
+    },
+}
+
+/// Game over screen shared by the games. A score entering the high score table asks for a name
+/// first, then the table is shown until A restarts the game.
+///
+/// The game keeps handling B to close, the screen covers the whole game while it is active.
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) struct GameOver {
+    info: AppInfo,
+    high_scores: HighScores,
+    state: State,
+    name_entry: NameEntry,
+    restart_request: KeyReleaseEvent,
+}
+
+impl GameOver {
+    pub fn new(info: AppInfo) -> Self {
+        Self {
+            info,
+            high_scores: HighScores::new(),
+            state: State::Hidden,
+            name_entry: NameEntry::new(),
+            restart_request: KeyReleaseEvent::new(),
+        }
+    }
+
+    /// Tables shared with the rest of the firmware. Without them the scores are only kept in RAM.
+    pub fn set_high_scores(&mut self, high_scores: HighScores) {
+        self.high_scores = high_scores;
+    }
+
+    pub fn reset(&mut self) {
+        self.state = State::Hidden;
+        self.name_entry.reset();
+        self.restart_request.reset();
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.state != State::Hidden
+    }
+
+    /// Shows the screen for the final `score` of the game.
+    pub fn start(&mut self, score: u32) {
+        self.state = match self.high_scores.qualifies(self.info.id, score) {
+            true => State::NameEntry { score },
+            false => State::Table { highlight: None },
+        };
+        self.name_entry.reset();
+        self.restart_request.reset();
+    }
+
+    /// `true` once A was pressed on the table.
+    pub fn restart_requested(&self) -> bool {
+        matches!(self.state, State::Table { .. }) && self.restart_request.fired()
+    }
+
+    pub fn update(&mut self, controls: &Controls) -> UpdateResult {
+        match self.state {
+            State::Hidden => RenderStatus::NoVisibleChange.into(),
+            State::NameEntry { score } => match self.name_entry.update(controls) {
+                (Some(name), _) => {
+                    let submitted = self.high_scores.submit(self.info.id, name, score);
+                    self.state = State::Table {
+                        highlight: submitted.as_ref().map(|(rank, _)| *rank),
+                    };
+                    let result = UpdateResult::from(RenderStatus::VisibleChange);
+                    match submitted {
+                        Some((_, persist)) => result.with_command(persist),
+                        None => result,
+                    }
+                }
+                (None, true) => RenderStatus::VisibleChange.into(),
+                (None, false) => RenderStatus::NoVisibleChange.into(),
+            },
+            State::Table { .. } => {
+                self.restart_request.update(controls.buttons_a);
+                RenderStatus::NoVisibleChange.into()
+            }
+        }
+    }
+
+    pub fn render<D, C>(&self, target: &mut D)
+    where
+        D: DrawTarget<Color = C>,
+        C: Color,
+    {
+        match self.state {
+            State::Hidden => {}
+            State::NameEntry { score } => self.name_entry.render(target, score),
+            State::Table { highlight } => {
+                let table = self.high_scores.table(self.info.id);
+                draw_table(target, self.info.name, &table, highlight);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{registry::Category, Command};
+
+    fn press(game_over: &mut GameOver, button: fn(&mut Controls)) -> UpdateResult {
+        let mut pressed = Controls::default();
+        button(&mut pressed);
+        let _ = game_over.update(&Controls::default());
+        let _ = game_over.update(&pressed);
+        game_over.update(&Controls::default())
+    }
+
+    #[test]
+    fn qualifying_score_asks_for_a_name_and_persists_it() {
+        let high_scores = HighScores::new();
+        let mut game_over = GameOver::new(AppInfo::new("snake", "Schnek", Category::Games));
+        game_over.set_high_scores(high_scores.clone());
+
+        game_over.start(12);
+        assert!(game_over.is_active());
+        let _ = press(&mut game_over, |c| c.dpad_up = true);
+        let confirmed = press(&mut game_over, |c| c.buttons_a = true);
+        let [Command::Persist { .. }] = confirmed.commands() else {
+            panic!("Expected the table to be persisted");
+        };
+        assert_eq!(high_scores.table("snake").entries()[0].name(), "BAA");
+
+        // A score of 0 goes straight to the table
+        game_over.start(0);
+        assert_eq!(game_over.state, State::Table { highlight: None });
+        assert!(!game_over.restart_requested());
+        let _ = press(&mut game_over, |c| c.buttons_a = true);
+        assert!(game_over.restart_requested());
+    }
+}
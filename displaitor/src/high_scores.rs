@@ -0,0 +1,359 @@
+use alloc::{rc::Rc, vec::Vec};
+use core::cell::RefCell;
+
+use crate::{Command, PersistValue, Storage};
+
+/// Keys of the high score tables are `HIGH_SCORES_KEY_BASE` plus a slot derived from the id of the
+/// game, see [`HighScores::load`].
+pub const HIGH_SCORES_KEY_BASE: u16 = 0x4800;
+/// Slots of the tables, the keys from [`HIGH_SCORES_KEY_BASE`] on
+const SLOTS: u16 = 256;
+/// Slots tried from the one of the id on, when it holds the table of another game
+const MAX_PROBES: u16 = 8;
+/// Length of a table. All of them fit on the 64x32 panel at once.
+pub const HIGH_SCORE_ENTRIES: usize = 4;
+
+/// Bumped whenever the encoding changes. Older records are ignored.
+const VERSION: u8 = 1;
+
+/// Three letters entered arcade-style, `A`-`Z` or a space.
+pub type Initials = [u8; 3];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct HighScore {
+    pub name: Initials,
+    pub score: u32,
+}
+
+impl HighScore {
+    pub fn name(&self) -> &str {
+        core::str::from_utf8(&self.name).unwrap_or("???")
+    }
+}
+
+/// The best scores of a game, highest first.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct HighScoreTable {
+    entries: heapless::Vec<HighScore, HIGH_SCORE_ENTRIES>,
+}
+
+impl HighScoreTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn entries(&self) -> &[HighScore] {
+        &self.entries
+    }
+
+    /// `true`, if `score` would enter the table. A score of 0 never does.
+    pub fn qualifies(&self, score: u32) -> bool {
+        score > 0
+            && (!self.entries.is_full()
+                || self.entries.last().is_some_and(|last| score > last.score))
+    }
+
+    /// Adds the score behind the ones which are at least as high and drops the lowest one, if the
+    /// table is full. Returns the rank, starting at 0, or `None`, if the score didn't qualify.
+    pub fn insert(&mut self, name: Initials, score: u32) -> Option<usize> {
+        if !self.qualifies(score) {
+            return None;
+        }
+        let rank = self
+            .entries
+            .iter()
+            .position(|entry| entry.score < score)
+            .unwrap_or(self.entries.len());
+        if self.entries.is_full() {
+            self.entries.pop();
+        }
+        let _ = self.entries.insert(rank, HighScore { name, score });
+        Some(rank)
+    }
+
+    /// Encodes the table of the game `id` for [`Command::Persist`]. The last byte is a checksum.
+    pub fn to_bytes(&self, id: &str) -> PersistValue {
+        let mut bytes = PersistValue::new();
+        let _ = bytes.push(VERSION);
+        // Games, whose ids hash to the same slot, probe the following ones, so the hash of the id
+        // tells their tables apart.
+        let _ = bytes.extend_from_slice(&fnv1a(id).to_le_bytes());
+        let _ = bytes.push(self.entries.len() as u8);
+        for entry in &self.entries {
+            let _ = bytes.extend_from_slice(&entry.name);
+            let _ = bytes.extend_from_slice(&entry.score.to_le_bytes());
+        }
+        let _ = bytes.push(checksum(&bytes));
+        bytes
+    }
+
+    /// Decodes a table written by [`HighScoreTable::to_bytes`] for the same `id`. `None` for
+    /// corrupted or outdated records and ones of another game.
+    pub fn from_bytes(id: &str, bytes: &[u8]) -> Option<Self> {
+        const HEADER: usize = 6;
+        const ENTRY: usize = 7;
+        let header = bytes.get(..HEADER)?;
+        if header[0] != VERSION || header[1..5] != fnv1a(id).to_le_bytes() {
+            return None;
+        }
+        let count = header[5] as usize;
+        if count > HIGH_SCORE_ENTRIES {
+            return None;
+        }
+        let record = bytes.get(..HEADER + count * ENTRY)?;
+        if *bytes.get(record.len())? != checksum(record) {
+            return None;
+        }
+
+        let mut table = Self::new();
+        for entry in record[HEADER..].chunks_exact(ENTRY) {
+            let _ = table.entries.push(HighScore {
+                name: [entry[0], entry[1], entry[2]],
+                score: u32::from_le_bytes([entry[3], entry[4], entry[5], entry[6]]),
+            });
+        }
+        Some(table)
+    }
+}
+
+/// Hash of the id of the game, whose table is stored in `bytes`. `None` for outdated records.
+fn stored_id_hash(bytes: &[u8]) -> Option<u32> {
+    match bytes.first() {
+        Some(&VERSION) => Some(u32::from_le_bytes(bytes.get(1..5)?.try_into().ok()?)),
+        _ => None,
+    }
+}
+
+#[derive(Debug)]
+struct GameTable {
+    id: &'static str,
+    /// Under which the table is persisted
+    key: u16,
+    table: HighScoreTable,
+}
+
+/// The high score tables of all games, shared by the games and the high score view.
+///
+/// Cloning gives another handle to the same tables. They are loaded once at startup, changes are
+/// persisted with [`Command::Persist`] like everything else.
+#[derive(Clone, Default, Debug)]
+pub struct HighScores {
+    tables: Rc<RefCell<Vec<GameTable>>>,
+}
+
+/// Handles are equal, if they share the same tables.
+impl PartialEq for HighScores {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.tables, &other.tables)
+    }
+}
+
+impl HighScores {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the table of the game `id`. A missing or invalid record gives an empty table.
+    ///
+    /// The table is persisted under the key of the slot the hash of the id points to. If another
+    /// game stored its table there or was loaded into it before, the following slots are tried.
+    /// So the keys don't depend on the order the games are loaded in.
+    pub fn load<S: Storage>(&self, storage: &mut S, id: &'static str) {
+        let mut bytes = [0; crate::command::PERSIST_VALUE_MAX];
+        let mut free = None;
+        for key in probe(id) {
+            let record = match storage.read(key, &mut bytes) {
+                Ok(Some(len)) => &bytes[..len.min(bytes.len())],
+                _ => &[],
+            };
+            match stored_id_hash(record) {
+                Some(hash) if hash == fnv1a(id) => {
+                    let table = HighScoreTable::from_bytes(id, record).unwrap_or_default();
+                    self.set(id, key, table);
+                    return;
+                }
+                // The table of another game
+                Some(_) => {}
+                None if free.is_none() && !self.is_taken(key) => free = Some(key),
+                None => {}
+            }
+        }
+        let key = free.unwrap_or_else(|| self.free_key(id));
+        self.set(id, key, HighScoreTable::new());
+    }
+
+    /// A copy of the table of the game `id`.
+    pub fn table(&self, id: &str) -> HighScoreTable {
+        let tables = self.tables.borrow();
+        let table = tables.iter().find(|table| table.id == id);
+        table.map(|table| table.table.clone()).unwrap_or_default()
+    }
+
+    pub fn qualifies(&self, id: &str, score: u32) -> bool {
+        self.table(id).qualifies(score)
+    }
+
+    /// Enters the score into the table of the game `id`. Returns the rank and the command, which
+    /// persists the table, or `None`, if the score didn't qualify.
+    pub fn submit(&self, id: &'static str, name: Initials, score: u32) -> Option<(usize, Command)> {
+        let mut table = self.table(id);
+        let rank = table.insert(name, score)?;
+        let value = table.to_bytes(id);
+        let key = self.set(id, self.free_key(id), table);
+        let command = Command::Persist {
+            key,
+            value: value.as_slice().into(),
+        };
+        Some((rank, command))
+    }
+
+    /// Replaces the table of the game `id`. A game without one gets `key`. Returns the key of the
+    /// table.
+    fn set(&self, id: &'static str, key: u16, table: HighScoreTable) -> u16 {
+        let mut tables = self.tables.borrow_mut();
+        match tables.iter_mut().find(|stored| stored.id == id) {
+            Some(stored) => {
+                stored.table = table;
+                stored.key
+            }
+            None => {
+                tables.push(GameTable { id, key, table });
+                key
+            }
+        }
+    }
+
+    /// Whether another game was loaded into the slot of `key`.
+    fn is_taken(&self, key: u16) -> bool {
+        self.tables.borrow().iter().any(|table| table.key == key)
+    }
+
+    /// The first slot of `id`, which no other game was loaded into. The slot of the id, if all
+    /// of them are.
+    fn free_key(&self, id: &str) -> u16 {
+        probe(id)
+            .find(|key| !self.is_taken(*key))
+            .unwrap_or_else(|| probe(id).next().unwrap_or(HIGH_SCORES_KEY_BASE))
+    }
+}
+
+/// Keys the table of the game `id` may be persisted under, the one of its hash first.
+fn probe(id: &str) -> impl Iterator<Item = u16> {
+    let home = (fnv1a(id) % SLOTS as u32) as u16;
+    (0..MAX_PROBES).map(move |i| HIGH_SCORES_KEY_BASE + (home + i) % SLOTS)
+}
+
+fn fnv1a(text: &str) -> u32 {
+    text.bytes().fold(0x811C_9DC5, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes
+        .iter()
+        .fold(0x5Au8, |sum, byte| sum.rotate_left(1) ^ byte)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LogStorage, RamFlash};
+
+    #[test]
+    fn keeps_the_best_scores_in_order() {
+        let mut table = HighScoreTable::new();
+        assert!(!table.qualifies(0));
+        assert_eq!(table.insert(*b"AAA", 10), Some(0));
+        assert_eq!(table.insert(*b"BBB", 30), Some(0));
+        assert_eq!(table.insert(*b"CCC", 10), Some(2));
+        assert_eq!(table.insert(*b"DDD", 20), Some(1));
+        assert!(!table.qualifies(10));
+        assert_eq!(table.insert(*b"EEE", 15), Some(2));
+
+        let names: Vec<&str> = table.entries().iter().map(HighScore::name).collect();
+        assert_eq!(names, ["BBB", "DDD", "EEE", "AAA"]);
+    }
+
+    #[test]
+    fn bytes_roundtrip() {
+        let mut table = HighScoreTable::new();
+        table.insert(*b"ZED", 1234);
+        table.insert(*b"A  ", 7);
+        let bytes = table.to_bytes("snake");
+        assert_eq!(HighScoreTable::from_bytes("snake", &bytes), Some(table));
+        assert_eq!(HighScoreTable::from_bytes("pong", &bytes), None);
+        assert_eq!(HighScoreTable::from_bytes("snake", &bytes[1..]), None);
+    }
+
+    #[test]
+    fn submitted_scores_survive_a_restart() {
+        let mut storage = LogStorage::mount(RamFlash::new(1024, 4, 128)).unwrap();
+        let high_scores = HighScores::new();
+        high_scores.load(&mut storage, "snake");
+        let viewer = high_scores.clone();
+
+        let Some((0, Command::Persist { key, value })) = high_scores.submit("snake", *b"BOB", 42)
+        else {
+            panic!("Expected the first rank and a persist command");
+        };
+        assert_eq!(viewer.table("snake").entries()[0].score, 42);
+        assert!(high_scores.submit("snake", *b"NOP", 0).is_none());
+
+        storage.write(key, &value).unwrap();
+        let reloaded = HighScores::new();
+        reloaded.load(&mut storage, "snake");
+        reloaded.load(&mut storage, "pong");
+        assert_eq!(reloaded.table("snake"), high_scores.table("snake"));
+        assert!(reloaded.table("pong").entries().is_empty());
+    }
+
+    #[test]
+    fn every_game_gets_its_own_key() {
+        // The low bytes of their hashes are the same
+        assert_eq!(fnv1a("game5") & 0xFF, fnv1a("game10") & 0xFF);
+        let mut storage = LogStorage::mount(RamFlash::new(1024, 4, 128)).unwrap();
+        let high_scores = HighScores::new();
+        for id in ["game5", "game10"] {
+            high_scores.load(&mut storage, id);
+            let Some((_, Command::Persist { key, value })) = high_scores.submit(id, *b"ABC", 7)
+            else {
+                panic!("Expected a persist command");
+            };
+            storage.write(key, &value).unwrap();
+        }
+
+        let reloaded = HighScores::new();
+        reloaded.load(&mut storage, "game5");
+        reloaded.load(&mut storage, "game10");
+        assert_eq!(reloaded.table("game5"), high_scores.table("game5"));
+        assert_eq!(reloaded.table("game10"), high_scores.table("game10"));
+    }
+
+    #[test]
+    fn keys_do_not_depend_on_the_order() {
+        let mut storage = LogStorage::mount(RamFlash::new(1024, 4, 128)).unwrap();
+        let high_scores = HighScores::new();
+        // Both hash to the same slot, the second one probes the next
+        for id in ["game5", "game10"] {
+            high_scores.load(&mut storage, id);
+        }
+        let Some((_, Command::Persist { key, value })) = high_scores.submit("game10", *b"ABC", 7)
+        else {
+            panic!("Expected a persist command");
+        };
+        storage.write(key, &value).unwrap();
+
+        // Reordered, with a new game in front
+        let reloaded = HighScores::new();
+        for id in ["pong", "game10", "game5"] {
+            reloaded.load(&mut storage, id);
+        }
+        assert_eq!(reloaded.table("game10"), high_scores.table("game10"));
+        let Some((_, Command::Persist { key: game5, .. })) = reloaded.submit("game5", *b"DEF", 3)
+        else {
+            panic!("Expected a persist command");
+        };
+        assert_ne!(game5, key);
+    }
+}
@@ -2,42 +2,122 @@
 #![no_std]
 #![feature(iter_collect_into)]
 #![feature(generic_arg_infer)]
+#![feature(allocator_api)]
 
 #[macro_use]
 extern crate alloc;
 
+mod app_clock;
+mod command;
 mod controls;
+#[cfg(feature = "debug-overlay")]
+mod debug_overlay;
+mod dimmed;
+mod display;
+mod error;
+mod framebuffer;
+mod high_scores;
 mod key_release;
+mod navigator;
+mod notifications;
+mod panels;
+mod profiler;
+mod registry;
+mod renderer;
+mod runtime;
+mod scheduler;
+mod screensaver;
+mod settings;
+mod storage;
 pub mod string_buffer;
+#[cfg(test)]
+mod testing;
+mod tracking_alloc;
 mod trait_app;
+mod transition;
 
 use alloc::boxed::Box;
 use apps::Menu;
+pub use app_clock::{AppClock, TimeControl, MAX_SPEED_PERCENT, NORMAL_SPEED_PERCENT};
+pub use command::{AudioChannel, AudioCommand, Command, Commands, PersistValue};
 pub use controls::Controls;
+#[cfg(feature = "debug-overlay")]
+pub use debug_overlay::DebugOverlay;
+pub use dimmed::Dimmed;
+pub use display::DisplayInfo;
+pub use error::{try_box, AppError};
+pub use framebuffer::FrameBuffer;
+pub use high_scores::{
+    HighScore, HighScoreTable, HighScores, Initials, HIGH_SCORES_KEY_BASE, HIGH_SCORE_ENTRIES,
+};
 use embedded_graphics::prelude::{DrawTarget, PixelColor, RgbColor};
 pub(crate) use key_release::KeyReleaseEvent;
+pub use navigator::{Navigation, Navigator, SceneId};
+pub use notifications::{
+    Icon, Notifications, StatusBar, Toast, DEFAULT_TOAST_US, MAX_QUEUED_TOASTS, SLIDE_US,
+    STATUS_BAR_US,
+};
+pub use panels::{Orientation, PanelLayout, Panels, SplitChain, MAX_PANELS};
+pub use profiler::{
+    AppProfile, FrameProfile, FrameTimes, Profiler, Timing, HISTOGRAM_BUCKETS, MAX_PROFILED_APPS,
+    MAX_PROFILED_NAME_LEN, OVERLAY_HEIGHT,
+};
+pub use registry::{AppInfo, AppMeta, Category, Registration, Registry};
+pub use renderer::{BufferMode, DirtyRegions, Renderer};
+pub use runtime::{
+    AudioOut, Canvas, Clock, Event, HeapUsage, Input, Runtime, Screen, Stats, STATS_PERIOD_US,
+};
+pub use scheduler::{Pacing, Scheduler, DEFAULT_TICK_US, INPUT_POLL_US, MAX_CATCH_UP};
+pub use screensaver::Screensaver;
+pub use settings::{MenuTheme, Setting, Settings, ThemeColors, SETTINGS_KEY};
+pub use storage::{
+    BlockDevice, LogStorage, PowerLoss, RamFlash, Storage, StorageError, MAX_VALUE_LEN,
+};
 use trait_app::Color;
-pub use trait_app::{App, AudioID};
+pub use tracking_alloc::{
+    attribute_heap_to, heap_app, AppHeap, HeapStats, TrackingAlloc, MAX_HEAP_APPS,
+};
+pub use trait_app::{App, AudioID, RenderStatus, UpdateResult};
+pub use transition::{Capture, Transition, TransitionFrame, TransitionKind};
 
 // Replace with a mod.rs ?
 pub mod apps {
+    mod active_child;
     mod app_animation;
+    mod app_broken;
     mod app_dummy;
+    mod app_dynamic_menu;
+    mod app_high_scores;
     mod app_image;
+    mod app_launcher;
     mod app_menu;
+    mod app_open_scene;
+    mod app_pause;
     mod app_scrolling_text;
+    mod app_settings;
     mod app_splashscreen;
+    mod high_score_view;
+    mod menu_list;
     pub use app_animation::Animation;
+    pub use app_broken::BrokenApp;
     pub use app_dummy::Dummy;
+    pub use app_dynamic_menu::{DynamicEntry, DynamicMenu, EntryKind};
+    pub use app_high_scores::HighScoresApp;
     pub use app_image::Image;
+    pub use app_launcher::Launcher;
     pub use app_menu::{Menu, MenuEntry};
+    pub use app_open_scene::OpenScene;
+    pub use app_pause::PauseOverlay;
     pub use app_scrolling_text::ScrollingText;
+    pub use app_settings::SettingsApp;
     pub use app_splashscreen::SplashScreen;
+    pub(crate) use high_score_view::{draw_table, NameEntry};
 }
 
 pub mod games {
     mod app_gameboy;
     mod app_pong;
+    mod game_over;
     mod app_snake;
     mod app_space_invader;
     pub use app_gameboy::GameBoy;
@@ -46,116 +126,208 @@ pub mod games {
     pub use app_space_invader::SpaceInvader;
 }
 
-pub fn startup_app<'a, D, C>() -> impl App<Target = D, Color = C>
+/// The splash screen with the startup music. Fails, if its images are broken, the firmware starts
+/// without it then.
+pub fn startup_app<'a, D, C>() -> Result<impl App<Target = D, Color = C>, AppError>
 where
     D: DrawTarget<Color = C> + 'static,
     // C: PixelColor + RgbColor + 'static
     C: Color + 'static,
 {
-    apps::SplashScreen::new([
+    let splash = apps::SplashScreen::try_new([
         include_bytes!("../assets/MicroRascon.qoi"),
         include_bytes!("../assets/MicroRascon_Text.qoi"),
-    ])
+    ])?;
+    Ok(splash.with_music(AudioID::MusicDepp))
 }
 
-pub fn main_app<'a, D, C>() -> impl App<Target = D, Color = C>
+/// All apps of the firmware. Register new apps here, the menus are built from it. Games keep their
+/// scores in `high_scores`, all apps are laid out for `display`.
+pub fn registry<D, C>(high_scores: &HighScores, display: DisplayInfo) -> Registry<D, C>
 where
     D: DrawTarget<Color = C> + 'static,
-    // C: PixelColor + RgbColor + 'static
     C: Color + 'static,
 {
-    let games_menu = apps::Menu::new([
-        apps::MenuEntry {
-            name: "Pong",
-            app: Box::new(games::Pong::<D, C>::new(64, 32)),
-        },
-        apps::MenuEntry {
-            name: "Schnek",
-            app: Box::new(games::Snake::<64, 32, 32, D, C>::new()),
-        },
-    ]);
-    let animation_menu = apps::Menu::new([
-        apps::MenuEntry {
-            name: "Nyankatz",
-            app: Box::new(apps::Animation::new(
-                [
-                    include_bytes!("../assets/nyan/01.qoi"),
-                    include_bytes!("../assets/nyan/02.qoi"),
-                    include_bytes!("../assets/nyan/03.qoi"),
-                    include_bytes!("../assets/nyan/04.qoi"),
-                    include_bytes!("../assets/nyan/05.qoi"),
-                    include_bytes!("../assets/nyan/06.qoi"),
-                    include_bytes!("../assets/nyan/07.qoi"),
-                    include_bytes!("../assets/nyan/08.qoi"),
-                    include_bytes!("../assets/nyan/09.qoi"),
-                    include_bytes!("../assets/nyan/10.qoi"),
-                    include_bytes!("../assets/nyan/11.qoi"),
-                    include_bytes!("../assets/nyan/12.qoi"),
-                ],
-                AudioID::MusicNyan,
-            )),
-        },
-        apps::MenuEntry {
-            name: "Hyper!",
-            app: Box::new(apps::Animation::new(
-                [
-                    include_bytes!("../assets/hyperspace/0001.qoi"),
-                    include_bytes!("../assets/hyperspace/0002.qoi"),
-                    include_bytes!("../assets/hyperspace/0003.qoi"),
-                    include_bytes!("../assets/hyperspace/0004.qoi"),
-                    include_bytes!("../assets/hyperspace/0005.qoi"),
-                    include_bytes!("../assets/hyperspace/0006.qoi"),
-                    include_bytes!("../assets/hyperspace/0007.qoi"),
-                    include_bytes!("../assets/hyperspace/0008.qoi"),
-                    include_bytes!("../assets/hyperspace/0009.qoi"),
-                    include_bytes!("../assets/hyperspace/0010.qoi"),
-                    include_bytes!("../assets/hyperspace/0011.qoi"),
-                    include_bytes!("../assets/hyperspace/0012.qoi"),
-                    include_bytes!("../assets/hyperspace/0013.qoi"),
-                    include_bytes!("../assets/hyperspace/0014.qoi"),
-                ],
-                AudioID::Stop,
-            )),
-        },
-        apps::MenuEntry {
-            name: "A break",
-            app: Box::new(apps::Animation::new(
-                [
-                    include_bytes!("../assets/fire2/0001.qoi"),
-                    include_bytes!("../assets/fire2/0002.qoi"),
-                    include_bytes!("../assets/fire2/0003.qoi"),
-                    include_bytes!("../assets/fire2/0004.qoi"),
-                ],
-                AudioID::Stop,
-            )),
-        }, 
-        //  TODO: With love 
+    let mut registry = Registry::new();
+    let pong_scores = high_scores.clone();
+    let snake_scores = high_scores.clone();
+    registry
+        .register(Registration::of(move || {
+            games::Pong::new(display).with_high_scores(pong_scores.clone())
+        }))
+        .register(Registration::of(move || {
+            games::Snake::<32, D, C>::new(display).with_high_scores(snake_scores.clone())
+        }))
+        .register(Registration::placeholder(games::SpaceInvader::INFO))
+        .register(Registration::placeholder(games::GameBoy::INFO))
+        .register(Registration::try_new(
+            AppInfo::new("nyan", "Nyankatz", Category::Animations).with_audio(),
+            || {
+                apps::Animation::try_new(
+                    [
+                        include_bytes!("../assets/nyan/01.qoi"),
+                        include_bytes!("../assets/nyan/02.qoi"),
+                        include_bytes!("../assets/nyan/03.qoi"),
+                        include_bytes!("../assets/nyan/04.qoi"),
+                        include_bytes!("../assets/nyan/05.qoi"),
+                        include_bytes!("../assets/nyan/06.qoi"),
+                        include_bytes!("../assets/nyan/07.qoi"),
+                        include_bytes!("../assets/nyan/08.qoi"),
+                        include_bytes!("../assets/nyan/09.qoi"),
+                        include_bytes!("../assets/nyan/10.qoi"),
+                        include_bytes!("../assets/nyan/11.qoi"),
+                        include_bytes!("../assets/nyan/12.qoi"),
+                    ],
+                    AudioID::MusicNyan,
+                )
+            },
+        ))
+        .register(Registration::try_new(
+            AppInfo::new("hyperspace", "Hyper!", Category::Animations),
+            || {
+                apps::Animation::try_new(
+                    [
+                        include_bytes!("../assets/hyperspace/0001.qoi"),
+                        include_bytes!("../assets/hyperspace/0002.qoi"),
+                        include_bytes!("../assets/hyperspace/0003.qoi"),
+                        include_bytes!("../assets/hyperspace/0004.qoi"),
+                        include_bytes!("../assets/hyperspace/0005.qoi"),
+                        include_bytes!("../assets/hyperspace/0006.qoi"),
+                        include_bytes!("../assets/hyperspace/0007.qoi"),
+                        include_bytes!("../assets/hyperspace/0008.qoi"),
+                        include_bytes!("../assets/hyperspace/0009.qoi"),
+                        include_bytes!("../assets/hyperspace/0010.qoi"),
+                        include_bytes!("../assets/hyperspace/0011.qoi"),
+                        include_bytes!("../assets/hyperspace/0012.qoi"),
+                        include_bytes!("../assets/hyperspace/0013.qoi"),
+                        include_bytes!("../assets/hyperspace/0014.qoi"),
+                    ],
+                    AudioID::Stop,
+                )
+            },
+        ))
+        .register(Registration::try_new(
+            AppInfo::new("fire", "A break", Category::Animations),
+            || {
+                apps::Animation::try_new(
+                    [
+                        include_bytes!("../assets/fire2/0001.qoi"),
+                        include_bytes!("../assets/fire2/0002.qoi"),
+                        include_bytes!("../assets/fire2/0003.qoi"),
+                        include_bytes!("../assets/fire2/0004.qoi"),
+                    ],
+                    AudioID::Stop,
+                )
+            },
+        ))
+        //  TODO: With love
         //  - Pati, Elena, Manuel, David
-    ]);
+        .register(Registration::of(move || {
+            apps::ScrollingText::<D, C, _>::new(
+                display,
+                const_str::split!(include_str!("../assets/names.txt"), "\n"),
+            )
+        }));
+    registry
+}
 
-    let scrolling: apps::ScrollingText<D, C, _> =
-        apps::ScrollingText::new(const_str::split!(include_str!("../assets/names.txt"), "\n"));
+/// Length of the transitions between the menus and the apps they open
+const MENU_TRANSITION_US: i64 = 250_000;
 
-    let mut m = apps::Menu::new([
-        apps::MenuEntry {
-            name: "<3",
-            app: Box::new(scrolling),
-        },
-        apps::MenuEntry {
-            name: "Imageine",
-            app: Box::new(animation_menu),
-        },
-        // apps::MenuEntry {
-        //     name: "Games",
-        //     app: Box::new(games_menu),
-        // },
-        apps::MenuEntry {
-            name: "Pong",
-            app: Box::new(games::Pong::<D, C>::new(64, 32)),
+/// Builds a menu scene for every category of the `registry` and a launcher to open them. The
+/// settings app starts with `settings` and the start app of them is opened right away. The high
+/// score tables are read from `storage`, if there is one. S pauses whatever is running. Everything
+/// is laid out for `display`.
+pub fn main_app<'a, D, C, S>(
+    settings: &Settings,
+    storage: Option<&mut S>,
+    display: DisplayInfo,
+) -> impl App<Target = D, Color = C>
+where
+    D: DrawTarget<Color = C> + Capture + 'static,
+    // C: PixelColor + RgbColor + 'static
+    C: Color + 'static,
+    S: Storage,
+{
+    let high_scores = HighScores::new();
+    let mut registry = registry::<D, C>(&high_scores, display);
+    let start_apps: alloc::vec::Vec<AppInfo> = registry
+        .iter()
+        .filter(|registration| registration.is_available())
+        .map(|registration| registration.info)
+        .collect();
+    let games: alloc::vec::Vec<AppInfo> = start_apps
+        .iter()
+        .filter(|info| info.has_high_scores)
+        .copied()
+        .collect();
+    if let Some(storage) = storage {
+        for game in &games {
+            high_scores.load(storage, game.id);
+        }
+    }
+    let viewer_scores = high_scores.clone();
+    registry.register(Registration::new(
+        <apps::HighScoresApp<D, C> as AppMeta>::INFO,
+        move || apps::HighScoresApp::new(viewer_scores.clone(), games.clone()),
+    ));
+    let initial_settings = settings.clone();
+    registry.register(Registration::new(
+        <apps::SettingsApp<D, C> as AppMeta>::INFO,
+        move || {
+            apps::SettingsApp::new(initial_settings.clone(), start_apps.clone())
+                .with_viewport(display.size)
         },
-    ]);
-    let _ = m.pre_select_entry(0);
-    m
+    ));
+    let mut navigator =
+        Navigator::new().with_transition(TransitionKind::Slide, MENU_TRANSITION_US);
+
+    let launcher_entries = Category::ALL.map(|category| {
+        let mut menu = apps::DynamicMenu::new()
+            .with_viewport(display.size)
+            .with_transition(TransitionKind::FadeThroughBlack, MENU_TRANSITION_US);
+        for registration in registry.in_category(category) {
+            let entry = match registration.build() {
+                Some(app) => apps::DynamicEntry::app(registration.info.name, app),
+                None => {
+                    let mut entry = apps::DynamicEntry::command(
+                        registration.info.name,
+                        Command::Notify("Coming soon"),
+                    );
+                    entry.enabled = false;
+                    entry
+                }
+            };
+            menu.push(entry);
+        }
+        let scene = navigator.add_scene(Box::new(menu));
+        let mut entry =
+            apps::MenuEntry::new(category.name(), Box::new(apps::OpenScene::new(scene)));
+        // The tile of a category shows the icon of its first app with one. A broken icon falls
+        // back to the tile with the initial.
+        let icon = registry
+            .in_category(category)
+            .find_map(|registration| registration.info.icon);
+        entry.icon = icon.and_then(|icon| error::decode_qoi(icon).ok());
+        entry
+    });
+
+    // The categories open scenes, which the navigator blends to, so the launcher has no
+    // transition of its own.
+    let launcher = apps::Launcher::new(launcher_entries).with_viewport(display.size);
+    let root_scene = navigator.add_scene(Box::new(launcher));
+    navigator.set_root(root_scene);
+
+    // A separate instance, so the one in the menu keeps its own state
+    let start_app = settings
+        .start_app()
+        .and_then(|id| registry.get(id))
+        .and_then(|start| Some((start.info.name, start.build()?)));
+    if let Some((name, app)) = start_app {
+        let start_scene = navigator.add_named_scene(name, app);
+        navigator.open_on_enter(start_scene);
+    }
+    apps::PauseOverlay::new(navigator, settings.clone())
 }
 
 fn add(left: u64, right: u64) -> u64 {
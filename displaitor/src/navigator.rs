@@ -0,0 +1,720 @@
+use alloc::vec::Vec;
+
+use embedded_graphics::{prelude::*, primitives::Rectangle};
+
+use crate::{
+    trait_app::{AppBoxed, Color, RenderStatus, UpdateResult},
+    App, AppClock, Capture, Command, Controls, Pacing, Transition, TransitionKind,
+};
+
+/// Handle of a scene registered with a [`Navigator`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, defmt::Format)]
+pub struct SceneId(usize);
+
+/// Navigation intent an app can return from `update`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, defmt::Format)]
+pub enum Navigation {
+    /// Open the scene on top of the current one.
+    Push(SceneId),
+    /// Close the current scene and return to the one below.
+    Pop,
+    /// Close the current scene and open the given one in its place.
+    Replace(SceneId),
+    /// Close everything above the root scene.
+    Home,
+}
+
+/// Owns all scenes and a stack of the currently opened ones. Only the top of the stack is updated
+/// and rendered. The bottom of the stack (the root) is never popped. Its close requests are
+/// ignored, so the navigator keeps running, e.g. when B is pressed in the root scene.
+///
+/// Lifecycle guarantees:
+/// - `reset_state` and `on_enter` are called on a scene right before it becomes the top of the
+///   stack by `Push` or `Replace`. The root is used as is and entered together with the navigator.
+/// - `on_exit` and `teardown` are called on a scene when it is removed from the stack.
+/// - A scene covered by a `Push` gets `on_pause` and is not updated until it is on top again,
+///   where it gets `on_resume` and continues where it left off.
+///
+/// Each scene gets its own time, which starts at zero when it is opened or restarted and stands
+/// still while it is covered. The heap a named scene uses is attributed to its name, see
+/// [`crate::attribute_heap_to`].
+///
+/// With a transition, `Push`, `Pop` and `Home` blend from the top scene to the next one. A popped
+/// scene is closed, once its view was captured. No scene is updated while the transition runs.
+pub struct Navigator<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    scenes: Vec<Scene<D, C>>,
+    stack: Vec<SceneId>,
+    /// Pushed on top of the root by the first `on_enter`
+    start: Option<SceneId>,
+    transition: Option<Transition<D, C>>,
+    /// `Pop` or `Home` waiting for the transition to capture the top
+    pending: Option<Navigation>,
+}
+
+struct Scene<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    app: AppBoxed<D, C>,
+    clock: AppClock,
+    name: Option<&'static str>,
+    /// App the heap usage was attributed to before the scene was opened
+    heap_app: Option<&'static str>,
+}
+
+impl<D, C> Navigator<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    pub fn new() -> Self {
+        Self {
+            scenes: Vec::new(),
+            stack: Vec::new(),
+            start: None,
+            transition: None,
+            pending: None,
+        }
+    }
+
+    /// Blends between the scenes, when one is pushed or popped. Also `Home` blends to the root.
+    pub fn with_transition(mut self, kind: TransitionKind, duration_us: i64) -> Self
+    where
+        D: Capture,
+    {
+        self.transition = Some(Transition::new(kind, duration_us));
+        self
+    }
+
+    /// Registers a scene, so it can later be opened with a [`Navigation`] intent.
+    pub fn add_scene(&mut self, scene: AppBoxed<D, C>) -> SceneId {
+        self.push_scene(None, scene)
+    }
+
+    /// Registers a scene, like [`Navigator::add_scene`], whose heap usage is attributed to `name`.
+    pub fn add_named_scene(&mut self, name: &'static str, scene: AppBoxed<D, C>) -> SceneId {
+        self.push_scene(Some(name), scene)
+    }
+
+    fn push_scene(&mut self, name: Option<&'static str>, scene: AppBoxed<D, C>) -> SceneId {
+        self.scenes.push(Scene {
+            app: scene,
+            clock: AppClock::new(),
+            name,
+            heap_app: None,
+        });
+        SceneId(self.scenes.len() - 1)
+    }
+
+    /// Makes `scene` the root of the stack. Everything currently opened is closed. The root is used
+    /// as is, so it can be prepared beforehand (e.g. a pre-selected menu entry).
+    pub fn set_root(&mut self, scene: SceneId) {
+        while let Some(id) = self.stack.pop() {
+            let _ = self.scenes[id.0].app.on_exit();
+            self.scenes[id.0].app.teardown();
+        }
+        self.scenes[scene.0].clock.reset();
+        self.stack.push(scene);
+    }
+
+    /// Opens `scene` on top of the root, when the navigator is entered the next time. Closing it
+    /// returns to the root as usual.
+    pub fn open_on_enter(&mut self, scene: SceneId) {
+        self.start = Some(scene);
+    }
+
+    /// Number of opened scenes, including the root.
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Applies the intent and returns the combined result of the triggered lifecycle hooks. `Pop`
+    /// and `Home` are applied with the next update, if the transition captures the top first.
+    pub fn navigate(&mut self, navigation: Navigation) -> UpdateResult {
+        let finished = self.finish_transition();
+        let depth = self.stack.len();
+        let result = match navigation {
+            Navigation::Pop | Navigation::Home if depth > 1 && self.request_transition() => {
+                self.pending = Some(navigation);
+                RenderStatus::VisibleChange.into()
+            }
+            Navigation::Push(_) => {
+                let pushed = self.apply(navigation);
+                if self.stack.len() > depth {
+                    self.request_transition();
+                }
+                pushed
+            }
+            _ => self.apply(navigation),
+        };
+        finished.merge(result)
+    }
+
+    fn request_transition(&mut self) -> bool {
+        self.transition
+            .as_mut()
+            .is_some_and(|transition| transition.request())
+    }
+
+    /// Stops the transition and applies the navigation waiting for it.
+    fn finish_transition(&mut self) -> UpdateResult {
+        if let Some(transition) = self.transition.as_mut() {
+            transition.stop();
+        }
+        match self.pending.take() {
+            Some(navigation) => self.apply(navigation),
+            None => RenderStatus::NoVisibleChange.into(),
+        }
+    }
+
+    fn transition_running(&self) -> bool {
+        self.transition
+            .as_ref()
+            .is_some_and(|transition| transition.is_running())
+    }
+
+    /// Scenes the transition blends from and to
+    fn transition_views(&self) -> (Option<SceneId>, Option<SceneId>) {
+        let top = self.stack.last().copied();
+        let below = self.stack.len().checked_sub(2).map(|i| self.stack[i]);
+        match self.pending {
+            Some(Navigation::Home) => (top, self.stack.first().copied()),
+            Some(_) => (top, below),
+            // Pushed
+            None => (below, top),
+        }
+    }
+
+    fn render_scene(&self, target: &mut D, scene: Option<SceneId>) {
+        if let Some(scene) = scene {
+            self.scenes[scene.0].app.render(target);
+        }
+    }
+
+    fn apply(&mut self, navigation: Navigation) -> UpdateResult {
+        match navigation {
+            Navigation::Push(id) => {
+                if !self.can_open(id) {
+                    return RenderStatus::NoVisibleChange.into();
+                }
+                let paused = match self.top_mut() {
+                    Some(top) => top.on_pause(),
+                    None => RenderStatus::NoVisibleChange.into(),
+                };
+                paused.merge(self.open(id))
+            }
+            Navigation::Pop => {
+                if self.stack.len() <= 1 {
+                    return RenderStatus::NoVisibleChange.into();
+                }
+                let closed = self.close_top();
+                closed.merge(self.top_mut().unwrap().on_resume())
+            }
+            Navigation::Replace(id) => {
+                if self.stack.len() <= 1 {
+                    // The root can't be replaced, so the new scene is opened on top of it.
+                    return self.apply(Navigation::Push(id));
+                }
+                if !self.can_open(id) {
+                    return RenderStatus::NoVisibleChange.into();
+                }
+                let closed = self.close_top();
+                closed.merge(self.open(id))
+            }
+            Navigation::Home => {
+                if self.stack.len() <= 1 {
+                    return RenderStatus::NoVisibleChange.into();
+                }
+                let mut result: UpdateResult = RenderStatus::NoVisibleChange.into();
+                while self.stack.len() > 1 {
+                    result = result.merge(self.close_top());
+                }
+                result.merge(self.top_mut().unwrap().on_resume())
+            }
+        }
+    }
+
+    fn can_open(&self, id: SceneId) -> bool {
+        // A scene can only be opened once, as it has a single state.
+        id.0 < self.scenes.len() && !self.stack.contains(&id)
+    }
+
+    fn open(&mut self, id: SceneId) -> UpdateResult {
+        let scene = &mut self.scenes[id.0];
+        scene.heap_app = crate::heap_app();
+        if let Some(name) = scene.name {
+            crate::attribute_heap_to(Some(name));
+        }
+        scene.app.reset_state();
+        scene.clock.reset();
+        let entered = scene.app.on_enter();
+        self.stack.push(id);
+        entered
+    }
+
+    fn close_top(&mut self) -> UpdateResult {
+        let id = self.stack.pop().unwrap();
+        let scene = &mut self.scenes[id.0];
+        let exited = scene.app.on_exit();
+        scene.app.teardown();
+        // Also undoes what the apps of the scene left attributed
+        crate::attribute_heap_to(scene.heap_app.take());
+        exited
+    }
+
+    fn top(&self) -> Option<&AppBoxed<D, C>> {
+        self.stack.last().map(|id| &self.scenes[id.0].app)
+    }
+
+    fn top_mut(&mut self) -> Option<&mut AppBoxed<D, C>> {
+        self.top_scene_mut().map(|scene| &mut scene.app)
+    }
+
+    fn top_scene_mut(&mut self) -> Option<&mut Scene<D, C>> {
+        self.stack.last().map(|id| &mut self.scenes[id.0])
+    }
+}
+
+impl<D, C> Default for Navigator<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D, C> App for Navigator<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    type Target = D;
+    type Color = C;
+
+    fn reset_state(&mut self) {
+        self.pending = None;
+        if let Some(transition) = self.transition.as_mut() {
+            transition.stop();
+        }
+        while self.stack.len() > 1 {
+            let _ = self.close_top();
+        }
+        if let Some(root) = self.top_scene_mut() {
+            root.app.reset_state();
+            root.clock.reset();
+        }
+    }
+
+    fn update(&mut self, dt: i64, _t: i64, controls: &Controls) -> UpdateResult {
+        if let Some(transition) = self.transition.as_mut() {
+            if transition.update(dt) {
+                let captured = transition.is_captured();
+                return match self.pending.take() {
+                    Some(navigation) if captured => self
+                        .apply(navigation)
+                        .merge(RenderStatus::VisibleChange.into()),
+                    pending => {
+                        self.pending = pending;
+                        RenderStatus::VisibleChange.into()
+                    }
+                };
+            }
+        }
+        // The transition couldn't capture the views
+        if let Some(navigation) = self.pending.take() {
+            return self
+                .apply(navigation)
+                .merge(RenderStatus::VisibleChange.into());
+        }
+
+        let close_top = match self.top() {
+            Some(top) => self.stack.len() > 1 && top.close_request(),
+            None => return RenderStatus::NoVisibleChange.into(),
+        };
+        if close_top {
+            let popped = self.navigate(Navigation::Pop);
+            return popped.merge(RenderStatus::VisibleChange.into());
+        }
+
+        let top = self.top_scene_mut().unwrap();
+        let dt = top.clock.advance(dt);
+        let mut result = top.app.update(dt, top.clock.t_us(), controls);
+        for navigation in result.take_navigation() {
+            let navigated = self.navigate(navigation);
+            result = result.merge(navigated).merge(RenderStatus::VisibleChange.into());
+        }
+        result
+    }
+
+    fn render(&self, target: &mut Self::Target) {
+        if let Some(transition) = self.transition.as_ref() {
+            let (outgoing, incoming) = self.transition_views();
+            let rendered = transition.render(
+                target,
+                |target| self.render_scene(target, outgoing),
+                |target| self.render_scene(target, incoming),
+            );
+            if rendered {
+                return;
+            }
+        }
+        if let Some(top) = self.top() {
+            top.render(target);
+        }
+    }
+
+    fn render_region(&self, target: &mut Self::Target, area: &Rectangle) {
+        if self.transition_running() {
+            return self.render(target);
+        }
+        if let Some(top) = self.top() {
+            top.render_region(target, area);
+        }
+    }
+
+    fn teardown(&mut self) {
+        for id in self.stack.iter().rev() {
+            self.scenes[id.0].app.teardown();
+        }
+        if let Some(id) = self.stack.get(1) {
+            crate::attribute_heap_to(self.scenes[id.0].heap_app.take());
+        }
+        // Keep the root, so the navigator can be used again after `reset_state`.
+        self.stack.truncate(1);
+    }
+
+    /// Only without a root
+    fn close_request(&self) -> bool {
+        self.top().is_none()
+    }
+
+    fn pacing(&self) -> Pacing {
+        match self.top() {
+            Some(top) if !self.transition_running() => top.pacing(),
+            _ => Pacing::DEFAULT,
+        }
+    }
+
+    fn active_app_name(&self) -> Option<&str> {
+        self.top()?.active_app_name()
+    }
+
+    fn on_enter(&mut self) -> UpdateResult {
+        let entered = match self.top_mut() {
+            Some(top) => top.on_enter(),
+            None => return RenderStatus::NoVisibleChange.into(),
+        };
+        // Shown right away, without a transition
+        match self.start.take() {
+            Some(start) => entered.merge(self.apply(Navigation::Push(start))),
+            None => entered,
+        }
+    }
+
+    fn on_pause(&mut self) -> UpdateResult {
+        match self.top_mut() {
+            Some(top) => top.on_pause(),
+            None => RenderStatus::NoVisibleChange.into(),
+        }
+    }
+
+    fn on_resume(&mut self) -> UpdateResult {
+        match self.top_mut() {
+            Some(top) => top.on_resume(),
+            None => RenderStatus::NoVisibleChange.into(),
+        }
+    }
+
+    fn restart(&mut self) -> UpdateResult {
+        let finished = self.finish_transition();
+        match self.top_scene_mut() {
+            Some(top) => {
+                top.clock.reset();
+                finished.merge(top.app.restart())
+            }
+            None => finished,
+        }
+    }
+
+    fn close_inner(&mut self) -> Option<UpdateResult> {
+        let finished = self.finish_transition();
+        if let Some(closed) = self.top_mut()?.close_inner() {
+            return Some(finished.merge(closed));
+        }
+        // The root is never closed, like for its close requests
+        if self.stack.len() <= 1 {
+            return None;
+        }
+        let popped = self.navigate(Navigation::Pop);
+        Some(popped.merge(RenderStatus::VisibleChange.into()))
+    }
+
+    fn on_exit(&mut self) -> UpdateResult {
+        let mut result = self.finish_transition();
+        while self.stack.len() > 1 {
+            result = result.merge(self.close_top());
+        }
+        match self.top_mut() {
+            Some(root) => result.merge(root.on_exit()),
+            None => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{boxed::Box, rc::Rc, vec::Vec};
+    use core::cell::RefCell;
+
+    use embedded_graphics::{mock_display::MockDisplay, pixelcolor::Rgb565};
+
+    use super::*;
+
+    type Log = Rc<RefCell<Vec<(usize, &'static str)>>>;
+
+    /// Records lifecycle calls and returns `intent` on its first update.
+    struct Probe {
+        id: usize,
+        log: Log,
+        intent: Option<Navigation>,
+        closing: bool,
+    }
+
+    impl App for Probe {
+        type Target = MockDisplay<Rgb565>;
+        type Color = Rgb565;
+
+        fn reset_state(&mut self) {
+            self.log.borrow_mut().push((self.id, "reset"));
+        }
+
+        fn update(&mut self, _dt: i64, _t: i64, _controls: &Controls) -> UpdateResult {
+            self.log.borrow_mut().push((self.id, "update"));
+            let mut result: UpdateResult = RenderStatus::NoVisibleChange.into();
+            if let Some(intent) = self.intent.take() {
+                result.push_command(Command::Navigate(intent));
+            }
+            result
+        }
+
+        fn render(&self, _target: &mut Self::Target) {}
+
+        fn teardown(&mut self) {
+            self.log.borrow_mut().push((self.id, "teardown"));
+        }
+
+        fn close_request(&self) -> bool {
+            self.closing
+        }
+
+        fn on_enter(&mut self) -> UpdateResult {
+            self.log.borrow_mut().push((self.id, "enter"));
+            RenderStatus::NoVisibleChange.into()
+        }
+
+        fn on_pause(&mut self) -> UpdateResult {
+            self.log.borrow_mut().push((self.id, "pause"));
+            RenderStatus::NoVisibleChange.into()
+        }
+
+        fn on_resume(&mut self) -> UpdateResult {
+            self.log.borrow_mut().push((self.id, "resume"));
+            RenderStatus::NoVisibleChange.into()
+        }
+
+        fn on_exit(&mut self) -> UpdateResult {
+            self.log.borrow_mut().push((self.id, "exit"));
+            RenderStatus::NoVisibleChange.into()
+        }
+    }
+
+    fn probe(id: usize, log: &Log, intent: Option<Navigation>) -> Box<Probe> {
+        Box::new(Probe {
+            id,
+            log: log.clone(),
+            intent,
+            closing: false,
+        })
+    }
+
+    fn controls() -> Controls {
+        Controls::new(false, false, false, false, false, false, false)
+    }
+
+    #[test]
+    fn push_and_pop_order() {
+        let log = Log::default();
+        let mut navigator = Navigator::new();
+        let child = navigator.add_scene(probe(1, &log, Some(Navigation::Pop)));
+        let root = navigator.add_scene(probe(0, &log, Some(Navigation::Push(child))));
+        navigator.set_root(root);
+
+        let result = navigator.update(0, 0, &controls());
+        assert!(result.visible_changes());
+        assert_eq!(navigator.depth(), 2);
+
+        let _ = navigator.update(0, 0, &controls());
+        assert_eq!(navigator.depth(), 1);
+        assert_eq!(
+            *log.borrow(),
+            [
+                (0, "update"),
+                (0, "pause"),
+                (1, "reset"),
+                (1, "enter"),
+                (1, "update"),
+                (1, "exit"),
+                (1, "teardown"),
+                (0, "resume"),
+            ]
+        );
+    }
+
+    #[test]
+    fn replace_and_home_keep_root() {
+        let log = Log::default();
+        let mut navigator = Navigator::new();
+        let root = navigator.add_scene(probe(0, &log, None));
+        let a = navigator.add_scene(probe(1, &log, None));
+        let b = navigator.add_scene(probe(2, &log, None));
+        navigator.set_root(root);
+
+        let _ = navigator.navigate(Navigation::Push(a));
+        let _ = navigator.navigate(Navigation::Replace(b));
+        assert_eq!(navigator.depth(), 2);
+        let _ = navigator.navigate(Navigation::Push(a));
+        // A scene can't be on the stack twice
+        let _ = navigator.navigate(Navigation::Push(a));
+        assert_eq!(navigator.depth(), 3);
+
+        let _ = navigator.navigate(Navigation::Home);
+        let _ = navigator.navigate(Navigation::Pop);
+        assert_eq!(navigator.depth(), 1);
+        assert_eq!(
+            *log.borrow(),
+            [
+                (0, "pause"),
+                (1, "reset"),
+                (1, "enter"),
+                (1, "exit"),
+                (1, "teardown"),
+                (2, "reset"),
+                (2, "enter"),
+                (2, "pause"),
+                (1, "reset"),
+                (1, "enter"),
+                (1, "exit"),
+                (1, "teardown"),
+                (2, "exit"),
+                (2, "teardown"),
+                (0, "resume"),
+            ]
+        );
+    }
+
+    #[test]
+    fn start_scene_opens_on_enter() {
+        let log = Log::default();
+        let mut navigator = Navigator::new();
+        let root = navigator.add_scene(probe(0, &log, None));
+        let start = navigator.add_scene(probe(1, &log, None));
+        navigator.set_root(root);
+        navigator.open_on_enter(start);
+
+        let _ = navigator.on_enter();
+        assert_eq!(navigator.depth(), 2);
+        // Only the first enter opens it
+        let _ = navigator.navigate(Navigation::Pop);
+        let _ = navigator.on_enter();
+        assert_eq!(navigator.depth(), 1);
+        assert_eq!(
+            log.borrow()[..4],
+            [(0, "enter"), (0, "pause"), (1, "reset"), (1, "enter")]
+        );
+    }
+
+    #[test]
+    fn restart_and_close_inner_reach_the_top() {
+        let log = Log::default();
+        let mut navigator = Navigator::new();
+        let root = navigator.add_scene(probe(0, &log, None));
+        let child = navigator.add_scene(probe(1, &log, None));
+        navigator.set_root(root);
+        let _ = navigator.navigate(Navigation::Push(child));
+        log.borrow_mut().clear();
+
+        let _ = navigator.restart();
+        assert!(navigator.close_inner().is_some());
+        // The root is never closed
+        assert!(navigator.close_inner().is_none());
+        assert_eq!(navigator.depth(), 1);
+        assert_eq!(
+            *log.borrow(),
+            [
+                (1, "exit"),
+                (1, "teardown"),
+                (1, "reset"),
+                (1, "enter"),
+                (1, "exit"),
+                (1, "teardown"),
+                (0, "resume"),
+            ]
+        );
+    }
+
+    type Canvas = crate::Panels<crate::FrameBuffer<8, 4, Rgb565>>;
+
+    /// Fills the canvas with its color
+    struct Filled(Rgb565);
+
+    impl App for Filled {
+        type Target = Canvas;
+        type Color = Rgb565;
+
+        fn reset_state(&mut self) {}
+
+        fn update(&mut self, _dt: i64, _t: i64, _controls: &Controls) -> UpdateResult {
+            RenderStatus::NoVisibleChange.into()
+        }
+
+        fn render(&self, target: &mut Self::Target) {
+            let _ = target.clear(self.0);
+        }
+    }
+
+    #[test]
+    fn transitions_pop_once_the_top_is_captured() {
+        let mut navigator = Navigator::new().with_transition(TransitionKind::Wipe, 1000);
+        let root = navigator.add_scene(Box::new(Filled(Rgb565::RED)));
+        let top = navigator.add_scene(Box::new(Filled(Rgb565::BLUE)));
+        navigator.set_root(root);
+        let layout = crate::PanelLayout::single(Size::new(8, 4));
+        let mut canvas = crate::Panels::new(crate::FrameBuffer::new(), layout);
+
+        let _ = navigator.navigate(Navigation::Push(top));
+        navigator.render(&mut canvas);
+        assert!(navigator.update(500, 0, &controls()).visible_changes());
+        navigator.render(&mut canvas);
+        // Half-way from the root to the pushed scene
+        assert_eq!(canvas.inner().pixel(Point::new(3, 0)), Some(Rgb565::BLUE));
+        assert_eq!(canvas.inner().pixel(Point::new(4, 0)), Some(Rgb565::RED));
+        let _ = navigator.update(500, 0, &controls());
+
+        let _ = navigator.navigate(Navigation::Pop);
+        let _ = navigator.update(0, 0, &controls());
+        assert_eq!(navigator.depth(), 2);
+        navigator.render(&mut canvas);
+        let _ = navigator.update(0, 0, &controls());
+        assert_eq!(navigator.depth(), 1);
+        let _ = navigator.update(1000, 0, &controls());
+        navigator.render(&mut canvas);
+        assert!(canvas.inner().pixels().iter().all(|pixel| *pixel == Rgb565::RED));
+    }
+}
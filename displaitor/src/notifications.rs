@@ -0,0 +1,377 @@
+use core::fmt::Write;
+
+use embedded_graphics::{
+    mono_font::{ascii::FONT_4X6, MonoTextStyle},
+    pixelcolor::Rgb888,
+    prelude::*,
+    primitives::{PrimitiveStyleBuilder, Rectangle},
+    text::{Baseline, Text},
+};
+
+use crate::{string_buffer::FixedBuffer, trait_app::Color, MenuTheme};
+
+/// Toasts waiting behind the one shown. Posting more drops the oldest.
+pub const MAX_QUEUED_TOASTS: usize = 4;
+/// How long a toast is shown, if not set otherwise
+pub const DEFAULT_TOAST_US: i64 = 2_000_000;
+/// Time a toast takes to slide in and out
+pub const SLIDE_US: i64 = 150_000;
+/// How long the status bar stays after a change
+pub const STATUS_BAR_US: i64 = 2_000_000;
+
+const CHAR_WIDTH: u32 = 4;
+const ICON_SIZE: u32 = 5;
+const TOAST_HEIGHT: u32 = 9;
+const STATUS_BAR_HEIGHT: u32 = 7;
+
+/// Tiny 5x5 pictures in front of the toasts.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, defmt::Format)]
+pub enum Icon {
+    Info,
+    Sound,
+    Muted,
+    Saved,
+    Warning,
+}
+
+impl Icon {
+    /// Pixels of each row, the highest of the five bits is the left column
+    fn rows(&self) -> [u8; ICON_SIZE as usize] {
+        match self {
+            Icon::Info => [0b00100, 0b00000, 0b00100, 0b00100, 0b00100],
+            Icon::Sound => [0b00010, 0b01110, 0b11111, 0b01110, 0b00010],
+            Icon::Muted => [0b10001, 0b01010, 0b00100, 0b01010, 0b10001],
+            Icon::Saved => [0b00000, 0b00001, 0b00010, 0b10100, 0b01000],
+            Icon::Warning => [0b00100, 0b00100, 0b00100, 0b00000, 0b00100],
+        }
+    }
+
+    fn color(&self) -> Rgb888 {
+        match self {
+            Icon::Info | Icon::Sound => Rgb888::CSS_DEEP_SKY_BLUE,
+            Icon::Muted | Icon::Warning => Rgb888::CSS_ORANGE_RED,
+            Icon::Saved => Rgb888::CSS_LIME,
+        }
+    }
+
+    fn draw<D, C>(&self, target: &mut D, top_left: Point)
+    where
+        D: DrawTarget<Color = C>,
+        C: Color,
+    {
+        let color = C::from(self.color());
+        let pixels = self
+            .rows()
+            .into_iter()
+            .enumerate()
+            .flat_map(move |(y, row)| {
+                (0..ICON_SIZE as i32)
+                    .filter(move |x| row & (1 << (ICON_SIZE as i32 - 1 - x)) != 0)
+                    .map(move |x| Pixel(top_left + Point::new(x, y as i32), color))
+            });
+        let _ = target.draw_iter(pixels);
+    }
+}
+
+/// Short message, which slides in over the current app. Apps post them with
+/// [`crate::Command::Toast`], the runtime with [`crate::Runtime::notify`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, defmt::Format)]
+pub struct Toast {
+    pub icon: Icon,
+    pub message: &'static str,
+    /// Time the toast is fully visible
+    pub duration_us: i64,
+}
+
+impl Toast {
+    pub const fn new(icon: Icon, message: &'static str) -> Self {
+        Self {
+            icon,
+            message,
+            duration_us: DEFAULT_TOAST_US,
+        }
+    }
+
+    pub const fn with_duration_us(mut self, duration_us: i64) -> Self {
+        self.duration_us = duration_us;
+        self
+    }
+}
+
+/// Shows the posted [`Toast`]s one after another along the bottom of the screen.
+pub struct Notifications {
+    screen: Size,
+    queue: heapless::Deque<Toast, MAX_QUEUED_TOASTS>,
+    /// The toast shown and how long it is shown already
+    current: Option<(Toast, i64)>,
+    /// Where the toast was drawn with the last update
+    drawn: Option<Rectangle>,
+}
+
+impl Notifications {
+    /// Toasts are laid out for a screen of `screen`.
+    pub fn new(screen: Size) -> Self {
+        Self {
+            screen,
+            queue: heapless::Deque::new(),
+            current: None,
+            drawn: None,
+        }
+    }
+
+    pub fn post(&mut self, toast: Toast) {
+        if self.queue.is_full() {
+            self.queue.pop_front();
+        }
+        let _ = self.queue.push_back(toast);
+    }
+
+    /// The toast shown
+    pub fn current(&self) -> Option<&Toast> {
+        self.current.as_ref().map(|(toast, _)| toast)
+    }
+
+    /// Advances the toasts by `dt_us`. Returns the area, which has to be redrawn.
+    pub fn update(&mut self, dt_us: i64) -> Option<Rectangle> {
+        let mut replaced = false;
+        if let Some((toast, shown_us)) = &mut self.current {
+            *shown_us += dt_us.max(0);
+            if *shown_us >= toast.duration_us + 2 * SLIDE_US {
+                self.current = None;
+                replaced = true;
+            }
+        }
+        if self.current.is_none() {
+            self.current = self.queue.pop_front().map(|toast| (toast, 0));
+            replaced |= self.current.is_some();
+        }
+
+        let before = self.drawn;
+        self.drawn = self.area();
+        match (before, self.drawn) {
+            (before, after) if before == after && !replaced => None,
+            (Some(before), Some(after)) => Some(envelope(&before, &after)),
+            (before, after) => before.or(after),
+        }
+    }
+
+    /// Where the toast is drawn to, if one is shown
+    pub fn area(&self) -> Option<Rectangle> {
+        let (toast, shown_us) = self.current.as_ref()?;
+        let visible_us = match *shown_us {
+            t if t < SLIDE_US => t,
+            t if t > toast.duration_us + SLIDE_US => toast.duration_us + 2 * SLIDE_US - t,
+            _ => SLIDE_US,
+        };
+        let visible = (TOAST_HEIGHT as i64 * visible_us / SLIDE_US) as u32;
+        let width =
+            (ICON_SIZE + 3 + toast.message.len() as u32 * CHAR_WIDTH + 2).min(self.screen.width);
+        let top_left = Point::new(
+            (self.screen.width - width) as i32 / 2,
+            self.screen.height as i32 - visible as i32,
+        );
+        Some(Rectangle::new(top_left, Size::new(width, TOAST_HEIGHT)))
+    }
+
+    pub fn draw<D, C>(&self, target: &mut D)
+    where
+        D: DrawTarget<Color = C>,
+        C: Color,
+    {
+        let (Some((toast, _)), Some(area)) = (&self.current, self.area()) else {
+            return;
+        };
+        let colors = MenuTheme::active().colors();
+        let _ = area
+            .into_styled(
+                PrimitiveStyleBuilder::new()
+                    .stroke_color(C::from(colors.indicator))
+                    .stroke_width(1)
+                    .fill_color(C::BLACK)
+                    .build(),
+            )
+            .draw(target);
+        let mut target = target.clipped(&area.offset(-1));
+        toast
+            .icon
+            .draw(&mut target, area.top_left + Point::new(2, 2));
+        let style = MonoTextStyle::new(&FONT_4X6, C::from(colors.text));
+        let position = area.top_left + Point::new(ICON_SIZE as i32 + 3, 2);
+        let _ =
+            Text::with_baseline(toast.message, position, style, Baseline::Top).draw(&mut target);
+    }
+}
+
+/// Strip along the top of the screen with the uptime and the volume. It hides itself a while after
+/// the last change.
+pub struct StatusBar {
+    width: u32,
+    uptime_us: i64,
+    volume: u8,
+    audio_enabled: bool,
+    /// Time left until it hides
+    remaining_us: i64,
+    /// Shown or changed since the last update
+    changed: bool,
+}
+
+impl StatusBar {
+    pub fn new(width: u32, volume: u8, audio_enabled: bool) -> Self {
+        Self {
+            width,
+            uptime_us: 0,
+            volume,
+            audio_enabled,
+            remaining_us: 0,
+            changed: false,
+        }
+    }
+
+    pub fn is_shown(&self) -> bool {
+        self.remaining_us > 0
+    }
+
+    /// Shows the bar for [`STATUS_BAR_US`]
+    pub fn show(&mut self) {
+        self.remaining_us = STATUS_BAR_US;
+        self.changed = true;
+    }
+
+    /// Shows the bar, if the audio settings changed.
+    pub fn set_audio(&mut self, volume: u8, audio_enabled: bool) {
+        if (volume, audio_enabled) != (self.volume, self.audio_enabled) {
+            self.volume = volume;
+            self.audio_enabled = audio_enabled;
+            self.show();
+        }
+    }
+
+    /// Advances the uptime. Returns the area, which has to be redrawn.
+    pub fn update(&mut self, dt_us: i64) -> Option<Rectangle> {
+        let dt_us = dt_us.max(0);
+        let was_shown = self.is_shown();
+        let second_before = self.uptime_us / 1_000_000;
+        self.uptime_us += dt_us;
+        self.remaining_us = (self.remaining_us - dt_us).max(0);
+        let second_changed = self.uptime_us / 1_000_000 != second_before;
+        let changed = core::mem::take(&mut self.changed);
+        (changed || was_shown != self.is_shown() || (self.is_shown() && second_changed))
+            .then(|| self.area())
+    }
+
+    pub fn area(&self) -> Rectangle {
+        Rectangle::new(Point::zero(), Size::new(self.width, STATUS_BAR_HEIGHT))
+    }
+
+    pub fn draw<D, C>(&self, target: &mut D)
+    where
+        D: DrawTarget<Color = C>,
+        C: Color,
+    {
+        if !self.is_shown() {
+            return;
+        }
+        let area = self.area();
+        let _ = target.fill_solid(&area, C::BLACK);
+        let style = MonoTextStyle::new(&FONT_4X6, C::from(MenuTheme::active().colors().text));
+
+        let mut uptime = FixedBuffer::<16>::new();
+        let seconds = self.uptime_us / 1_000_000;
+        let _ = write!(uptime, "{}:{:02}", seconds / 60, seconds % 60);
+        let _ = Text::with_baseline(uptime.as_str(), Point::new(1, 1), style, Baseline::Top)
+            .draw(target);
+
+        let right = self.width as i32 - 1;
+        if !self.audio_enabled {
+            Icon::Muted.draw(target, Point::new(right - ICON_SIZE as i32, 1));
+            return;
+        }
+        let mut volume = FixedBuffer::<8>::new();
+        let _ = write!(volume, "{}%", self.volume as u32 * 100 / 255);
+        let width = volume.as_str().len() as i32 * CHAR_WIDTH as i32;
+        let _ = Text::with_baseline(
+            volume.as_str(),
+            Point::new(right - width, 1),
+            style,
+            Baseline::Top,
+        )
+        .draw(target);
+        Icon::Sound.draw(target, Point::new(right - width - ICON_SIZE as i32 - 1, 1));
+    }
+}
+
+fn envelope(a: &Rectangle, b: &Rectangle) -> Rectangle {
+    let top_left = a.top_left.component_min(b.top_left);
+    let bottom_right = a
+        .bottom_right()
+        .unwrap_or(a.top_left)
+        .component_max(b.bottom_right().unwrap_or(b.top_left));
+    Rectangle::with_corners(top_left, bottom_right)
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::pixelcolor::Rgb565;
+
+    use super::*;
+    use crate::FrameBuffer;
+
+    const SCREEN: Size = Size::new(64, 32);
+
+    #[test]
+    fn toasts_slide_in_and_out_one_after_another() {
+        let mut notifications = Notifications::new(SCREEN);
+        assert_eq!(notifications.update(10_000), None);
+        notifications.post(Toast::new(Icon::Muted, "Sound off").with_duration_us(1_000_000));
+        notifications.post(Toast::new(Icon::Saved, "Saved"));
+
+        // Appears below the screen and slides up
+        let appeared = notifications.update(0).unwrap();
+        assert_eq!(appeared.top_left.y, 32);
+        let sliding = notifications.update(SLIDE_US / 3).unwrap();
+        assert_eq!(sliding.top_left.y, 29);
+        // The area before and after is redrawn
+        let redrawn = notifications.update(SLIDE_US).unwrap();
+        let shown = notifications.area().unwrap();
+        assert_eq!(redrawn.bottom_right(), Some(Point::new(54, 37)));
+        assert_eq!(shown.top_left.y, 32 - TOAST_HEIGHT as i32);
+        assert_eq!(shown.size.width, 5 + 3 + 9 * 4 + 2);
+        // Nothing changes while it is shown
+        assert_eq!(notifications.update(100_000), None);
+
+        // Slides out and the next one follows
+        let _ = notifications.update(900_000);
+        assert_eq!(notifications.current().unwrap().message, "Sound off");
+        let _ = notifications.update(SLIDE_US);
+        assert_eq!(notifications.current().unwrap().message, "Saved");
+    }
+
+    #[test]
+    fn drops_the_oldest_toasts() {
+        let mut notifications = Notifications::new(SCREEN);
+        for message in ["1", "2", "3", "4", "5"] {
+            notifications.post(Toast::new(Icon::Info, message));
+        }
+        let _ = notifications.update(0);
+        assert_eq!(notifications.current().unwrap().message, "2");
+    }
+
+    #[test]
+    fn status_bar_hides_itself() {
+        let mut status_bar = StatusBar::new(64, 255, true);
+        assert_eq!(status_bar.update(10_000), None);
+        status_bar.set_audio(255, true);
+        assert!(!status_bar.is_shown());
+
+        status_bar.set_audio(255, false);
+        assert_eq!(status_bar.update(10_000), Some(status_bar.area()));
+        let mut frame = FrameBuffer::<64, 32, Rgb565>::new();
+        let _ = frame.clear(Rgb565::BLUE);
+        status_bar.draw(&mut frame);
+        assert_eq!(frame.pixel(Point::new(0, 0)), Some(Rgb565::BLACK));
+        assert_eq!(frame.pixel(Point::new(0, 7)), Some(Rgb565::BLUE));
+
+        assert_eq!(status_bar.update(STATUS_BAR_US), Some(status_bar.area()));
+        assert!(!status_bar.is_shown());
+    }
+}
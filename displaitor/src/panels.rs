@@ -0,0 +1,537 @@
+use embedded_graphics::{prelude::*, primitives::Rectangle};
+
+use crate::{Capture, TransitionFrame};
+
+/// Most panels, which can be chained in a [`PanelLayout`]
+pub const MAX_PANELS: usize = 8;
+
+/// How the canvas is turned or flipped onto the panels. Rotations are clockwise.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, defmt::Format)]
+pub enum Orientation {
+    #[default]
+    Normal,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    /// Flipped left to right
+    MirrorX,
+    /// Flipped top to bottom
+    MirrorY,
+}
+
+impl Orientation {
+    pub const ALL: [Orientation; 6] = [
+        Orientation::Normal,
+        Orientation::Rotate90,
+        Orientation::Rotate180,
+        Orientation::Rotate270,
+        Orientation::MirrorX,
+        Orientation::MirrorY,
+    ];
+
+    /// The orientations, which keep the size of the canvas. Only these can be changed while the
+    /// apps run, they are laid out for a fixed size.
+    pub const SAME_SIZE: [Orientation; 4] = [
+        Orientation::Normal,
+        Orientation::Rotate180,
+        Orientation::MirrorX,
+        Orientation::MirrorY,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Orientation::Normal => "Normal",
+            Orientation::Rotate90 => "Rotate 90",
+            Orientation::Rotate180 => "Rotate 180",
+            Orientation::Rotate270 => "Rotate 270",
+            Orientation::MirrorX => "Mirror X",
+            Orientation::MirrorY => "Mirror Y",
+        }
+    }
+
+    pub fn swaps_axes(&self) -> bool {
+        matches!(self, Orientation::Rotate90 | Orientation::Rotate270)
+    }
+
+    pub(crate) fn from_u8(value: u8) -> Option<Orientation> {
+        Self::ALL
+            .into_iter()
+            .find(|orientation| *orientation as u8 == value)
+    }
+
+    /// Size of the source, which is turned onto `dest`.
+    pub fn source_size(&self, dest: Size) -> Size {
+        match self.swaps_axes() {
+            true => Size::new(dest.height, dest.width),
+            false => dest,
+        }
+    }
+
+    /// Where `point` of the source ends up on a destination of size `dest`.
+    pub fn apply(&self, point: Point, dest: Size) -> Point {
+        let (width, height) = (dest.width as i32, dest.height as i32);
+        let Point { x, y } = point;
+        match self {
+            Orientation::Normal => point,
+            Orientation::Rotate90 => Point::new(width - 1 - y, x),
+            Orientation::Rotate180 => Point::new(width - 1 - x, height - 1 - y),
+            Orientation::Rotate270 => Point::new(y, height - 1 - x),
+            Orientation::MirrorX => Point::new(width - 1 - x, y),
+            Orientation::MirrorY => Point::new(x, height - 1 - y),
+        }
+    }
+}
+
+/// Where a panel of the chain shows a part of the canvas.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Placement {
+    /// Top left corner of the panel on the untransformed canvas
+    top_left: Point,
+    /// How the panel is mounted. Rotations by 90° require square panels.
+    rotation: Orientation,
+}
+
+/// Maps a canvas onto panels of equal size, which are chained one after another.
+///
+/// The chain is addressed like a single wide panel: the first panel on the left, the next one to
+/// its right and so on, which is how chained HUB75 panels are driven. The canvas can be turned or
+/// mirrored as a whole with [`PanelLayout::set_orientation`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PanelLayout {
+    panel: Size,
+    /// In the order of the chain
+    placements: heapless::Vec<Placement, MAX_PANELS>,
+    /// Size of the untransformed canvas covered by the panels
+    size: Size,
+    orientation: Orientation,
+}
+
+impl PanelLayout {
+    /// A grid of `columns`x`rows` panels, filled row by row in the order of the chain.
+    fn grid(
+        panel: Size,
+        columns: u32,
+        rows: u32,
+        placement: impl Fn(u32, u32) -> Placement,
+    ) -> Self {
+        assert!(
+            (columns * rows) as usize <= MAX_PANELS,
+            "at most {} panels can be chained",
+            MAX_PANELS
+        );
+        let placements = (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .map(|(column, row)| placement(column, row))
+            .collect();
+        Self {
+            panel,
+            placements,
+            size: Size::new(panel.width * columns, panel.height * rows),
+            orientation: Orientation::Normal,
+        }
+    }
+
+    fn at(panel: Size, column: u32, row: u32, rotation: Orientation) -> Placement {
+        Placement {
+            top_left: Point::new((panel.width * column) as i32, (panel.height * row) as i32),
+            rotation,
+        }
+    }
+
+    pub fn single(panel: Size) -> Self {
+        Self::horizontal(panel, 1)
+    }
+
+    /// `count` panels side by side, the chain runs from left to right.
+    pub fn horizontal(panel: Size, count: u32) -> Self {
+        Self::grid(panel, count, 1, |column, row| {
+            Self::at(panel, column, row, Orientation::Normal)
+        })
+    }
+
+    /// `count` panels stacked, the chain runs from top to bottom.
+    pub fn vertical(panel: Size, count: u32) -> Self {
+        Self::grid(panel, 1, count, |column, row| {
+            Self::at(panel, column, row, Orientation::Normal)
+        })
+    }
+
+    /// `columns`x`rows` panels, the chain runs through them in a zigzag. The first row is run
+    /// from left to right, the next one from right to left with the panels mounted upside down,
+    /// which keeps the cables between the rows short.
+    pub fn serpentine(panel: Size, columns: u32, rows: u32) -> Self {
+        Self::grid(panel, columns, rows, |column, row| match row % 2 {
+            0 => Self::at(panel, column, row, Orientation::Normal),
+            _ => Self::at(panel, columns - 1 - column, row, Orientation::Rotate180),
+        })
+    }
+
+    /// Changes how the panel `index` of the chain is mounted.
+    pub fn with_panel_rotation(mut self, index: usize, rotation: Orientation) -> Self {
+        if let Some(placement) = self.placements.get_mut(index) {
+            placement.rotation = rotation;
+        }
+        self
+    }
+
+    pub fn with_orientation(mut self, orientation: Orientation) -> Self {
+        self.orientation = orientation;
+        self
+    }
+
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
+    /// Turns the canvas. The size of the canvas changes for rotations by 90°.
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        self.orientation = orientation;
+    }
+
+    pub fn panel_size(&self) -> Size {
+        self.panel
+    }
+
+    pub fn panel_count(&self) -> usize {
+        self.placements.len()
+    }
+
+    /// Size the apps draw to
+    pub fn canvas_size(&self) -> Size {
+        self.orientation.source_size(self.size)
+    }
+
+    /// Size of all panels addressed as one
+    pub fn chain_size(&self) -> Size {
+        Size::new(
+            self.panel.width * self.placements.len() as u32,
+            self.panel.height,
+        )
+    }
+
+    /// The panel and the point on it, which show `point` of the canvas. `None` outside of the
+    /// canvas.
+    pub fn map(&self, point: Point) -> Option<(usize, Point)> {
+        if !Rectangle::new(Point::zero(), self.canvas_size()).contains(point) {
+            return None;
+        }
+        let point = self.orientation.apply(point, self.size);
+        let panel = Rectangle::new(Point::zero(), self.panel);
+        self.placements
+            .iter()
+            .enumerate()
+            .find(|(_, placement)| panel.contains(point - placement.top_left))
+            .map(|(index, placement)| {
+                let local = point - placement.top_left;
+                (index, placement.rotation.apply(local, self.panel))
+            })
+    }
+
+    /// Where `point` of the canvas is on the chain.
+    pub fn to_chain(&self, point: Point) -> Option<Point> {
+        self.map(point)
+            .map(|(index, local)| local + Point::new(self.panel.width as i32 * index as i32, 0))
+    }
+
+    /// Whether canvas and chain are the same, so drawing can be passed through.
+    fn is_identity(&self) -> bool {
+        self.orientation == Orientation::Normal
+            && self.placements.len() == 1
+            && self.placements[0].rotation == Orientation::Normal
+    }
+}
+
+/// Wraps the target driving a chain of panels, so the apps draw to the canvas of a
+/// [`PanelLayout`]. Drawing can be redirected into a frame of the canvas, see [`Capture`].
+pub struct Panels<D: DrawTarget> {
+    target: D,
+    layout: PanelLayout,
+    capture: Option<TransitionFrame<D::Color>>,
+}
+
+impl<D: DrawTarget> Panels<D> {
+    pub fn new(target: D, layout: PanelLayout) -> Self {
+        Self {
+            target,
+            layout,
+            capture: None,
+        }
+    }
+
+    pub fn layout(&self) -> &PanelLayout {
+        &self.layout
+    }
+
+    /// Only affects what is drawn afterwards.
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        self.layout.set_orientation(orientation);
+    }
+
+    pub fn inner(&self) -> &D {
+        &self.target
+    }
+
+    pub fn inner_mut(&mut self) -> &mut D {
+        &mut self.target
+    }
+
+    pub fn into_inner(self) -> D {
+        self.target
+    }
+}
+
+impl<D: DrawTarget> OriginDimensions for Panels<D> {
+    fn size(&self) -> Size {
+        self.layout.canvas_size()
+    }
+}
+
+impl<D> DrawTarget for Panels<D>
+where
+    D: DrawTarget,
+    D::Color: RgbColor,
+{
+    type Color = D::Color;
+    type Error = D::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        if let Some(frame) = self.capture.as_mut() {
+            let _ = frame.draw_iter(pixels);
+            return Ok(());
+        }
+        let layout = &self.layout;
+        self.target
+            .draw_iter(pixels.into_iter().filter_map(|Pixel(point, color)| {
+                layout.to_chain(point).map(|point| Pixel(point, color))
+            }))
+    }
+
+    fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Self::Color>,
+    {
+        if let Some(frame) = self.capture.as_mut() {
+            let _ = frame.fill_contiguous(area, colors);
+            return Ok(());
+        }
+        if self.layout.is_identity() {
+            return self.target.fill_contiguous(area, colors);
+        }
+        self.draw_iter(
+            area.points()
+                .zip(colors)
+                .map(|(point, color)| Pixel(point, color)),
+        )
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        if let Some(frame) = self.capture.as_mut() {
+            let _ = frame.fill_solid(area, color);
+            return Ok(());
+        }
+        if self.layout.is_identity() {
+            return self.target.fill_solid(area, color);
+        }
+        let area = area.intersection(&self.bounding_box());
+        self.draw_iter(area.points().map(|point| Pixel(point, color)))
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        if let Some(frame) = self.capture.as_mut() {
+            let _ = frame.clear(color);
+            return Ok(());
+        }
+        // Every pixel of the chain belongs to the canvas
+        self.target.clear(color)
+    }
+}
+
+/// Captures in canvas coordinates, before the canvas is turned onto the panels.
+impl<D> Capture for Panels<D>
+where
+    D: DrawTarget,
+    D::Color: RgbColor,
+{
+    fn redirect(
+        &mut self,
+        frame: Option<TransitionFrame<D::Color>>,
+    ) -> Option<TransitionFrame<D::Color>> {
+        core::mem::replace(&mut self.capture, frame)
+    }
+}
+
+/// A chain made of `N` separate panel targets, e.g. one frame buffer or simulator window per
+/// panel. Drawing to the chain is split up between them like the hardware would.
+pub struct SplitChain<D, const N: usize> {
+    panels: [D; N],
+    panel: Size,
+}
+
+impl<D: Dimensions, const N: usize> SplitChain<D, N> {
+    /// The panels in the order of the chain, all of the size of the first one.
+    pub fn new(panels: [D; N]) -> Self {
+        let panel = panels
+            .first()
+            .map_or(Size::zero(), |panel| panel.bounding_box().size);
+        Self { panels, panel }
+    }
+
+    pub fn panels(&self) -> &[D; N] {
+        &self.panels
+    }
+
+    pub fn panels_mut(&mut self) -> &mut [D; N] {
+        &mut self.panels
+    }
+
+    pub fn into_panels(self) -> [D; N] {
+        self.panels
+    }
+}
+
+impl<D, const N: usize> OriginDimensions for SplitChain<D, N> {
+    fn size(&self) -> Size {
+        Size::new(self.panel.width * N as u32, self.panel.height)
+    }
+}
+
+impl<D: DrawTarget, const N: usize> DrawTarget for SplitChain<D, N> {
+    type Color = D::Color;
+    type Error = D::Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let width = self.panel.width.max(1) as i32;
+        for Pixel(point, color) in pixels {
+            if point.x < 0 {
+                continue;
+            }
+            let index = (point.x / width) as usize;
+            if let Some(panel) = self.panels.get_mut(index) {
+                let local = Point::new(point.x % width, point.y);
+                panel.draw_iter(core::iter::once(Pixel(local, color)))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        for panel in &mut self.panels {
+            panel.clear(color)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::{
+        pixelcolor::Rgb565,
+        primitives::{Line, PrimitiveStyle},
+    };
+
+    use super::*;
+    use crate::FrameBuffer;
+
+    type Panel = FrameBuffer<8, 4, Rgb565>;
+    const PANEL: Size = Size::new(8, 4);
+
+    fn panels<const N: usize>(layout: PanelLayout) -> Panels<SplitChain<Panel, N>> {
+        Panels::new(SplitChain::new([(); N].map(|_| Panel::new())), layout)
+    }
+
+    fn pixel<const N: usize>(
+        panels: &Panels<SplitChain<Panel, N>>,
+        index: usize,
+        point: Point,
+    ) -> Rgb565 {
+        panels.inner().panels()[index].pixel(point).unwrap()
+    }
+
+    #[test]
+    fn orientations_stay_on_the_destination() {
+        let dest = Size::new(4, 2);
+        for orientation in Orientation::ALL {
+            let source = Rectangle::new(Point::zero(), orientation.source_size(dest));
+            let mut mapped = source
+                .points()
+                .map(|point| orientation.apply(point, dest))
+                .collect::<alloc::vec::Vec<_>>();
+            mapped.sort_by_key(|point| (point.y, point.x));
+            let all = Rectangle::new(Point::zero(), dest)
+                .points()
+                .collect::<alloc::vec::Vec<_>>();
+            assert_eq!(mapped, all, "{:?}", orientation);
+        }
+        let rotate90 = Orientation::Rotate90;
+        assert_eq!(rotate90.apply(Point::zero(), dest), Point::new(3, 0));
+        assert_eq!(rotate90.apply(Point::new(1, 3), dest), Point::new(0, 1));
+    }
+
+    #[test]
+    fn splits_the_canvas_between_the_panels() {
+        let mut horizontal = panels::<2>(PanelLayout::horizontal(PANEL, 2));
+        assert_eq!(horizontal.size(), Size::new(16, 4));
+        let _ = horizontal.fill_solid(
+            &Rectangle::new(Point::new(6, 1), Size::new(4, 1)),
+            Rgb565::RED,
+        );
+        assert_eq!(pixel(&horizontal, 0, Point::new(7, 1)), Rgb565::RED);
+        assert_eq!(pixel(&horizontal, 1, Point::new(0, 1)), Rgb565::RED);
+        assert_eq!(pixel(&horizontal, 1, Point::new(2, 1)), Rgb565::BLACK);
+
+        let mut vertical = panels::<2>(PanelLayout::vertical(PANEL, 2));
+        assert_eq!(vertical.size(), Size::new(8, 8));
+        let _ = Pixel(Point::new(1, 5), Rgb565::GREEN).draw(&mut vertical);
+        assert_eq!(pixel(&vertical, 1, Point::new(1, 1)), Rgb565::GREEN);
+    }
+
+    #[test]
+    fn serpentine_rows_run_back_upside_down() {
+        let mut serpentine = panels::<4>(PanelLayout::serpentine(PANEL, 2, 2));
+        assert_eq!(serpentine.size(), Size::new(16, 8));
+        let _ = Line::new(Point::new(0, 4), Point::new(15, 4))
+            .into_styled(PrimitiveStyle::with_stroke(Rgb565::BLUE, 1))
+            .draw(&mut serpentine);
+        // The second row starts on the right with the third panel of the chain
+        assert_eq!(
+            serpentine.layout().map(Point::new(15, 4)),
+            Some((2, Point::new(0, 3)))
+        );
+        assert_eq!(
+            serpentine.layout().map(Point::new(0, 4)),
+            Some((3, Point::new(7, 3)))
+        );
+        for index in 2..4 {
+            assert_eq!(pixel(&serpentine, index, Point::new(4, 3)), Rgb565::BLUE);
+            assert_eq!(pixel(&serpentine, index, Point::new(4, 0)), Rgb565::BLACK);
+        }
+        assert_eq!(pixel(&serpentine, 0, Point::new(4, 3)), Rgb565::BLACK);
+    }
+
+    #[test]
+    fn orientation_can_be_changed_while_drawing() {
+        let mut single = panels::<1>(PanelLayout::single(PANEL));
+        single.set_orientation(Orientation::Rotate180);
+        assert_eq!(single.size(), PANEL);
+        let _ = Pixel(Point::new(0, 0), Rgb565::RED).draw(&mut single);
+        assert_eq!(pixel(&single, 0, Point::new(7, 3)), Rgb565::RED);
+
+        single.set_orientation(Orientation::Rotate90);
+        assert_eq!(single.size(), Size::new(4, 8));
+        let _ = Pixel(Point::new(0, 0), Rgb565::GREEN).draw(&mut single);
+        assert_eq!(pixel(&single, 0, Point::new(7, 0)), Rgb565::GREEN);
+
+        let mut rotated = panels::<2>(
+            PanelLayout::horizontal(PANEL, 2).with_panel_rotation(1, Orientation::MirrorX),
+        );
+        let _ = Pixel(Point::new(8, 0), Rgb565::BLUE).draw(&mut rotated);
+        assert_eq!(pixel(&rotated, 1, Point::new(7, 0)), Rgb565::BLUE);
+        assert_eq!(rotated.layout().map(Point::new(16, 0)), None);
+    }
+}
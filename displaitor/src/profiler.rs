@@ -0,0 +1,291 @@
+use embedded_graphics::{
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+};
+
+use crate::trait_app::Color;
+
+/// Buckets of a [`Timing`] histogram. The first one counts durations below 1 ms, each further one
+/// twice as long as the one before, the last one everything from 64 ms on.
+pub const HISTOGRAM_BUCKETS: usize = 8;
+/// Apps profiled separately. Frames of further apps only count into the total.
+pub const MAX_PROFILED_APPS: usize = 16;
+/// Longer app names are cut off
+pub const MAX_PROFILED_NAME_LEN: usize = 16;
+/// Height of the bars drawn by [`Profiler::draw_overlay`]
+pub const OVERLAY_HEIGHT: u32 = 2;
+
+/// Durations of a single frame of the loop.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, defmt::Format)]
+pub struct FrameTimes {
+    /// All updates run in the frame and the commands they issued
+    pub update_us: u32,
+    /// Drawing the app
+    pub render_us: u32,
+    /// Showing the frame, e.g. committing it to the panel driver
+    pub present_us: u32,
+}
+
+impl FrameTimes {
+    pub fn total_us(&self) -> u32 {
+        self.update_us + self.render_us + self.present_us
+    }
+}
+
+/// Minimum, average, maximum and histogram of a duration.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, defmt::Format)]
+pub struct Timing {
+    pub count: u32,
+    pub total_us: u64,
+    pub min_us: u32,
+    pub max_us: u32,
+    pub histogram: [u32; HISTOGRAM_BUCKETS],
+}
+
+impl Timing {
+    pub fn record(&mut self, us: u32) {
+        self.min_us = match self.count {
+            0 => us,
+            _ => self.min_us.min(us),
+        };
+        self.max_us = self.max_us.max(us);
+        self.count += 1;
+        self.total_us += us as u64;
+        self.histogram[Self::bucket(us)] += 1;
+    }
+
+    pub fn avg_us(&self) -> u32 {
+        (self.total_us / self.count.max(1) as u64) as u32
+    }
+
+    /// Histogram bucket of a duration
+    pub fn bucket(us: u32) -> usize {
+        match us / 1_000 {
+            0 => 0,
+            ms => (ms.ilog2() as usize + 1).min(HISTOGRAM_BUCKETS - 1),
+        }
+    }
+
+    /// Durations counted by `bucket` are below this. `None` for the last bucket.
+    pub fn bucket_limit_us(bucket: usize) -> Option<u32> {
+        (bucket < HISTOGRAM_BUCKETS - 1).then(|| 1_000 << bucket)
+    }
+}
+
+/// Timings of all phases of a frame.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, defmt::Format)]
+pub struct FrameProfile {
+    pub update: Timing,
+    pub render: Timing,
+    pub present: Timing,
+    /// The whole frame
+    pub frame: Timing,
+}
+
+impl FrameProfile {
+    pub fn record(&mut self, times: &FrameTimes) {
+        self.update.record(times.update_us);
+        self.render.record(times.render_us);
+        self.present.record(times.present_us);
+        self.frame.record(times.total_us());
+    }
+}
+
+/// Frames attributed to an app, see [`crate::App::active_app_name`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct AppProfile {
+    pub name: heapless::String<MAX_PROFILED_NAME_LEN>,
+    pub profile: FrameProfile,
+}
+
+/// Collects the [`FrameTimes`] of the loop, in total and for each app they are spent in. Finds the
+/// apps which blow the frame budget.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Profiler {
+    total: FrameProfile,
+    apps: heapless::Vec<AppProfile, MAX_PROFILED_APPS>,
+    last: FrameTimes,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds a frame, which was spent in the app named `app`.
+    pub fn record(&mut self, app: Option<&str>, times: FrameTimes) {
+        self.total.record(&times);
+        self.last = times;
+        let Some(name) = app.map(truncated) else {
+            return;
+        };
+        match self.apps.iter_mut().find(|profile| profile.name == name) {
+            Some(profile) => profile.profile.record(&times),
+            None => {
+                let mut profile = AppProfile {
+                    name: heapless::String::try_from(name).unwrap_or_default(),
+                    profile: FrameProfile::default(),
+                };
+                profile.profile.record(&times);
+                let _ = self.apps.push(profile);
+            }
+        }
+    }
+
+    /// All frames since the last reset
+    pub fn total(&self) -> &FrameProfile {
+        &self.total
+    }
+
+    pub fn apps(&self) -> &[AppProfile] {
+        &self.apps
+    }
+
+    pub fn app(&self, name: &str) -> Option<&FrameProfile> {
+        let name = truncated(name);
+        self.apps
+            .iter()
+            .find(|profile| profile.name == name)
+            .map(|profile| &profile.profile)
+    }
+
+    /// The most recent frame
+    pub fn last(&self) -> FrameTimes {
+        self.last
+    }
+
+    /// Starts a new period, e.g. after the timings were reported.
+    pub fn reset(&mut self) {
+        self.total = FrameProfile::default();
+        self.apps.clear();
+    }
+
+    /// Where [`Profiler::draw_overlay`] draws to on a screen of `size`: a strip along the bottom
+    pub fn overlay_area(size: Size) -> Rectangle {
+        let height = OVERLAY_HEIGHT.min(size.height);
+        Rectangle::new(
+            Point::new(0, (size.height - height) as i32),
+            Size::new(size.width, height),
+        )
+    }
+
+    /// Draws the phases of the last frame as bars into `area`: update green, render blue and
+    /// present yellow. The full width is `budget_us`, frames over budget are drawn red.
+    pub fn draw_overlay<D, C>(&self, target: &mut D, area: Rectangle, budget_us: u32)
+    where
+        D: DrawTarget<Color = C>,
+        C: Color,
+    {
+        let _ = target.fill_solid(&area, C::BLACK);
+        let budget_us = budget_us.max(1) as u64;
+        let width = |us: u32| (us as u64 * area.size.width as u64 / budget_us) as u32;
+        let over_budget = self.last.total_us() as u64 > budget_us;
+        let mut x = area.top_left.x;
+        for (us, color) in [
+            (self.last.update_us, C::GREEN),
+            (self.last.render_us, C::BLUE),
+            (self.last.present_us, C::YELLOW),
+        ] {
+            let color = if over_budget { C::RED } else { color };
+            let bar = Rectangle::new(
+                Point::new(x, area.top_left.y),
+                Size::new(width(us), area.size.height),
+            )
+            .intersection(&area);
+            let _ = bar
+                .into_styled(PrimitiveStyle::with_fill(color))
+                .draw(target);
+            x += bar.size.width as i32;
+        }
+    }
+}
+
+/// Cut off at [`MAX_PROFILED_NAME_LEN`], on a character boundary
+fn truncated(name: &str) -> &str {
+    let mut end = name.len().min(MAX_PROFILED_NAME_LEN);
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    &name[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::pixelcolor::Rgb565;
+
+    use super::*;
+    use crate::FrameBuffer;
+
+    fn times(update_us: u32, render_us: u32, present_us: u32) -> FrameTimes {
+        FrameTimes {
+            update_us,
+            render_us,
+            present_us,
+        }
+    }
+
+    #[test]
+    fn collects_timings_per_app() {
+        let mut profiler = Profiler::new();
+        profiler.record(Some("pong"), times(1_000, 4_000, 500));
+        profiler.record(Some("pong"), times(3_000, 6_000, 500));
+        profiler.record(Some("snake"), times(500, 20_000, 500));
+        profiler.record(None, times(0, 100, 0));
+
+        let total = profiler.total();
+        assert_eq!(total.frame.count, 4);
+        assert_eq!((total.render.min_us, total.render.max_us), (100, 20_000));
+        let pong = profiler.app("pong").unwrap();
+        assert_eq!(pong.update.avg_us(), 2_000);
+        assert_eq!(pong.frame.max_us, 9_500);
+        // 5.5 ms is below 8 ms, 9.5 ms below 16 ms
+        assert_eq!(pong.frame.histogram[3..5], [1, 1]);
+        assert_eq!(profiler.apps().len(), 2);
+
+        profiler.reset();
+        assert_eq!(profiler.total().frame.count, 0);
+        assert!(profiler.apps().is_empty());
+        assert_eq!(profiler.last(), times(0, 100, 0));
+    }
+
+    #[test]
+    fn histogram_buckets() {
+        assert_eq!(Timing::bucket(999), 0);
+        assert_eq!(Timing::bucket(1_000), 1);
+        assert_eq!(Timing::bucket(3_999), 2);
+        assert_eq!(Timing::bucket(1_000_000), HISTOGRAM_BUCKETS - 1);
+        assert_eq!(Timing::bucket_limit_us(2), Some(4_000));
+        assert_eq!(Timing::bucket_limit_us(HISTOGRAM_BUCKETS - 1), None);
+    }
+
+    #[test]
+    fn draws_the_last_frame() {
+        let mut profiler = Profiler::new();
+        profiler.record(None, times(5_000, 10_000, 5_000));
+        let mut frame = FrameBuffer::<8, 4, Rgb565>::new();
+        let area = Profiler::overlay_area(Size::new(8, 4));
+        assert_eq!(area, Rectangle::new(Point::new(0, 2), Size::new(8, 2)));
+
+        profiler.draw_overlay(&mut frame, area, 40_000);
+        let row: heapless::Vec<_, 8> = (0..8)
+            .map(|x| frame.pixel(Point::new(x, 3)).unwrap())
+            .collect();
+        assert_eq!(
+            row,
+            [
+                Rgb565::GREEN,
+                Rgb565::BLUE,
+                Rgb565::BLUE,
+                Rgb565::YELLOW,
+                Rgb565::BLACK,
+                Rgb565::BLACK,
+                Rgb565::BLACK,
+                Rgb565::BLACK
+            ]
+        );
+
+        // Over budget
+        profiler.draw_overlay(&mut frame, area, 10_000);
+        assert_eq!(frame.pixel(Point::new(7, 2)), Some(Rgb565::RED));
+    }
+}
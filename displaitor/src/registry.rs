@@ -0,0 +1,259 @@
+use alloc::{boxed::Box, vec::Vec};
+
+use embedded_graphics::prelude::*;
+
+use crate::{
+    apps::BrokenApp,
+    trait_app::{AppBoxed, Color},
+    try_box, App, AppError,
+};
+
+/// Group of apps, each category gets its own menu.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, defmt::Format)]
+pub enum Category {
+    Games,
+    Animations,
+    Messages,
+    System,
+}
+
+impl Category {
+    /// All categories in the order they are shown.
+    pub const ALL: [Category; 4] = [
+        Category::Messages,
+        Category::Animations,
+        Category::Games,
+        Category::System,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Category::Games => "Games",
+            Category::Animations => "Imageine",
+            Category::Messages => "<3",
+            Category::System => "Setup",
+        }
+    }
+}
+
+/// Description of an app, shown by menus and the launcher.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct AppInfo {
+    /// Unique and stable, e.g. as key for settings or high scores
+    pub id: &'static str,
+    pub name: &'static str,
+    pub category: Category,
+    /// QOI data of a 16x16 icon
+    pub icon: Option<&'static [u8]>,
+    pub uses_audio: bool,
+    /// Keeps a table in [`crate::HighScores`] under its id
+    pub has_high_scores: bool,
+}
+
+impl AppInfo {
+    pub const fn new(id: &'static str, name: &'static str, category: Category) -> Self {
+        Self {
+            id,
+            name,
+            category,
+            icon: None,
+            uses_audio: false,
+            has_high_scores: false,
+        }
+    }
+
+    pub const fn with_icon(mut self, qoi_data: &'static [u8]) -> Self {
+        self.icon = Some(qoi_data);
+        self
+    }
+
+    pub const fn with_audio(mut self) -> Self {
+        self.uses_audio = true;
+        self
+    }
+
+    pub const fn with_high_scores(mut self) -> Self {
+        self.has_high_scores = true;
+        self
+    }
+}
+
+/// Implemented by app types to describe themselves.
+pub trait AppMeta {
+    const INFO: AppInfo;
+}
+
+/// Creates a new instance of an app
+type Build<D, C> = Box<dyn Fn() -> Result<AppBoxed<D, C>, AppError>>;
+
+/// An app known to the [`Registry`] and how to create it.
+pub struct Registration<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    pub info: AppInfo,
+    build: Option<Build<D, C>>,
+}
+
+impl<D, C> Registration<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    pub fn new<A>(info: AppInfo, build: impl Fn() -> A + 'static) -> Self
+    where
+        A: App<Target = D, Color = C> + 'static,
+    {
+        Self::try_new(info, move || Ok(build()))
+    }
+
+    /// Registers an app, which may fail to build, e.g. because an asset is broken.
+    pub fn try_new<A>(info: AppInfo, build: impl Fn() -> Result<A, AppError> + 'static) -> Self
+    where
+        A: App<Target = D, Color = C> + 'static,
+    {
+        Self {
+            info,
+            build: Some(Box::new(move || try_box(build()?))),
+        }
+    }
+
+    /// Registers an app type with its own [`AppInfo`].
+    pub fn of<A>(build: impl Fn() -> A + 'static) -> Self
+    where
+        A: App<Target = D, Color = C> + AppMeta + 'static,
+    {
+        Self::new(A::INFO, build)
+    }
+
+    /// An app which is announced, but can't be started yet.
+    pub fn placeholder(info: AppInfo) -> Self {
+        Self { info, build: None }
+    }
+
+    pub fn is_available(&self) -> bool {
+        self.build.is_some()
+    }
+
+    /// Creates a new instance of the app. `None` for placeholders.
+    pub fn try_build(&self) -> Option<Result<AppBoxed<D, C>, AppError>> {
+        self.build.as_ref().map(|build| build())
+    }
+
+    /// Like [`Registration::try_build`], but an app which fails to build is replaced by a
+    /// [`BrokenApp`], so menus can still offer it.
+    pub fn build(&self) -> Option<AppBoxed<D, C>>
+    where
+        D: 'static,
+        C: 'static,
+    {
+        let app = match self.try_build()? {
+            Ok(app) => app,
+            Err(error) => Box::new(BrokenApp::new(self.info.name, error)),
+        };
+        Some(app)
+    }
+}
+
+/// All apps of the firmware. Menus are built from it by category.
+pub struct Registry<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    registrations: Vec<Registration<D, C>>,
+}
+
+impl<D, C> Registry<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    pub fn new() -> Self {
+        Self {
+            registrations: Vec::new(),
+        }
+    }
+
+    pub fn register(&mut self, registration: Registration<D, C>) -> &mut Self {
+        debug_assert!(
+            self.get(registration.info.id).is_none(),
+            "App id registered twice"
+        );
+        self.registrations.push(registration);
+        self
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Registration<D, C>> {
+        self.registrations.iter()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Registration<D, C>> {
+        self.registrations.iter().find(|r| r.info.id == id)
+    }
+
+    pub fn in_category(&self, category: Category) -> impl Iterator<Item = &Registration<D, C>> {
+        self.registrations
+            .iter()
+            .filter(move |r| r.info.category == category)
+    }
+
+    /// Categories with at least one app, in the order of [`Category::ALL`].
+    pub fn categories(&self) -> impl Iterator<Item = Category> + '_ {
+        Category::ALL
+            .into_iter()
+            .filter(|category| self.in_category(*category).next().is_some())
+    }
+}
+
+impl<D, C> Default for Registry<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::{mock_display::MockDisplay, pixelcolor::Rgb565};
+
+    use super::*;
+    use crate::{
+        apps::{Dummy, Image},
+        games::GameBoy,
+    };
+
+    #[test]
+    fn lookup_by_id_and_category() {
+        let mut registry: Registry<MockDisplay<Rgb565>, Rgb565> = Registry::new();
+        registry
+            .register(Registration::new(
+                AppInfo::new("dummy", "Dummy", Category::Messages),
+                Dummy::new,
+            ))
+            .register(Registration::placeholder(GameBoy::INFO));
+
+        assert!(registry.get("dummy").unwrap().build().is_some());
+        assert!(!registry.get(GameBoy::INFO.id).unwrap().is_available());
+        assert_eq!(registry.in_category(Category::Games).count(), 1);
+        assert!(registry
+            .categories()
+            .eq([Category::Messages, Category::Games]));
+    }
+
+    #[test]
+    fn broken_apps_are_replaced() {
+        let broken: Registration<MockDisplay<Rgb565>, Rgb565> =
+            Registration::try_new(AppInfo::new("image", "Image", Category::Messages), || {
+                Image::try_new(b"not an image")
+            });
+        assert_eq!(broken.try_build().unwrap().err(), Some(AppError::Decode));
+        // The placeholder waits for B, instead of closing right away
+        let app = broken.build().unwrap();
+        assert!(!app.close_request());
+    }
+}
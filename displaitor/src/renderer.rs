@@ -0,0 +1,228 @@
+use embedded_graphics::{prelude::*, primitives::Rectangle};
+
+use crate::{
+    trait_app::{Color, RenderStatus},
+    App,
+};
+
+/// Maximum number of separate regions. Adding more merges regions into their envelope.
+pub const MAX_DIRTY_REGIONS: usize = 4;
+
+/// A small set of screen areas that changed since the last frame.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct DirtyRegions {
+    regions: heapless::Vec<Rectangle, MAX_DIRTY_REGIONS>,
+}
+
+impl DirtyRegions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Rectangle> {
+        self.regions.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.regions.clear();
+    }
+
+    /// Adds an area. If all slots are used, the area is merged with the region, which grows the
+    /// least by doing so.
+    pub fn add(&mut self, area: Rectangle) {
+        if area.is_zero_sized() || self.regions.iter().any(|r| contains(r, &area)) {
+            return;
+        }
+        self.regions.retain(|r| !contains(&area, r));
+
+        if let Err(area) = self.regions.push(area) {
+            let growth = |r: &Rectangle| size_of(&envelope(r, &area)) - size_of(r);
+            let (index, _) = self
+                .regions
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, r)| growth(r))
+                .unwrap();
+            let merged = envelope(&self.regions[index], &area);
+            self.regions.swap_remove(index);
+            // Merging can make other regions redundant
+            self.add(merged);
+        }
+    }
+
+    pub fn extend(&mut self, other: &DirtyRegions) {
+        for area in other.iter() {
+            self.add(*area);
+        }
+    }
+
+    /// The smallest rectangle containing all regions.
+    pub fn bounding_box(&self) -> Option<Rectangle> {
+        let mut regions = self.regions.iter();
+        let first = *regions.next()?;
+        Some(regions.fold(first, |acc, r| envelope(&acc, r)))
+    }
+}
+
+impl From<Rectangle> for DirtyRegions {
+    fn from(area: Rectangle) -> Self {
+        let mut regions = DirtyRegions::new();
+        regions.add(area);
+        regions
+    }
+}
+
+fn contains(outer: &Rectangle, inner: &Rectangle) -> bool {
+    outer.intersection(inner) == *inner
+}
+
+fn size_of(area: &Rectangle) -> u32 {
+    area.size.width * area.size.height
+}
+
+fn envelope(a: &Rectangle, b: &Rectangle) -> Rectangle {
+    match (a.bottom_right(), b.bottom_right()) {
+        (None, _) => *b,
+        (_, None) => *a,
+        (Some(a_br), Some(b_br)) => Rectangle::with_corners(
+            a.top_left.component_min(b.top_left),
+            a_br.component_max(b_br),
+        ),
+    }
+}
+
+/// How the target keeps its content between frames.
+#[derive(Clone, Copy, PartialEq, Debug, defmt::Format)]
+pub enum BufferMode {
+    /// Content is kept, so only the dirty regions have to be redrawn (e.g. the simulator).
+    Persistent,
+    /// Two buffers are swapped on commit, so the back buffer is two frames old. The regions of the
+    /// previous frame are redrawn as well.
+    DoubleBuffered,
+    /// The target is blank after every commit, so every change is a full redraw. Dirty regions
+    /// only save the frames without any change, e.g. on the badge with the hub75 driver.
+    ClearedOnCommit,
+}
+
+/// Decides what to redraw based on the `RenderStatus` of an update.
+pub struct Renderer {
+    mode: BufferMode,
+    /// Regions drawn in the previous frame. `None` if it was a full redraw.
+    previous: Option<DirtyRegions>,
+    last_drawn: Option<DirtyRegions>,
+}
+
+impl Renderer {
+    pub fn new(mode: BufferMode) -> Self {
+        Self {
+            mode,
+            previous: None,
+            last_drawn: None,
+        }
+    }
+
+    pub fn mode(&self) -> BufferMode {
+        self.mode
+    }
+
+    /// The next frame is a full redraw after changing the mode.
+    pub fn set_mode(&mut self, mode: BufferMode) {
+        self.mode = mode;
+        self.previous = None;
+    }
+
+    /// Regions redrawn by the last call to `render`. `None` if it was a full redraw.
+    pub fn last_drawn(&self) -> Option<&DirtyRegions> {
+        self.last_drawn.as_ref()
+    }
+
+    /// Draws the changes described by `status`. Returns `true`, if something was drawn and the
+    /// target has to be committed.
+    pub fn render<A, D, C>(&mut self, app: &A, target: &mut D, status: &RenderStatus) -> bool
+    where
+        A: App<Target = D, Color = C> + ?Sized,
+        D: DrawTarget<Color = C>,
+        C: Color,
+    {
+        let mut regions = match status {
+            RenderStatus::NoVisibleChange => return false,
+            RenderStatus::Dirty(regions) if regions.is_empty() => return false,
+            RenderStatus::VisibleChange => None,
+            RenderStatus::Dirty(_) if self.mode == BufferMode::ClearedOnCommit => None,
+            RenderStatus::Dirty(regions) => Some(regions.clone()),
+        };
+        if self.mode == BufferMode::DoubleBuffered {
+            regions = match (regions, &self.previous) {
+                (Some(mut current), Some(previous)) => {
+                    current.extend(previous);
+                    Some(current)
+                }
+                _ => None,
+            };
+        }
+
+        match &regions {
+            None => {
+                let _ = target.clear(C::BLACK);
+                app.render(target);
+            }
+            Some(regions) => {
+                let bounds = target.bounding_box();
+                for area in regions.iter() {
+                    let area = area.intersection(&bounds);
+                    if area.is_zero_sized() {
+                        continue;
+                    }
+                    let _ = target.fill_solid(&area, C::BLACK);
+                    app.render_region(target, &area);
+                }
+            }
+        }
+
+        // Only the regions of this frame are stale in the other buffer.
+        self.previous = match status {
+            RenderStatus::Dirty(current) => Some(current.clone()),
+            _ => None,
+        };
+        self.last_drawn = regions;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: i32, y: i32, w: u32, h: u32) -> Rectangle {
+        Rectangle::new(Point::new(x, y), Size::new(w, h))
+    }
+
+    #[test]
+    fn contained_regions_are_skipped() {
+        let mut regions = DirtyRegions::from(rect(0, 0, 10, 10));
+        regions.add(rect(2, 2, 3, 3));
+        regions.add(Rectangle::zero());
+        assert_eq!(regions.iter().count(), 1);
+
+        regions.add(rect(-1, -1, 20, 20));
+        assert_eq!(regions.iter().collect::<heapless::Vec<_, 4>>(), [&rect(-1, -1, 20, 20)]);
+    }
+
+    #[test]
+    fn overflow_merges_closest_regions() {
+        let mut regions = DirtyRegions::new();
+        regions.add(rect(0, 0, 1, 1));
+        regions.add(rect(20, 0, 1, 1));
+        regions.add(rect(40, 0, 1, 1));
+        regions.add(rect(60, 0, 1, 1));
+        regions.add(rect(1, 0, 1, 1));
+
+        assert_eq!(regions.iter().count(), MAX_DIRTY_REGIONS);
+        assert!(regions.iter().any(|r| *r == rect(0, 0, 2, 1)));
+        assert_eq!(regions.bounding_box(), Some(rect(0, 0, 61, 1)));
+    }
+}
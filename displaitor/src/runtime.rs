@@ -0,0 +1,959 @@
+use core::fmt::Debug;
+
+use embedded_graphics::{
+    prelude::*,
+    primitives::{PrimitiveStyle, StyledDrawable},
+};
+
+use crate::{
+    trait_app::Color, App, AppClock, AudioChannel, AudioCommand, AudioID, BufferMode, Command,
+    Controls, Dimmed, FrameTimes, Icon, Notifications, PanelLayout, Panels, Profiler, RenderStatus,
+    Renderer, Scheduler, Screensaver, Setting, Settings, StatusBar, Storage, TimeControl, Toast,
+    UpdateResult, MAX_VALUE_LEN, SETTINGS_KEY,
+};
+
+/// Time between two reports of the [`Stats`]
+pub const STATS_PERIOD_US: u64 = 3_000_000;
+
+/// What the apps draw to: the display of the backend behind the brightness and the panel layout.
+pub type Canvas<D> = Dimmed<Panels<D>>;
+
+/// Shows the frames drawn to the display `D`, e.g. by committing them to the panel driver.
+pub trait Screen<D> {
+    /// How the display behaves after [`Screen::present`]
+    fn buffer_mode(&self) -> BufferMode;
+
+    /// Shows everything drawn since the last call.
+    fn present(&mut self, display: &mut D);
+
+    /// Called with the gamma setting on start and whenever it changes. Nothing happens by default,
+    /// e.g. if the gamma can only be set when the driver is started.
+    fn set_gamma_tenths(&mut self, _gamma_tenths: u8) {}
+}
+
+/// Source of the button states.
+pub trait Input {
+    /// The current state of the buttons. `None` stops the runtime, e.g. when the window is closed.
+    fn poll(&mut self) -> Option<Controls>;
+
+    /// Whether the redrawn regions should be outlined, a debugging aid.
+    fn outline_redraws(&self) -> bool {
+        false
+    }
+
+    /// Whether the [`Profiler`] overlay should be drawn over the app, a debugging aid.
+    fn show_profile(&self) -> bool {
+        false
+    }
+
+    /// Whether the debug overlay should be drawn over the app. It is only available with the
+    /// `debug-overlay` feature.
+    fn show_debug_overlay(&self) -> bool {
+        false
+    }
+
+    /// A change of the time of the running app, a debugging aid. Called once per loop.
+    fn time_control(&mut self) -> Option<TimeControl> {
+        None
+    }
+}
+
+impl<F: FnMut() -> Option<Controls>> Input for F {
+    fn poll(&mut self) -> Option<Controls> {
+        self()
+    }
+}
+
+pub trait Clock {
+    /// Monotonic time in µs
+    fn now_us(&mut self) -> u64;
+
+    /// Waits for `us`, there is nothing to do until then.
+    fn wait_us(&mut self, us: u64);
+}
+
+/// Plays the sounds requested by the apps.
+pub trait AudioOut {
+    fn play(&mut self, id: AudioID);
+
+    fn stop(&mut self);
+
+    /// From 0 (mute) to 255 (full)
+    fn set_volume(&mut self, level: u8);
+}
+
+/// No audio at all
+impl AudioOut for () {
+    fn play(&mut self, _id: AudioID) {}
+
+    fn stop(&mut self) {}
+
+    fn set_volume(&mut self, _level: u8) {}
+}
+
+/// Memory of the heap in bytes
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, defmt::Format)]
+pub struct HeapUsage {
+    pub used: usize,
+    pub free: usize,
+}
+
+/// Counters of a period of [`STATS_PERIOD_US`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, defmt::Format)]
+pub struct Stats {
+    pub period_us: u64,
+    /// Iterations of the loop
+    pub loops: u32,
+    pub updates: u32,
+    /// Frames drawn and presented
+    pub renders: u32,
+}
+
+impl Stats {
+    pub fn renders_per_s(&self) -> u32 {
+        (self.renders as u64 * 1_000_000 / self.period_us.max(1)) as u32
+    }
+}
+
+/// Something the backend may want to log.
+#[derive(Debug)]
+pub enum Event<'a> {
+    /// The settings were applied
+    Settings(&'a Settings),
+    Stored {
+        key: u16,
+        len: usize,
+    },
+    StoreFailed {
+        key: u16,
+        error: &'a dyn Debug,
+    },
+    /// There is no storage, the value is lost with the next reset.
+    NotStored {
+        key: u16,
+    },
+    Notify(&'static str),
+    /// The time of the running app was paused, stepped or changed its speed
+    Clock(&'a AppClock),
+    /// A command the runtime doesn't execute
+    Unhandled(&'a Command),
+    /// Commands of an update which didn't fit into its result
+    Dropped {
+        commands: u8,
+    },
+    Stats(Stats),
+    /// The frame times of the period of the [`Stats`]
+    Profile(&'a Profiler),
+}
+
+/// Runs the apps on a backend: the startup sequence, the update and render loop, the commands of
+/// the apps and the settings.
+///
+/// The backend provides the display `D` with a [`Screen`] `P` showing it, the [`Input`] `I`, the
+/// [`Clock`] `K`, the [`AudioOut`] `A` and optionally a [`Storage`] `S` for persisted values.
+pub struct Runtime<D, P, I, K, A, S>
+where
+    D: DrawTarget,
+{
+    canvas: Canvas<D>,
+    screen: P,
+    input: I,
+    clock: K,
+    audio: A,
+    storage: Option<S>,
+    settings: Settings,
+    /// Sound requested last, `None` after it was stopped
+    playing: Option<AudioID>,
+    /// Music requested last, also while the sound is off. It is played again, when the sound is
+    /// switched on.
+    music: Option<AudioID>,
+    heap_usage: Option<fn() -> HeapUsage>,
+
+    renderer: Renderer,
+    scheduler: Scheduler,
+    screensaver: Screensaver,
+    /// The screen is blank, everything has to be redrawn when it wakes up
+    blanked: bool,
+    last_us: Option<u64>,
+    notifications: Notifications,
+    status_bar: Option<StatusBar>,
+
+    stats: Stats,
+    /// Counters of the last full period
+    last_stats: Stats,
+    stats_start_us: u64,
+    stats_start_ticks: u64,
+    profiler: Profiler,
+    /// Overlays drawn with the last frame, the profile and the debug overlay
+    overlays: (bool, bool),
+    log: fn(&Event),
+}
+
+impl<D, C, P, I, K, A, S> Runtime<D, P, I, K, A, S>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+    P: Screen<D>,
+    I: Input,
+    K: Clock,
+    A: AudioOut,
+    S: Storage,
+    S::Error: Debug,
+{
+    /// Runs with the default settings and without storage.
+    pub fn new(display: D, layout: PanelLayout, screen: P, input: I, clock: K, audio: A) -> Self {
+        let canvas = Dimmed::new(Panels::new(display, layout));
+        let size = canvas.bounding_box().size;
+        let mut runtime = Self {
+            canvas,
+            renderer: Renderer::new(screen.buffer_mode()),
+            screen,
+            input,
+            clock,
+            audio,
+            storage: None,
+            settings: Settings::default(),
+            playing: None,
+            music: None,
+            heap_usage: None,
+
+            scheduler: Scheduler::new(),
+            screensaver: Screensaver::new(0),
+            blanked: false,
+            last_us: None,
+            notifications: Notifications::new(size),
+            status_bar: None,
+
+            stats: Stats::default(),
+            last_stats: Stats::default(),
+            stats_start_us: 0,
+            stats_start_ticks: 0,
+            profiler: Profiler::new(),
+            overlays: (false, false),
+            log: |_| {},
+        };
+        runtime.apply_settings();
+        runtime
+    }
+
+    /// Persists values in `storage` and loads the settings from it.
+    pub fn with_storage(mut self, mut storage: S) -> Self {
+        let mut bytes = [0; MAX_VALUE_LEN];
+        let stored = storage
+            .read(SETTINGS_KEY, &mut bytes)
+            .ok()
+            .flatten()
+            .and_then(|len| Settings::from_bytes(&bytes[..len.min(bytes.len())]));
+        self.storage = Some(storage);
+        if let Some(settings) = stored {
+            self.settings = settings;
+            self.apply_settings();
+        }
+        self
+    }
+
+    /// Called with everything worth logging.
+    pub fn with_log(mut self, log: fn(&Event)) -> Self {
+        self.log = log;
+        self
+    }
+
+    /// Reports the usage of the heap, e.g. for the debug overlay.
+    pub fn with_heap_usage(mut self, heap_usage: fn() -> HeapUsage) -> Self {
+        self.heap_usage = Some(heap_usage);
+        self
+    }
+
+    /// Shows the uptime and the volume along the top, whenever the volume or the mute changes.
+    pub fn with_status_bar(mut self) -> Self {
+        let width = self.canvas.bounding_box().size.width;
+        let settings = &self.settings;
+        self.status_bar = Some(StatusBar::new(
+            width,
+            settings.volume,
+            settings.audio_enabled,
+        ));
+        self
+    }
+
+    /// Shows `toast` over the running app, after the ones posted before.
+    pub fn notify(&mut self, toast: Toast) {
+        self.notifications.post(toast);
+    }
+
+    pub fn notifications(&self) -> &Notifications {
+        &self.notifications
+    }
+
+    pub fn status_bar(&self) -> Option<&StatusBar> {
+        self.status_bar.as_ref()
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    pub fn storage_mut(&mut self) -> Option<&mut S> {
+        self.storage.as_mut()
+    }
+
+    pub fn canvas(&self) -> &Canvas<D> {
+        &self.canvas
+    }
+
+    pub fn audio(&self) -> &A {
+        &self.audio
+    }
+
+    /// Sound requested last, `None` after it was stopped
+    pub fn playing(&self) -> Option<AudioID> {
+        self.playing
+    }
+
+    pub fn heap_usage(&self) -> Option<HeapUsage> {
+        self.heap_usage.map(|heap_usage| heap_usage())
+    }
+
+    /// Time of the running app
+    pub fn app_clock(&self) -> &AppClock {
+        self.scheduler.clock()
+    }
+
+    pub fn app_clock_mut(&mut self) -> &mut AppClock {
+        self.scheduler.clock_mut()
+    }
+
+    /// Counters of the current period
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Frame times of the current period
+    pub fn profiler(&self) -> &Profiler {
+        &self.profiler
+    }
+
+    /// Runs `startup` until it requests closure, then `app`. Returns when the input stops the
+    /// runtime.
+    pub fn run<T, U>(&mut self, startup: &mut T, app: &mut U)
+    where
+        T: App<Target = Canvas<D>, Color = C> + ?Sized,
+        U: App<Target = Canvas<D>, Color = C> + ?Sized,
+    {
+        if self.run_app(startup) {
+            self.run_app(app);
+        }
+    }
+
+    /// Runs `app` from `on_enter` until it requests closure or the input stops the runtime. Returns
+    /// `false` for the latter.
+    pub fn run_app<T>(&mut self, app: &mut T) -> bool
+    where
+        T: App<Target = Canvas<D>, Color = C> + ?Sized,
+    {
+        self.scheduler.reset();
+        let entered = app.on_enter();
+        self.handle_result(&entered);
+        self.scheduler.mark(RenderStatus::VisibleChange);
+
+        let mut running = true;
+        while running && !app.close_request() {
+            running = self.step(app);
+        }
+
+        let exited = app.on_exit();
+        self.handle_result(&exited);
+        app.teardown();
+        running
+    }
+
+    /// A single iteration of the loop: polls the input, runs the updates, which are due, renders
+    /// and waits. Returns `false`, if the input stops the runtime.
+    pub fn step<T>(&mut self, app: &mut T) -> bool
+    where
+        T: App<Target = Canvas<D>, Color = C> + ?Sized,
+    {
+        let Some(controls) = self.input.poll() else {
+            return false;
+        };
+        let now_us = self.clock.now_us();
+        let dt_us = now_us.saturating_sub(self.last_us.unwrap_or(now_us)) as i64;
+        self.last_us = Some(now_us);
+        self.stats.loops += 1;
+        if let Some(control) = self.input.time_control() {
+            let tick_us = self.scheduler.pacing().tick_us;
+            self.scheduler.clock_mut().apply(control, tick_us);
+            (self.log)(&Event::Clock(self.scheduler.clock()));
+        }
+        if let Some(area) = self.notifications.update(dt_us) {
+            self.scheduler.mark(RenderStatus::dirty(area));
+        }
+        if let Some(area) = self.status_bar.as_mut().and_then(|bar| bar.update(dt_us)) {
+            self.scheduler.mark(RenderStatus::dirty(area));
+        }
+
+        if self.screensaver.update(dt_us, &controls) {
+            if !self.blanked {
+                let _ = self.canvas.clear(C::BLACK);
+                self.present();
+                self.stop_audio();
+                self.blanked = true;
+            }
+            self.report_stats(now_us);
+            self.clock.wait_us(crate::INPUT_POLL_US as u64);
+            return true;
+        }
+
+        let ticks = self.scheduler.ticks();
+        let input = self.screensaver.input(&controls);
+        let result = self.scheduler.update(app, dt_us, &input);
+        let settings_changed = self.handle_result(&result);
+        if self.blanked || settings_changed {
+            self.scheduler.mark(RenderStatus::VisibleChange);
+        }
+        self.blanked = false;
+        let updated = self.scheduler.ticks() != ticks;
+        let updated_us = self.clock.now_us();
+        let mut times = FrameTimes {
+            update_us: updated_us.saturating_sub(now_us) as u32,
+            ..Default::default()
+        };
+
+        // The overlays show the previous frame. They are redrawn after every update, not with
+        // every render, or they would keep the app rendering all the time. Everything is redrawn
+        // when one is shown or hidden.
+        let show_profile = self.input.show_profile();
+        let show_debug = cfg!(feature = "debug-overlay") && self.input.show_debug_overlay();
+        if (show_profile, show_debug) != self.overlays {
+            self.overlays = (show_profile, show_debug);
+            self.scheduler.mark(RenderStatus::VisibleChange);
+        }
+        let size = self.canvas.bounding_box().size;
+        let profile_area = Profiler::overlay_area(size);
+        if show_profile && updated {
+            self.scheduler.mark(RenderStatus::dirty(profile_area));
+        }
+        #[cfg(feature = "debug-overlay")]
+        if show_debug && updated {
+            let debug_area = crate::DebugOverlay::area(size);
+            self.scheduler.mark(RenderStatus::dirty(debug_area));
+        }
+        let mut rendered = false;
+
+        let outline = self.input.outline_redraws();
+        let mode = match outline {
+            true => BufferMode::DoubleBuffered,
+            false => self.screen.buffer_mode(),
+        };
+        if self.renderer.mode() != mode {
+            // The outlines are drawn into the regions, so they have to be redrawn with the next
+            // frame as well.
+            self.renderer.set_mode(mode);
+        }
+        if let Some(render_status) = self.scheduler.take_render() {
+            if self.renderer.render(app, &mut self.canvas, &render_status) {
+                if let Some(bar) = &self.status_bar {
+                    bar.draw(&mut self.canvas);
+                }
+                self.notifications.draw(&mut self.canvas);
+                if outline {
+                    let style = PrimitiveStyle::with_stroke(C::RED, 1);
+                    for area in self
+                        .renderer
+                        .last_drawn()
+                        .into_iter()
+                        .flat_map(|r| r.iter())
+                    {
+                        let _ = area.draw_styled(&style, &mut self.canvas);
+                    }
+                }
+                if show_profile {
+                    let budget_us = self.scheduler.pacing().tick_us as u32;
+                    self.profiler
+                        .draw_overlay(&mut self.canvas, profile_area, budget_us);
+                }
+                #[cfg(feature = "debug-overlay")]
+                if show_debug {
+                    self.draw_debug_overlay(app.active_app_name(), &controls);
+                }
+                let rendered_us = self.clock.now_us();
+                self.present();
+                let presented_us = self.clock.now_us();
+                times.render_us = rendered_us.saturating_sub(updated_us) as u32;
+                times.present_us = presented_us.saturating_sub(rendered_us) as u32;
+                rendered = true;
+                self.stats.renders += 1;
+            }
+        }
+        if updated || rendered {
+            self.profiler.record(app.active_app_name(), times);
+        }
+
+        self.report_stats(now_us);
+        self.clock.wait_us(self.scheduler.idle_us() as u64);
+        true
+    }
+
+    #[cfg(feature = "debug-overlay")]
+    fn draw_debug_overlay(&mut self, app: Option<&str>, controls: &Controls) {
+        let overlay = crate::DebugOverlay {
+            stats: self.last_stats,
+            frame: self.profiler.last(),
+            heap: self.heap_usage(),
+            app,
+            playing: self.playing,
+            settings: &self.settings,
+            controls: *controls,
+        };
+        overlay.draw(&mut self.canvas);
+    }
+
+    fn play(&mut self, id: AudioID) {
+        self.audio.play(id);
+        self.playing = Some(id);
+    }
+
+    fn stop_audio(&mut self) {
+        self.audio.stop();
+        self.playing = None;
+    }
+
+    fn present(&mut self) {
+        self.screen.present(self.canvas.inner_mut().inner_mut());
+    }
+
+    /// Executes the commands. Returns `true`, if the settings changed.
+    fn handle_result(&mut self, result: &UpdateResult) -> bool {
+        if result.dropped_commands() > 0 {
+            (self.log)(&Event::Dropped {
+                commands: result.dropped_commands(),
+            });
+        }
+        let mut settings_changed = false;
+        for command in result.commands() {
+            match command {
+                Command::Audio(AudioCommand::Play { channel, id }) => {
+                    if *channel == AudioChannel::Music {
+                        self.music = Some(*id);
+                    }
+                    if self.settings.audio_enabled {
+                        self.play(*id);
+                    }
+                }
+                Command::Audio(AudioCommand::Stop { channel }) => {
+                    if *channel == AudioChannel::Music {
+                        self.music = None;
+                    }
+                    self.stop_audio();
+                }
+                Command::Audio(AudioCommand::Volume { level, .. }) => self.audio.set_volume(*level),
+                Command::Brightness(level) => {
+                    self.settings.brightness = *level;
+                    settings_changed = true;
+                }
+                Command::Setting(setting) => {
+                    match (self.settings.audio_enabled, setting) {
+                        (true, Setting::AudioEnabled(false)) => {
+                            self.notify(Toast::new(Icon::Muted, "Sound off"))
+                        }
+                        (false, Setting::AudioEnabled(true)) => {
+                            self.notify(Toast::new(Icon::Sound, "Sound on"));
+                            if let Some(music) = self.music {
+                                self.play(music);
+                            }
+                        }
+                        _ => {}
+                    }
+                    self.settings.apply(*setting);
+                    settings_changed = true;
+                }
+                Command::SaveSettings => self.persist(SETTINGS_KEY, &self.settings.to_bytes()),
+                Command::Persist { key, value } => self.persist(*key, value),
+                Command::Notify(message) => {
+                    self.notify(Toast::new(Icon::Info, message));
+                    (self.log)(&Event::Notify(message));
+                }
+                Command::Toast(toast) => {
+                    self.notify(*toast);
+                    (self.log)(&Event::Notify(toast.message));
+                }
+                Command::Navigate(_) => (self.log)(&Event::Unhandled(command)),
+            }
+        }
+        if settings_changed {
+            self.apply_settings();
+            if let Some(bar) = &mut self.status_bar {
+                bar.set_audio(self.settings.volume, self.settings.audio_enabled);
+            }
+        }
+        settings_changed
+    }
+
+    fn persist(&mut self, key: u16, value: &[u8]) {
+        let Some(storage) = &mut self.storage else {
+            (self.log)(&Event::NotStored { key });
+            return;
+        };
+        match storage.write(key, value) {
+            Ok(()) => (self.log)(&Event::Stored {
+                key,
+                len: value.len(),
+            }),
+            Err(error) => (self.log)(&Event::StoreFailed { key, error: &error }),
+        }
+    }
+
+    fn apply_settings(&mut self) {
+        let settings = &self.settings;
+        self.canvas.set_brightness(settings.brightness);
+        self.canvas
+            .inner_mut()
+            .set_orientation(settings.orientation);
+        self.screen.set_gamma_tenths(settings.gamma_tenths);
+        self.screensaver.set_timeout(settings.screensaver_timeout_s);
+        settings.theme.activate();
+        self.audio.set_volume(settings.volume);
+        if !settings.audio_enabled {
+            self.stop_audio();
+        }
+        (self.log)(&Event::Settings(&self.settings));
+    }
+
+    fn report_stats(&mut self, now_us: u64) {
+        self.stats.period_us = now_us.saturating_sub(self.stats_start_us);
+        self.stats.updates = (self
+            .scheduler
+            .ticks()
+            .saturating_sub(self.stats_start_ticks)) as u32;
+        if self.stats.period_us < STATS_PERIOD_US {
+            return;
+        }
+        (self.log)(&Event::Stats(self.stats));
+        self.last_stats = self.stats;
+        (self.log)(&Event::Profile(&self.profiler));
+        self.profiler.reset();
+        self.stats = Stats::default();
+        self.stats_start_us = now_us;
+        self.stats_start_ticks = self.scheduler.ticks();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{boxed::Box, rc::Rc, vec::Vec};
+    use core::{
+        cell::RefCell,
+        marker::PhantomData,
+        sync::atomic::{AtomicU8, Ordering},
+    };
+
+    use embedded_graphics::pixelcolor::Rgb565;
+
+    use super::*;
+    use crate::{
+        apps::{BrokenApp, Dummy, Launcher, MenuEntry},
+        command::MAX_COMMANDS,
+        AppError, FrameBuffer, LogStorage, Navigator, RamFlash,
+    };
+
+    type Display = FrameBuffer<8, 4, Rgb565>;
+    type TestRuntime<I> = Runtime<Display, Presenter, I, FakeClock, Recorder, LogStorage<RamFlash>>;
+
+    #[derive(Default)]
+    struct Presenter {
+        presented: usize,
+        gamma_tenths: u8,
+    }
+
+    impl Screen<Display> for Presenter {
+        fn buffer_mode(&self) -> BufferMode {
+            BufferMode::Persistent
+        }
+
+        fn present(&mut self, _display: &mut Display) {
+            self.presented += 1;
+        }
+
+        fn set_gamma_tenths(&mut self, gamma_tenths: u8) {
+            self.gamma_tenths = gamma_tenths;
+        }
+    }
+
+    /// Advances by the waited time and a frame of work.
+    #[derive(Default)]
+    struct FakeClock {
+        now_us: u64,
+    }
+
+    impl Clock for FakeClock {
+        fn now_us(&mut self) -> u64 {
+            self.now_us += 1_000;
+            self.now_us
+        }
+
+        fn wait_us(&mut self, us: u64) {
+            self.now_us += us;
+        }
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        played: Vec<AudioID>,
+        stopped: usize,
+        volume: u8,
+    }
+
+    impl AudioOut for Recorder {
+        fn play(&mut self, id: AudioID) {
+            self.played.push(id);
+        }
+
+        fn stop(&mut self) {
+            self.stopped += 1;
+        }
+
+        fn set_volume(&mut self, level: u8) {
+            self.volume = level;
+        }
+    }
+
+    /// Issues `commands` with its first update and closes after `updates`.
+    struct Scripted {
+        commands: Vec<Command>,
+        updates: usize,
+        remaining: usize,
+        rendered: Rc<RefCell<usize>>,
+        _marker: PhantomData<Canvas<Display>>,
+    }
+
+    impl Scripted {
+        fn new(updates: usize, commands: Vec<Command>) -> Self {
+            Self {
+                commands,
+                updates,
+                remaining: updates,
+                rendered: Default::default(),
+                _marker: PhantomData,
+            }
+        }
+    }
+
+    impl App for Scripted {
+        type Target = Canvas<Display>;
+        type Color = Rgb565;
+
+        fn reset_state(&mut self) {
+            self.remaining = self.updates;
+        }
+
+        fn update(&mut self, _dt_us: i64, _t_us: i64, _controls: &Controls) -> UpdateResult {
+            let mut result: UpdateResult = RenderStatus::VisibleChange.into();
+            if self.remaining == self.updates {
+                for command in self.commands.drain(..) {
+                    result.push_command(command);
+                }
+            }
+            self.remaining = self.remaining.saturating_sub(1);
+            result
+        }
+
+        fn render(&self, target: &mut Self::Target) {
+            *self.rendered.borrow_mut() += 1;
+            let _ = Pixel(Point::zero(), Rgb565::WHITE).draw(target);
+        }
+
+        fn close_request(&self) -> bool {
+            self.remaining == 0
+        }
+    }
+
+    fn runtime<I: Input>(input: I) -> TestRuntime<I> {
+        Runtime::new(
+            Display::new(),
+            PanelLayout::single(Size::new(8, 4)),
+            Presenter::default(),
+            input,
+            FakeClock::default(),
+            Recorder::default(),
+        )
+        .with_storage(LogStorage::mount(RamFlash::new(1024, 4, 64)).unwrap())
+    }
+
+    fn released() -> Option<Controls> {
+        Some(Controls::default())
+    }
+
+    #[test]
+    fn runs_the_startup_app_before_the_main_app() {
+        let mut runtime = runtime(released);
+        let mut startup = Scripted::new(3, vec![Command::play_music(AudioID::MusicDepp)]);
+        let settings = Settings {
+            brightness: 128,
+            gamma_tenths: 22,
+            ..Default::default()
+        };
+        let mut app = Scripted::new(
+            2,
+            vec![
+                Command::Setting(Setting::Brightness(128)),
+                Command::Setting(Setting::GammaTenths(22)),
+                Command::SaveSettings,
+            ],
+        );
+        let (startup_rendered, app_rendered) = (startup.rendered.clone(), app.rendered.clone());
+        runtime.run(&mut startup, &mut app);
+
+        assert_eq!(runtime.audio().played, [AudioID::MusicDepp]);
+        assert!(*startup_rendered.borrow() > 0);
+        assert!(*app_rendered.borrow() > 0);
+        assert_eq!(runtime.settings(), &settings);
+        assert_eq!(runtime.canvas().brightness(), 128);
+        assert_eq!(runtime.screen.gamma_tenths, 22);
+
+        // The settings are loaded again from the storage
+        let storage = runtime.storage.take().unwrap();
+        let runtime = self::runtime(released).with_storage(storage);
+        assert_eq!(runtime.settings(), &settings);
+    }
+
+    #[test]
+    fn stops_with_the_input() {
+        let mut polls = 0;
+        let mut runtime = runtime(move || {
+            polls += 1;
+            (polls < 5).then(Controls::default)
+        });
+        let mut app = Scripted::new(usize::MAX, Vec::new());
+        assert!(!runtime.run_app(&mut app));
+        assert_eq!(runtime.stats().loops, 4);
+    }
+
+    #[test]
+    fn keeps_running_when_b_is_pressed_at_the_root() {
+        let mut polls = 0;
+        let mut runtime = runtime(move || {
+            polls += 1;
+            let controls = Controls {
+                buttons_b: (5..10).contains(&polls),
+                ..Default::default()
+            };
+            (polls < 30).then_some(controls)
+        });
+        let mut navigator = Navigator::new();
+        let launcher = Launcher::new([MenuEntry::new("Dummy", Box::new(Dummy::new()))]);
+        let root = navigator.add_scene(Box::new(launcher));
+        navigator.set_root(root);
+        // Stopped by the input, not by the close request of the launcher
+        assert!(!runtime.run_app(&mut navigator));
+        assert_eq!(runtime.stats().loops, 29);
+    }
+
+    #[test]
+    fn profiles_the_frames() {
+        let mut runtime = runtime(released);
+        let mut app = Scripted::new(3, Vec::new());
+        runtime.run_app(&mut app);
+        let total = runtime.profiler().total();
+        assert!(total.frame.count > 0);
+        // Every reading of the fake clock takes 1 ms
+        assert_eq!(total.update.max_us, 1_000);
+        assert_eq!(total.render.max_us, 1_000);
+        assert_eq!(total.present.max_us, 1_000);
+        // The app has no name
+        assert!(runtime.profiler().apps().is_empty());
+    }
+
+    #[test]
+    fn shows_a_toast_when_the_sound_is_switched_off() {
+        let mut runtime = runtime(released).with_status_bar();
+        let off = Command::Setting(Setting::AudioEnabled(false));
+        let mut app = Scripted::new(usize::MAX, vec![off]);
+        for _ in 0..20 {
+            runtime.step(&mut app);
+        }
+        let toast = runtime.notifications().current().unwrap();
+        assert_eq!((toast.icon, toast.message), (Icon::Muted, "Sound off"));
+        assert!(runtime.status_bar().unwrap().is_shown());
+        // Drawn over the app, which draws its pixel to the top left
+        assert_ne!(
+            runtime.canvas().inner().inner().pixel(Point::zero()),
+            Some(Rgb565::WHITE)
+        );
+    }
+
+    #[test]
+    fn plays_the_music_again_when_the_sound_is_switched_on() {
+        let mut runtime = runtime(released);
+        let mut execute = |command| {
+            let result = UpdateResult::from(RenderStatus::NoVisibleChange).with_command(command);
+            runtime.handle_result(&result);
+        };
+        execute(Command::play_music(AudioID::MusicNyan));
+        execute(Command::Setting(Setting::AudioEnabled(false)));
+        execute(Command::Setting(Setting::AudioEnabled(true)));
+        assert_eq!(runtime.playing(), Some(AudioID::MusicNyan));
+        assert_eq!(
+            runtime.audio().played,
+            [AudioID::MusicNyan, AudioID::MusicNyan]
+        );
+    }
+
+    #[test]
+    fn logs_the_commands_which_did_not_fit() {
+        static DROPPED: AtomicU8 = AtomicU8::new(0);
+        let mut runtime = runtime(released).with_log(|event| {
+            if let Event::Dropped { commands } = event {
+                DROPPED.fetch_add(*commands, Ordering::Relaxed);
+            }
+        });
+        let mut app = Scripted::new(usize::MAX, vec![Command::Notify("Hi"); MAX_COMMANDS + 2]);
+        for _ in 0..20 {
+            runtime.step(&mut app);
+        }
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn blanks_the_screen_without_input() {
+        let mut runtime = runtime(released);
+        let timeout = Command::Setting(Setting::ScreensaverTimeout(1));
+        let mut app = Scripted::new(usize::MAX, vec![timeout]);
+        for _ in 0..1000 {
+            runtime.step(&mut app);
+        }
+        assert!(runtime.blanked);
+        assert_eq!(
+            runtime.canvas().inner().inner().pixel(Point::zero()),
+            Some(Rgb565::BLACK)
+        );
+        assert_eq!(runtime.audio().stopped, 1);
+    }
+
+    #[test]
+    fn the_input_waking_the_screen_does_not_reach_the_app() {
+        let mut polls = 0;
+        let mut runtime = runtime(move || {
+            polls += 1;
+            let controls = Controls {
+                buttons_b: (1000..1010).contains(&polls),
+                ..Default::default()
+            };
+            Some(controls)
+        });
+        runtime.settings.screensaver_timeout_s = 1;
+        runtime.apply_settings();
+        let mut app = BrokenApp::new("broken", AppError::Decode);
+        app.reset_state();
+        for _ in 1..1000 {
+            runtime.step(&mut app);
+        }
+        assert!(runtime.blanked);
+
+        // B is pressed to wake the screen up and released again
+        for _ in 0..20 {
+            runtime.step(&mut app);
+            assert!(!app.close_request());
+        }
+        assert!(!runtime.blanked);
+    }
+}
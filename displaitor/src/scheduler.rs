@@ -0,0 +1,316 @@
+use crate::{App, AppClock, Controls, RenderStatus, UpdateResult};
+
+/// Time between two updates, if an app doesn't ask for another rate. 50 updates per second.
+pub const DEFAULT_TICK_US: i64 = 20_000;
+/// Longest time the loops should wait between two samples of the buttons, so short presses
+/// aren't missed by apps with a slow tick rate.
+pub const INPUT_POLL_US: i64 = 5_000;
+/// Updates run in a row at most to catch up after a slow frame. The time beyond is dropped, so
+/// the game slows down instead of stalling the loop.
+pub const MAX_CATCH_UP: u32 = 4;
+
+/// How often an app wants to be updated and rendered, see [`App::pacing`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, defmt::Format)]
+pub struct Pacing {
+    /// Time between two updates. Every update gets exactly this as `dt_us`.
+    pub tick_us: i64,
+    /// Shortest time between two renders. 0 renders after every update with visible changes.
+    pub render_us: i64,
+}
+
+impl Pacing {
+    pub const DEFAULT: Pacing = Pacing::every(DEFAULT_TICK_US);
+
+    /// Updates every `tick_us`, rendering after each of them.
+    pub const fn every(tick_us: i64) -> Self {
+        Self {
+            tick_us,
+            render_us: 0,
+        }
+    }
+
+    pub const fn with_render_us(mut self, render_us: i64) -> Self {
+        self.render_us = render_us;
+        self
+    }
+}
+
+impl Default for Pacing {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Updates an app at a fixed rate, independent of how often the loop runs or how long rendering
+/// takes.
+///
+/// The elapsed time is collected and spent in whole ticks of the app's [`Pacing`], so the game
+/// time `t_us` advances in the same steps on the badge, in the simulator and in tests. It passes
+/// through an [`AppClock`] first, which can pause the app or run it slower or faster. Buttons
+/// are latched between ticks: a press, which was sampled by the loop, reaches the app with the
+/// next update, even if it was released before.
+pub struct Scheduler {
+    clock: AppClock,
+    accumulator_us: i64,
+    /// Game time, the sum of all ticks
+    t_us: i64,
+    /// Updates run so far
+    ticks: u64,
+    since_render_us: i64,
+    max_catch_up: u32,
+    pacing: Pacing,
+    /// Buttons pressed in any sample since the last update
+    latched: Controls,
+    /// Changes since the last render
+    pending: Option<RenderStatus>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self {
+            clock: AppClock::new(),
+            accumulator_us: 0,
+            t_us: 0,
+            ticks: 0,
+            // The first changes are rendered right away
+            since_render_us: i64::MAX,
+            max_catch_up: MAX_CATCH_UP,
+            pacing: Pacing::DEFAULT,
+            latched: Controls::default(),
+            pending: None,
+        }
+    }
+
+    /// Updates run in a row at most, see [`MAX_CATCH_UP`].
+    pub fn with_max_catch_up(mut self, steps: u32) -> Self {
+        self.max_catch_up = steps.max(1);
+        self
+    }
+
+    /// Starts the time of the next app at zero. The speed of the clock and the ticks are kept.
+    pub fn reset(&mut self) {
+        self.clock.reset();
+        self.accumulator_us = 0;
+        self.t_us = 0;
+        self.since_render_us = i64::MAX;
+        self.latched = Controls::default();
+        self.pending = None;
+    }
+
+    pub fn clock(&self) -> &AppClock {
+        &self.clock
+    }
+
+    pub fn clock_mut(&mut self) -> &mut AppClock {
+        &mut self.clock
+    }
+
+    /// Game time passed to the app with the last update
+    pub fn t_us(&self) -> i64 {
+        self.t_us
+    }
+
+    /// Number of updates run so far
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// Pacing of the app at the last update
+    pub fn pacing(&self) -> Pacing {
+        self.pacing
+    }
+
+    /// Adds `elapsed_us` and runs as many updates of `app` as are due. The results of all of them
+    /// are combined, no update results in `NoVisibleChange`.
+    pub fn update<A: App + ?Sized>(
+        &mut self,
+        app: &mut A,
+        elapsed_us: i64,
+        controls: &Controls,
+    ) -> UpdateResult {
+        let elapsed_us = elapsed_us.max(0);
+        self.accumulator_us += self.clock.advance(elapsed_us);
+        // Renders are limited in wall clock time
+        self.since_render_us = self.since_render_us.saturating_add(elapsed_us);
+        self.latched = latch(&self.latched, controls);
+
+        let mut result: UpdateResult = RenderStatus::NoVisibleChange.into();
+        let mut steps = 0;
+        loop {
+            // Read every time, opening an app changes it
+            self.pacing = app.pacing();
+            let tick_us = self.pacing.tick_us.max(1);
+            if self.accumulator_us < tick_us {
+                break;
+            }
+            if steps == self.max_catch_up {
+                self.accumulator_us %= tick_us;
+                break;
+            }
+            self.accumulator_us -= tick_us;
+            self.t_us += tick_us;
+            self.ticks += 1;
+            // Only the first update gets the latched presses, the rest get the current state.
+            let controls = match steps {
+                0 => self.latched,
+                _ => *controls,
+            };
+            result = result.merge(app.update(tick_us, self.t_us, &controls));
+            steps += 1;
+        }
+
+        if steps > 0 {
+            self.latched = Controls::default();
+            self.mark(result.render_status().clone());
+        }
+        result
+    }
+
+    /// Adds changes to the next render, e.g. [`RenderStatus::VisibleChange`] after new settings.
+    pub fn mark(&mut self, changes: RenderStatus) {
+        self.pending = Some(match self.pending.take() {
+            Some(pending) => pending.merge(changes),
+            None => changes,
+        });
+    }
+
+    /// The changes to render, if there are any and the render interval of the app passed.
+    pub fn take_render(&mut self) -> Option<RenderStatus> {
+        if self.since_render_us < self.pacing.render_us {
+            return None;
+        }
+        match self.pending.take()? {
+            RenderStatus::NoVisibleChange => None,
+            changes => {
+                self.since_render_us = 0;
+                Some(changes)
+            }
+        }
+    }
+
+    /// How long the loop can wait until the next update is due, at most [`INPUT_POLL_US`].
+    pub fn idle_us(&self) -> i64 {
+        (self.pacing.tick_us - self.accumulator_us).clamp(0, INPUT_POLL_US)
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Buttons pressed in either of them
+fn latch(a: &Controls, b: &Controls) -> Controls {
+    Controls {
+        buttons_a: a.buttons_a || b.buttons_a,
+        buttons_b: a.buttons_b || b.buttons_b,
+        buttons_s: a.buttons_s || b.buttons_s,
+        dpad_up: a.dpad_up || b.dpad_up,
+        dpad_down: a.dpad_down || b.dpad_down,
+        dpad_left: a.dpad_left || b.dpad_left,
+        dpad_right: a.dpad_right || b.dpad_right,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use embedded_graphics::{mock_display::MockDisplay, pixelcolor::Rgb565};
+
+    use super::*;
+
+    /// Records the updates it gets.
+    #[derive(Default)]
+    struct Ticker {
+        updates: Vec<(i64, i64, bool)>,
+    }
+
+    impl App for Ticker {
+        type Target = MockDisplay<Rgb565>;
+        type Color = Rgb565;
+
+        fn reset_state(&mut self) {}
+
+        fn update(&mut self, dt_us: i64, t_us: i64, controls: &Controls) -> UpdateResult {
+            self.updates.push((dt_us, t_us, controls.buttons_a));
+            RenderStatus::VisibleChange.into()
+        }
+
+        fn render(&self, _target: &mut Self::Target) {}
+
+        fn pacing(&self) -> Pacing {
+            Pacing::every(10_000).with_render_us(25_000)
+        }
+    }
+
+    #[test]
+    fn updates_in_fixed_steps() {
+        let mut scheduler = Scheduler::new();
+        let mut app = Ticker::default();
+        for elapsed_us in [4_000, 4_000, 4_000, 15_000] {
+            let _ = scheduler.update(&mut app, elapsed_us, &Controls::default());
+        }
+        assert_eq!(
+            app.updates,
+            [(10_000, 10_000, false), (10_000, 20_000, false)]
+        );
+        assert_eq!(scheduler.idle_us(), 3_000);
+
+        // A long stall only catches up a few updates
+        let _ = scheduler.update(&mut app, 1_000_000, &Controls::default());
+        assert_eq!(app.updates.len(), 2 + MAX_CATCH_UP as usize);
+        assert_eq!(scheduler.t_us(), (2 + MAX_CATCH_UP as i64) * 10_000);
+    }
+
+    #[test]
+    fn short_presses_are_latched() {
+        let mut scheduler = Scheduler::new();
+        let mut app = Ticker::default();
+        let pressed = Controls {
+            buttons_a: true,
+            ..Default::default()
+        };
+        let _ = scheduler.update(&mut app, 5_000, &pressed);
+        let _ = scheduler.update(&mut app, 5_000, &Controls::default());
+        let _ = scheduler.update(&mut app, 10_000, &Controls::default());
+        let pressed: Vec<bool> = app.updates.iter().map(|update| update.2).collect();
+        assert_eq!(pressed, [true, false]);
+    }
+
+    #[test]
+    fn follows_the_clock() {
+        let mut scheduler = Scheduler::new();
+        let mut app = Ticker::default();
+        scheduler.clock_mut().set_speed_percent(50);
+        let _ = scheduler.update(&mut app, 40_000, &Controls::default());
+        assert_eq!(app.updates.len(), 2);
+
+        scheduler.clock_mut().pause();
+        let _ = scheduler.update(&mut app, 40_000, &Controls::default());
+        assert_eq!(app.updates.len(), 2);
+        scheduler.clock_mut().step(10_000);
+        let _ = scheduler.update(&mut app, 40_000, &Controls::default());
+        assert_eq!(app.updates.len(), 3);
+
+        scheduler.reset();
+        assert_eq!(scheduler.t_us(), 0);
+        assert_eq!(scheduler.clock().speed_percent(), 50);
+    }
+
+    #[test]
+    fn renders_at_the_render_rate() {
+        let mut scheduler = Scheduler::new();
+        let mut app = Ticker::default();
+        let mut renders = 0;
+        for _ in 0..10 {
+            let _ = scheduler.update(&mut app, 10_000, &Controls::default());
+            renders += scheduler.take_render().is_some() as usize;
+        }
+        assert_eq!(app.updates.len(), 10);
+        assert_eq!(renders, 4);
+        // Nothing changed since
+        assert_eq!(scheduler.take_render(), None);
+    }
+}
@@ -0,0 +1,89 @@
+use crate::Controls;
+
+/// Blanks the screen after a time without input. Any change of the controls wakes it up again.
+pub struct Screensaver {
+    timeout_us: i64,
+    idle_us: i64,
+    last_controls: Option<Controls>,
+    /// Woke up and the buttons pressed for it aren't all released yet
+    waking: bool,
+}
+
+impl Screensaver {
+    /// A timeout of 0 disables the screensaver.
+    pub fn new(timeout_s: u16) -> Self {
+        Self {
+            timeout_us: timeout_s as i64 * 1_000_000,
+            idle_us: 0,
+            last_controls: None,
+            waking: false,
+        }
+    }
+
+    pub fn set_timeout(&mut self, timeout_s: u16) {
+        self.timeout_us = timeout_s as i64 * 1_000_000;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.timeout_us > 0 && self.idle_us >= self.timeout_us
+    }
+
+    /// Returns `true`, while the screen should stay blank. The app should not be updated then,
+    /// and only gets the [`Screensaver::input`] after, so it doesn't see the input waking it up.
+    pub fn update(&mut self, dt_us: i64, controls: &Controls) -> bool {
+        let was_active = self.is_active();
+        if self.last_controls.is_some_and(|last| last != *controls) {
+            self.idle_us = 0;
+        } else {
+            self.idle_us = self.idle_us.saturating_add(dt_us);
+        }
+        self.last_controls = Some(*controls);
+        if was_active && !self.is_active() {
+            self.waking = true;
+        }
+        if *controls == Controls::default() {
+            self.waking = false;
+        }
+        was_active || self.is_active()
+    }
+
+    /// The `controls` for the app: none are pressed after waking up, until all buttons are
+    /// released. Otherwise releasing them would e.g. close the app.
+    pub fn input(&self, controls: &Controls) -> Controls {
+        match self.waking {
+            true => Controls::default(),
+            false => *controls,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blanks_after_timeout_and_wakes_on_input() {
+        let idle = Controls::default();
+        let pressed = Controls {
+            buttons_a: true,
+            ..Default::default()
+        };
+        let mut screensaver = Screensaver::new(2);
+        assert!(!screensaver.update(1_000_000, &idle));
+        assert!(!screensaver.update(900_000, &idle));
+        assert!(screensaver.update(100_000, &idle));
+
+        // The frame with the wake-up input is still blank
+        assert!(screensaver.update(10_000, &pressed));
+        assert!(!screensaver.is_active());
+        assert!(!screensaver.update(10_000, &pressed));
+        // Held until it is released
+        assert_eq!(screensaver.input(&pressed), Controls::default());
+        assert!(!screensaver.update(10_000, &idle));
+        assert!(!screensaver.update(10_000, &pressed));
+        assert_eq!(screensaver.input(&pressed), pressed);
+
+        screensaver.set_timeout(0);
+        assert!(!screensaver.update(i64::MAX, &pressed));
+    }
+}
@@ -0,0 +1,268 @@
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use embedded_graphics::pixelcolor::{Rgb888, WebColors};
+use embedded_graphics::prelude::RgbColor;
+
+use crate::{Orientation, PersistValue};
+
+/// Key under which the top-level loops persist the settings.
+pub const SETTINGS_KEY: u16 = 0x5E77;
+
+/// Bumped whenever the encoding changes. Older records are ignored.
+const VERSION: u8 = 2;
+/// Maximum length of the start app id
+pub const START_APP_ID_MAX: usize = 16;
+
+/// Colours of the text menus.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, defmt::Format)]
+pub enum MenuTheme {
+    Classic,
+    Ocean,
+    Ember,
+    Mono,
+}
+
+static ACTIVE_THEME: AtomicU8 = AtomicU8::new(MenuTheme::Classic as u8);
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ThemeColors {
+    pub text: Rgb888,
+    pub selected: Rgb888,
+    pub disabled: Rgb888,
+    pub header: Rgb888,
+    pub indicator: Rgb888,
+}
+
+impl MenuTheme {
+    pub const ALL: [MenuTheme; 4] = [
+        MenuTheme::Classic,
+        MenuTheme::Ocean,
+        MenuTheme::Ember,
+        MenuTheme::Mono,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MenuTheme::Classic => "Classic",
+            MenuTheme::Ocean => "Ocean",
+            MenuTheme::Ember => "Ember",
+            MenuTheme::Mono => "Mono",
+        }
+    }
+
+    pub fn colors(&self) -> ThemeColors {
+        match self {
+            MenuTheme::Classic => ThemeColors {
+                text: Rgb888::WHITE,
+                selected: Rgb888::MAGENTA,
+                disabled: Rgb888::CSS_DIM_GRAY,
+                header: Rgb888::CSS_GOLD,
+                indicator: Rgb888::CSS_GRAY,
+            },
+            MenuTheme::Ocean => ThemeColors {
+                text: Rgb888::CSS_LIGHT_CYAN,
+                selected: Rgb888::CSS_DEEP_SKY_BLUE,
+                disabled: Rgb888::CSS_SLATE_GRAY,
+                header: Rgb888::CSS_AQUAMARINE,
+                indicator: Rgb888::CSS_STEEL_BLUE,
+            },
+            MenuTheme::Ember => ThemeColors {
+                text: Rgb888::CSS_WHEAT,
+                selected: Rgb888::CSS_ORANGE_RED,
+                disabled: Rgb888::CSS_DIM_GRAY,
+                header: Rgb888::CSS_GOLD,
+                indicator: Rgb888::CSS_DARK_ORANGE,
+            },
+            MenuTheme::Mono => ThemeColors {
+                text: Rgb888::CSS_GRAY,
+                selected: Rgb888::WHITE,
+                disabled: Rgb888::CSS_DIM_GRAY,
+                header: Rgb888::WHITE,
+                indicator: Rgb888::CSS_GRAY,
+            },
+        }
+    }
+
+    /// The theme used by all menus. Set by the top-level loops with [`MenuTheme::activate`].
+    pub fn active() -> MenuTheme {
+        Self::from_u8(ACTIVE_THEME.load(Ordering::Relaxed)).unwrap_or(MenuTheme::Classic)
+    }
+
+    pub fn activate(self) {
+        ACTIVE_THEME.store(self as u8, Ordering::Relaxed);
+    }
+
+    fn from_u8(value: u8) -> Option<MenuTheme> {
+        Self::ALL.into_iter().find(|theme| *theme as u8 == value)
+    }
+}
+
+/// User preferences. Changed by the settings app, applied and persisted by the top-level loops.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Settings {
+    /// From 0 (off) to 255 (full)
+    pub brightness: u8,
+    /// Gamma of the panel in tenths, e.g. 22 for 2.2
+    pub gamma_tenths: u8,
+    /// From 0 (mute) to 255 (full)
+    pub volume: u8,
+    pub audio_enabled: bool,
+    /// Seconds without input until the screen is blanked. 0 disables the screensaver.
+    pub screensaver_timeout_s: u16,
+    pub theme: MenuTheme,
+    /// How the canvas is shown on the panels, one of [`Orientation::SAME_SIZE`]
+    pub orientation: Orientation,
+    /// `AppInfo::id` of the app opened on start. `None` starts with the launcher.
+    pub start_app: Option<heapless::String<START_APP_ID_MAX>>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            brightness: 255,
+            gamma_tenths: 10,
+            volume: 255,
+            audio_enabled: true,
+            screensaver_timeout_s: 0,
+            theme: MenuTheme::Classic,
+            orientation: Orientation::Normal,
+            start_app: None,
+        }
+    }
+}
+
+/// A single changed setting, sent with [`crate::Command::Setting`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Setting {
+    Brightness(u8),
+    GammaTenths(u8),
+    Volume(u8),
+    AudioEnabled(bool),
+    ScreensaverTimeout(u16),
+    Theme(MenuTheme),
+    Orientation(Orientation),
+    /// `AppInfo::id` of the app opened on start
+    StartApp(Option<&'static str>),
+}
+
+impl Settings {
+    /// Changes the setting. An id of a start app which is too long falls back to the launcher.
+    pub fn apply(&mut self, setting: Setting) {
+        match setting {
+            Setting::Brightness(brightness) => self.brightness = brightness,
+            Setting::GammaTenths(gamma_tenths) => self.gamma_tenths = gamma_tenths,
+            Setting::Volume(volume) => self.volume = volume,
+            Setting::AudioEnabled(enabled) => self.audio_enabled = enabled,
+            Setting::ScreensaverTimeout(timeout_s) => self.screensaver_timeout_s = timeout_s,
+            Setting::Theme(theme) => self.theme = theme,
+            Setting::Orientation(orientation) => self.orientation = orientation,
+            Setting::StartApp(id) => self.start_app = id.and_then(|id| id.try_into().ok()),
+        }
+    }
+
+    pub fn gamma(&self) -> f32 {
+        self.gamma_tenths as f32 / 10.0
+    }
+
+    pub fn start_app(&self) -> Option<&str> {
+        self.start_app.as_deref()
+    }
+
+    /// Encodes the settings for the [`crate::Storage`]. The last byte is a checksum.
+    pub fn to_bytes(&self) -> PersistValue {
+        let mut bytes = PersistValue::new();
+        let timeout = self.screensaver_timeout_s.to_le_bytes();
+        let start_app = self.start_app().unwrap_or("").as_bytes();
+        let _ = bytes.extend_from_slice(&[
+            VERSION,
+            self.brightness,
+            self.gamma_tenths,
+            self.volume,
+            self.audio_enabled as u8,
+            timeout[0],
+            timeout[1],
+            self.theme as u8,
+            self.orientation as u8,
+            start_app.len() as u8,
+        ]);
+        let _ = bytes.extend_from_slice(start_app);
+        let _ = bytes.push(checksum(&bytes));
+        bytes
+    }
+
+    /// Decodes settings written by [`Settings::to_bytes`]. Trailing bytes are ignored, so a whole
+    /// flash page can be passed. `None` for erased, corrupted or outdated records.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Settings> {
+        const HEADER: usize = 10;
+        let header = bytes.get(..HEADER)?;
+        if header[0] != VERSION {
+            return None;
+        }
+        let id_len = header[9] as usize;
+        let record = bytes.get(..HEADER + id_len)?;
+        if *bytes.get(HEADER + id_len)? != checksum(record) {
+            return None;
+        }
+        let start_app = match core::str::from_utf8(&record[HEADER..]).ok()? {
+            "" => None,
+            id => Some(heapless::String::try_from(id).ok()?),
+        };
+
+        Some(Settings {
+            brightness: header[1],
+            gamma_tenths: header[2],
+            volume: header[3],
+            audio_enabled: header[4] != 0,
+            screensaver_timeout_s: u16::from_le_bytes([header[5], header[6]]),
+            theme: MenuTheme::from_u8(header[7])?,
+            orientation: Orientation::from_u8(header[8])?,
+            start_app,
+        })
+    }
+}
+
+fn checksum(bytes: &[u8]) -> u8 {
+    // Starts with a non-zero value, so an all-zero record is invalid as well.
+    bytes
+        .iter()
+        .fold(0xA5u8, |sum, byte| sum.rotate_left(1) ^ byte)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_roundtrip() {
+        let settings = Settings {
+            brightness: 80,
+            gamma_tenths: 22,
+            volume: 128,
+            audio_enabled: false,
+            screensaver_timeout_s: 300,
+            theme: MenuTheme::Ember,
+            orientation: Orientation::MirrorY,
+            start_app: Some("snake".try_into().unwrap()),
+        };
+        let mut page = [0xFF; 64];
+        let bytes = settings.to_bytes();
+        page[..bytes.len()].copy_from_slice(&bytes);
+        assert_eq!(Settings::from_bytes(&page), Some(settings));
+        assert_eq!(
+            Settings::from_bytes(&Settings::default().to_bytes()),
+            Some(Settings::default())
+        );
+    }
+
+    #[test]
+    fn invalid_records_are_rejected() {
+        assert_eq!(Settings::from_bytes(&[0xFF; 64]), None);
+        assert_eq!(Settings::from_bytes(&[0; 64]), None);
+
+        let mut bytes = Settings::default().to_bytes();
+        bytes[1] ^= 0x10;
+        assert_eq!(Settings::from_bytes(&bytes), None);
+        bytes.truncate(4);
+        assert_eq!(Settings::from_bytes(&bytes), None);
+    }
+}
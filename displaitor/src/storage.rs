@@ -0,0 +1,594 @@
+use alloc::vec::Vec;
+
+/// Flash-like memory. It is erased in sectors, which sets all bytes to `0xFF`, and programmed in
+/// pages. Programming can only clear bits, so a page has to be erased before it is written again.
+pub trait BlockDevice {
+    type Error: core::fmt::Debug;
+
+    fn sector_size(&self) -> usize;
+
+    fn sector_count(&self) -> usize;
+
+    /// Unit of programming. `program` is only called with page aligned offsets and whole pages.
+    fn page_size(&self) -> usize;
+
+    fn read(&mut self, offset: usize, buffer: &mut [u8]) -> Result<(), Self::Error>;
+
+    fn erase(&mut self, sector: usize) -> Result<(), Self::Error>;
+
+    fn program(&mut self, offset: usize, data: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// Persistent key/value store. The keys are the ones of [`crate::Command::Persist`].
+pub trait Storage {
+    type Error;
+
+    /// Copies the value of `key` into `buffer` and returns its length. Longer values are cut off
+    /// at the end of the buffer, the returned length is the full one. `None`, if `key` isn't stored.
+    fn read(&mut self, key: u16, buffer: &mut [u8]) -> Result<Option<usize>, Self::Error>;
+
+    fn write(&mut self, key: u16, value: &[u8]) -> Result<(), Self::Error>;
+
+    fn remove(&mut self, key: u16) -> Result<(), Self::Error>;
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StorageError<E> {
+    Device(E),
+    /// The value doesn't fit into a page or is longer than [`MAX_VALUE_LEN`].
+    TooLarge,
+    /// The live values don't fit into a single sector anymore.
+    Full,
+    /// The device has less than two sectors or pages too small for a record.
+    Unsupported,
+}
+
+impl<E> From<E> for StorageError<E> {
+    fn from(error: E) -> Self {
+        StorageError::Device(error)
+    }
+}
+
+/// Longest value a [`LogStorage`] can keep, if the pages are large enough.
+pub const MAX_VALUE_LEN: usize = 128;
+
+const SECTOR_MAGIC: [u8; 4] = *b"KVL1";
+const SECTOR_HEADER_LEN: usize = 12;
+const RECORD_VALUE: u8 = 0xA1;
+const RECORD_REMOVED: u8 = 0xA2;
+/// Kind, key and length in front of the value, CRC behind it
+const RECORD_HEADER_LEN: usize = 4;
+const RECORD_OVERHEAD: usize = RECORD_HEADER_LEN + 4;
+const RECORD_MAX_LEN: usize = RECORD_OVERHEAD + MAX_VALUE_LEN;
+
+/// Location of a record: sector and page within it.
+type Position = (usize, usize);
+
+struct Record {
+    kind: u8,
+    key: u16,
+    len: usize,
+    /// Kind, key, length, value and CRC
+    bytes: [u8; RECORD_MAX_LEN],
+}
+
+impl Record {
+    fn value(&self) -> &[u8] {
+        &self.bytes[RECORD_HEADER_LEN..RECORD_HEADER_LEN + self.len]
+    }
+}
+
+/// Log-structured key/value store on top of a [`BlockDevice`].
+///
+/// Every write appends a record to the current sector, one record per page. The first page of a
+/// sector holds a header with an increasing sequence number, which orders the sectors. If the
+/// current sector is full, the next one in turn is started, so all sectors are erased equally often.
+/// One sector is always kept free: before it is used, the values still current in the oldest
+/// sector are copied to the newest one and the oldest is erased.
+///
+/// Headers and records carry a CRC. A write cut off by a power loss leaves an invalid record, which
+/// is skipped, so the previous value of the key is read again.
+pub struct LogStorage<B: BlockDevice> {
+    device: B,
+    /// Sector records are appended to and its sequence number. `None` for an empty device.
+    active: Option<(usize, u32)>,
+    /// Next free page in the active sector
+    next_page: usize,
+}
+
+impl<B: BlockDevice> LogStorage<B> {
+    /// Scans the device and finishes a compaction interrupted by a power loss. An erased or
+    /// unformatted device is used as an empty store.
+    pub fn mount(device: B) -> Result<Self, StorageError<B::Error>> {
+        let page_size = device.page_size();
+        if device.sector_count() < 2
+            || page_size < SECTOR_HEADER_LEN
+            || page_size < RECORD_OVERHEAD + 1
+            || device.sector_size() < 2 * page_size
+        {
+            return Err(StorageError::Unsupported);
+        }
+
+        let mut storage = Self {
+            device,
+            active: None,
+            next_page: 0,
+        };
+        storage.active = storage.sectors_in_order()?.last().copied();
+        if let Some((sector, _)) = storage.active {
+            // Continue after the last page used, even if it only holds a part of a record.
+            storage.next_page = 1;
+            for page in (1..storage.pages_per_sector()).rev() {
+                if !storage.page_erased(sector, page)? {
+                    storage.next_page = page + 1;
+                    break;
+                }
+            }
+        }
+        storage.ensure_free_sector()?;
+        Ok(storage)
+    }
+
+    pub fn device(&self) -> &B {
+        &self.device
+    }
+
+    pub fn device_mut(&mut self) -> &mut B {
+        &mut self.device
+    }
+
+    pub fn into_device(self) -> B {
+        self.device
+    }
+
+    /// Longest value, which can be written to this device.
+    pub fn max_value_len(&self) -> usize {
+        (self.device.page_size() - RECORD_OVERHEAD).min(MAX_VALUE_LEN)
+    }
+
+    fn pages_per_sector(&self) -> usize {
+        self.device.sector_size() / self.device.page_size()
+    }
+
+    fn offset(&self, (sector, page): Position) -> usize {
+        sector * self.device.sector_size() + page * self.device.page_size()
+    }
+
+    fn page_erased(&mut self, sector: usize, page: usize) -> Result<bool, B::Error> {
+        let mut offset = self.offset((sector, page));
+        let end = offset + self.device.page_size();
+        let mut chunk = [0; 32];
+        while offset < end {
+            let len = chunk.len().min(end - offset);
+            self.device.read(offset, &mut chunk[..len])?;
+            if chunk[..len].iter().any(|byte| *byte != 0xFF) {
+                return Ok(false);
+            }
+            offset += len;
+        }
+        Ok(true)
+    }
+
+    /// Sequence number of a sector with a valid header.
+    fn read_header(&mut self, sector: usize) -> Result<Option<u32>, B::Error> {
+        let mut header = [0; SECTOR_HEADER_LEN];
+        self.device.read(self.offset((sector, 0)), &mut header)?;
+        let valid = header[..4] == SECTOR_MAGIC && header[8..] == crc32(&header[..8]).to_le_bytes();
+        Ok(valid.then(|| u32::from_le_bytes([header[4], header[5], header[6], header[7]])))
+    }
+
+    /// Sectors with a valid header and their sequence number, oldest first.
+    fn sectors_in_order(&mut self) -> Result<Vec<(usize, u32)>, B::Error> {
+        let mut sectors = Vec::new();
+        for sector in 0..self.device.sector_count() {
+            if let Some(sequence) = self.read_header(sector)? {
+                sectors.push((sector, sequence));
+            }
+        }
+        sectors.sort_unstable_by_key(|(_, sequence)| *sequence);
+        Ok(sectors)
+    }
+
+    fn read_record(&mut self, position: Position) -> Result<Option<Record>, B::Error> {
+        let mut record = Record {
+            kind: 0,
+            key: 0,
+            len: 0,
+            bytes: [0; RECORD_MAX_LEN],
+        };
+        let offset = self.offset(position);
+        self.device
+            .read(offset, &mut record.bytes[..RECORD_HEADER_LEN])?;
+        record.kind = record.bytes[0];
+        record.key = u16::from_le_bytes([record.bytes[1], record.bytes[2]]);
+        record.len = record.bytes[3] as usize;
+        let total = RECORD_OVERHEAD + record.len;
+        if !matches!(record.kind, RECORD_VALUE | RECORD_REMOVED)
+            || total > RECORD_MAX_LEN
+            || total > self.device.page_size()
+        {
+            return Ok(None);
+        }
+
+        self.device.read(
+            offset + RECORD_HEADER_LEN,
+            &mut record.bytes[RECORD_HEADER_LEN..total],
+        )?;
+        let crc_offset = RECORD_HEADER_LEN + record.len;
+        let crc = crc32(&record.bytes[..crc_offset]).to_le_bytes();
+        Ok((record.bytes[crc_offset..total] == crc).then_some(record))
+    }
+
+    /// The newest record of `key`, if it holds a value and wasn't removed.
+    fn find_value(&mut self, key: u16) -> Result<Option<Record>, B::Error> {
+        match self.find(key)? {
+            Some(position) => Ok(self
+                .read_record(position)?
+                .filter(|record| record.kind == RECORD_VALUE)),
+            None => Ok(None),
+        }
+    }
+
+    /// Number of records in `sector`, which hold the current value of their key.
+    fn live_records(&mut self, sector: usize) -> Result<usize, B::Error> {
+        let mut count = 0;
+        for page in 1..self.pages_per_sector() {
+            if let Some(record) = self.read_record((sector, page))? {
+                if record.kind == RECORD_VALUE && self.find(record.key)? == Some((sector, page)) {
+                    count += 1;
+                }
+            }
+        }
+        Ok(count)
+    }
+
+    /// Position of the newest record of `key`, including removals.
+    fn find(&mut self, key: u16) -> Result<Option<Position>, B::Error> {
+        let mut found = None;
+        for (sector, _) in self.sectors_in_order()? {
+            for page in 1..self.pages_per_sector() {
+                if let Some(record) = self.read_record((sector, page))? {
+                    if record.key == key {
+                        found = Some((sector, page));
+                    }
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    /// Erases `sector` and makes it the active one.
+    fn start_sector(&mut self, sector: usize, sequence: u32) -> Result<(), B::Error> {
+        self.device.erase(sector)?;
+        let mut page = vec![0xFF; self.device.page_size()];
+        page[..4].copy_from_slice(&SECTOR_MAGIC);
+        page[4..8].copy_from_slice(&sequence.to_le_bytes());
+        let crc = crc32(&page[..8]);
+        page[8..SECTOR_HEADER_LEN].copy_from_slice(&crc.to_le_bytes());
+        self.device.program(self.offset((sector, 0)), &page)?;
+        self.active = Some((sector, sequence));
+        self.next_page = 1;
+        Ok(())
+    }
+
+    /// Writes the record to the next free page. Fails with `Full`, if the active sector is full.
+    fn program_record(
+        &mut self,
+        kind: u8,
+        key: u16,
+        value: &[u8],
+    ) -> Result<(), StorageError<B::Error>> {
+        let Some((sector, _)) = self.active else {
+            return Err(StorageError::Full);
+        };
+        if self.next_page >= self.pages_per_sector() {
+            return Err(StorageError::Full);
+        }
+
+        let mut page = vec![0xFF; self.device.page_size()];
+        let crc_offset = RECORD_HEADER_LEN + value.len();
+        page[0] = kind;
+        page[1..3].copy_from_slice(&key.to_le_bytes());
+        page[3] = value.len() as u8;
+        page[RECORD_HEADER_LEN..crc_offset].copy_from_slice(value);
+        let crc = crc32(&page[..crc_offset]);
+        page[crc_offset..crc_offset + 4].copy_from_slice(&crc.to_le_bytes());
+
+        // The page counts as used, even if programming fails halfway.
+        let position = (sector, self.next_page);
+        self.next_page += 1;
+        self.device.program(self.offset(position), &page)?;
+        Ok(())
+    }
+
+    fn append(&mut self, kind: u8, key: u16, value: &[u8]) -> Result<(), StorageError<B::Error>> {
+        match self.active {
+            None => self.start_sector(0, 1)?,
+            Some((sector, sequence)) if self.next_page >= self.pages_per_sector() => {
+                // Starting the next sector requires compacting the oldest one into it. Give up
+                // before erasing anything, if the record wouldn't fit afterwards.
+                let sectors = self.sectors_in_order()?;
+                if sectors.len() + 1 >= self.device.sector_count()
+                    && self.live_records(sectors[0].0)? + 1 >= self.pages_per_sector()
+                {
+                    return Err(StorageError::Full);
+                }
+                let next = (sector + 1) % self.device.sector_count();
+                self.start_sector(next, sequence.wrapping_add(1))?;
+                self.ensure_free_sector()?;
+            }
+            Some(_) => {}
+        }
+        self.program_record(kind, key, value)
+    }
+
+    /// If all sectors are in use, the current values of the oldest one are copied to the active
+    /// sector and the oldest one is erased.
+    fn ensure_free_sector(&mut self) -> Result<(), StorageError<B::Error>> {
+        let sectors = self.sectors_in_order()?;
+        if sectors.len() < self.device.sector_count() {
+            return Ok(());
+        }
+
+        let (oldest, _) = sectors[0];
+        for page in 1..self.pages_per_sector() {
+            let Some(record) = self.read_record((oldest, page))? else {
+                continue;
+            };
+            // Older values of the key are gone, so removals don't have to be kept.
+            if record.kind == RECORD_VALUE && self.find(record.key)? == Some((oldest, page)) {
+                self.program_record(RECORD_VALUE, record.key, record.value())?;
+            }
+        }
+        self.device.erase(oldest)?;
+        Ok(())
+    }
+}
+
+impl<B: BlockDevice> Storage for LogStorage<B> {
+    type Error = StorageError<B::Error>;
+
+    fn read(&mut self, key: u16, buffer: &mut [u8]) -> Result<Option<usize>, Self::Error> {
+        let Some(record) = self.find_value(key)? else {
+            return Ok(None);
+        };
+        let value = record.value();
+        let len = value.len().min(buffer.len());
+        buffer[..len].copy_from_slice(&value[..len]);
+        Ok(Some(value.len()))
+    }
+
+    fn write(&mut self, key: u16, value: &[u8]) -> Result<(), Self::Error> {
+        if value.len() > self.max_value_len() {
+            return Err(StorageError::TooLarge);
+        }
+        self.append(RECORD_VALUE, key, value)
+    }
+
+    fn remove(&mut self, key: u16) -> Result<(), Self::Error> {
+        match self.find_value(key)? {
+            Some(_) => self.append(RECORD_REMOVED, key, &[]),
+            None => Ok(()),
+        }
+    }
+}
+
+/// CRC-32 (IEEE). Bitwise, as the records are short.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// The device lost power during a write. Returned by [`RamFlash`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PowerLoss;
+
+/// [`BlockDevice`] in RAM for the simulator and tests. Can simulate a power loss after a number
+/// of written bytes, which leaves the write it happens in incomplete.
+pub struct RamFlash {
+    data: Vec<u8>,
+    sector_size: usize,
+    page_size: usize,
+    erase_counts: Vec<u32>,
+    /// Bytes which can be erased or programmed until the power is cut
+    power_budget: Option<usize>,
+}
+
+impl RamFlash {
+    pub fn new(sector_size: usize, sector_count: usize, page_size: usize) -> Self {
+        debug_assert!(sector_size.is_multiple_of(page_size));
+        Self {
+            data: vec![0xFF; sector_size * sector_count],
+            sector_size,
+            page_size,
+            erase_counts: vec![0; sector_count],
+            power_budget: None,
+        }
+    }
+
+    /// Cuts the power after `bytes` erased or programmed bytes. All writes fail afterwards until
+    /// [`RamFlash::restore_power`].
+    pub fn cut_power_after(&mut self, bytes: usize) {
+        self.power_budget = Some(bytes);
+    }
+
+    pub fn restore_power(&mut self) {
+        self.power_budget = None;
+    }
+
+    /// How often each sector was erased.
+    pub fn erase_counts(&self) -> &[u32] {
+        &self.erase_counts
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Consumes the budget for `len` bytes. Returns how many bytes can still be written.
+    fn use_power(&mut self, len: usize) -> usize {
+        match self.power_budget.as_mut() {
+            None => len,
+            Some(budget) => {
+                let possible = len.min(*budget);
+                *budget -= possible;
+                possible
+            }
+        }
+    }
+}
+
+impl BlockDevice for RamFlash {
+    type Error = PowerLoss;
+
+    fn sector_size(&self) -> usize {
+        self.sector_size
+    }
+
+    fn sector_count(&self) -> usize {
+        self.erase_counts.len()
+    }
+
+    fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    fn read(&mut self, offset: usize, buffer: &mut [u8]) -> Result<(), PowerLoss> {
+        buffer.copy_from_slice(&self.data[offset..offset + buffer.len()]);
+        Ok(())
+    }
+
+    fn erase(&mut self, sector: usize) -> Result<(), PowerLoss> {
+        let start = sector * self.sector_size;
+        let erased = self.use_power(self.sector_size);
+        self.data[start..start + erased].fill(0xFF);
+        if erased < self.sector_size {
+            return Err(PowerLoss);
+        }
+        self.erase_counts[sector] += 1;
+        Ok(())
+    }
+
+    fn program(&mut self, offset: usize, data: &[u8]) -> Result<(), PowerLoss> {
+        debug_assert!(
+            offset.is_multiple_of(self.page_size) && data.len().is_multiple_of(self.page_size)
+        );
+        let programmed = self.use_power(data.len());
+        for (byte, new) in self.data[offset..].iter_mut().zip(&data[..programmed]) {
+            *byte &= *new;
+        }
+        if programmed < data.len() {
+            return Err(PowerLoss);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tinyrand::{Rand, Seeded, StdRand};
+
+    use super::*;
+
+    fn flash() -> RamFlash {
+        RamFlash::new(256, 4, 32)
+    }
+
+    fn read(storage: &mut LogStorage<RamFlash>, key: u16) -> Option<Vec<u8>> {
+        let mut buffer = [0; MAX_VALUE_LEN];
+        let len = storage.read(key, &mut buffer).unwrap()?;
+        Some(buffer[..len].to_vec())
+    }
+
+    #[test]
+    fn values_survive_remount() {
+        let mut storage = LogStorage::mount(flash()).unwrap();
+        assert_eq!(read(&mut storage, 1), None);
+
+        storage.write(1, b"one").unwrap();
+        storage.write(2, b"two").unwrap();
+        storage.write(1, b"uno").unwrap();
+        storage.remove(2).unwrap();
+        assert_eq!(storage.write(3, &[0; 32]), Err(StorageError::TooLarge));
+
+        let mut storage = LogStorage::mount(storage.into_device()).unwrap();
+        assert_eq!(read(&mut storage, 1).as_deref(), Some(&b"uno"[..]));
+        assert_eq!(read(&mut storage, 2), None);
+    }
+
+    #[test]
+    fn sectors_wear_evenly() {
+        let mut storage = LogStorage::mount(flash()).unwrap();
+        for i in 0..500u16 {
+            storage.write(i % 3, &i.to_le_bytes()).unwrap();
+        }
+        for i in 497..500u16 {
+            assert_eq!(
+                read(&mut storage, i % 3).as_deref(),
+                Some(&i.to_le_bytes()[..])
+            );
+        }
+
+        let counts = storage.device().erase_counts();
+        let (min, max) = (counts.iter().min().unwrap(), counts.iter().max().unwrap());
+        assert!(*min > 10 && max - min <= 1, "{:?}", counts);
+    }
+
+    #[test]
+    fn full_when_live_values_exceed_a_sector() {
+        let mut storage = LogStorage::mount(flash()).unwrap();
+        let result = (0..100).try_for_each(|key| storage.write(key, b"value"));
+        assert_eq!(result, Err(StorageError::Full));
+    }
+
+    #[test]
+    fn recovers_last_good_value_after_power_loss() {
+        const KEYS: usize = 4;
+        let mut prng = StdRand::seed(0x5EED);
+        for _ in 0..300 {
+            let mut storage = LogStorage::mount(flash()).unwrap();
+            // Written once, so it has to survive all compactions
+            storage.write(100, b"static").unwrap();
+            let mut committed: [Option<[u8; 4]>; KEYS] = [None; KEYS];
+
+            for _ in 0..5 {
+                storage
+                    .device_mut()
+                    .cut_power_after(prng.next_lim_usize(2000));
+                let mut in_flight = None;
+                for round in 0..100 {
+                    let key = round % KEYS;
+                    let value = prng.next_u32().to_le_bytes();
+                    if storage.write(key as u16, &value).is_err() {
+                        in_flight = Some((key, value));
+                        break;
+                    }
+                    committed[key] = Some(value);
+                }
+
+                let mut flash = storage.into_device();
+                flash.restore_power();
+                storage = LogStorage::mount(flash).unwrap();
+                assert_eq!(read(&mut storage, 100).as_deref(), Some(&b"static"[..]));
+                for (key, committed) in committed.iter_mut().enumerate() {
+                    let current = read(&mut storage, key as u16);
+                    let expected = committed.map(|value| value.to_vec());
+                    match in_flight {
+                        // The interrupted write either completed or left the previous value.
+                        Some((k, value)) if k == key && current.as_deref() == Some(&value[..]) => {
+                            *committed = Some(value);
+                        }
+                        _ => assert_eq!(current, expected, "Key {}", key),
+                    }
+                }
+            }
+        }
+    }
+}
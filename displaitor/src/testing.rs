@@ -0,0 +1,281 @@
+//! Headless runs of apps for tests. An app is driven with scripted controls at a fixed frame rate
+//! through a [`Scheduler`], like in the top-level loops, and its frames are compared against golden
+//! images in `displaitor/golden`.
+//!
+//! Golden images are binary PPM files, which most image viewers open. Set `UPDATE_GOLDEN=1` to
+//! write the current frames as the new golden images, e.g. after an intended visual change.
+
+extern crate std;
+
+use std::{
+    env, fmt::Write as _, format, fs, path::PathBuf, string::String, vec::Vec,
+};
+
+use embedded_graphics::{
+    pixelcolor::{Rgb565, Rgb888},
+    prelude::*,
+};
+
+use crate::{App, Controls, FrameBuffer, Scheduler, UpdateResult};
+
+/// Time between two iterations of the loop, 50 frames per second
+pub const FRAME_US: i64 = 20_000;
+
+pub type Frame<const W: usize, const H: usize> = FrameBuffer<W, H, Rgb565>;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Button {
+    A,
+    B,
+    S,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Button {
+    pub fn controls(self) -> Controls {
+        let mut controls = Controls::default();
+        match self {
+            Button::A => controls.buttons_a = true,
+            Button::B => controls.buttons_b = true,
+            Button::S => controls.buttons_s = true,
+            Button::Up => controls.dpad_up = true,
+            Button::Down => controls.dpad_down = true,
+            Button::Left => controls.dpad_left = true,
+            Button::Right => controls.dpad_right = true,
+        }
+        controls
+    }
+}
+
+/// A step of a script, each one takes at least a frame.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Step {
+    /// Frames without input
+    Wait(usize),
+    /// Pressed for one frame, see [`Harness::hold`]
+    Press(Button),
+    /// Held down for the frames, see [`Harness::hold`]
+    Hold(Button, usize),
+}
+
+/// Runs an app against a `W`x`H` frame buffer with a simulated clock.
+pub struct Harness<A, const W: usize, const H: usize> {
+    app: A,
+    frame: Frame<W, H>,
+    scheduler: Scheduler,
+    t_us: i64,
+}
+
+impl<A, const W: usize, const H: usize> Harness<A, W, H>
+where
+    A: App<Target = Frame<W, H>, Color = Rgb565>,
+{
+    /// Starts the app like a container does, with `reset_state` and `on_enter`.
+    pub fn new(mut app: A) -> Self {
+        app.reset_state();
+        let _ = app.on_enter();
+        Self {
+            app,
+            frame: Frame::new(),
+            scheduler: Scheduler::new(),
+            t_us: 0,
+        }
+    }
+
+    pub fn app(&self) -> &A {
+        &self.app
+    }
+
+    pub fn app_mut(&mut self) -> &mut A {
+        &mut self.app
+    }
+
+    pub fn t_us(&self) -> i64 {
+        self.t_us
+    }
+
+    /// Advances the clock by a frame and runs the updates of the app, which are due.
+    pub fn step(&mut self, controls: &Controls) -> UpdateResult {
+        self.t_us += FRAME_US;
+        self.scheduler.update(&mut self.app, FRAME_US, controls)
+    }
+
+    pub fn wait(&mut self, frames: usize) {
+        for _ in 0..frames {
+            let _ = self.step(&Controls::default());
+        }
+    }
+
+    /// Holds `button` down for `frames`, then releases it for two frames. A release is only
+    /// noticed by the apps, if the button stays up for more than a frame.
+    pub fn hold(&mut self, button: Button, frames: usize) {
+        for _ in 0..frames {
+            let _ = self.step(&button.controls());
+        }
+        self.wait(2);
+    }
+
+    pub fn press(&mut self, button: Button) {
+        self.hold(button, 1);
+    }
+
+    pub fn run(&mut self, script: &[Step]) {
+        for step in script {
+            match *step {
+                Step::Wait(frames) => self.wait(frames),
+                Step::Press(button) => self.press(button),
+                Step::Hold(button, frames) => self.hold(button, frames),
+            }
+        }
+    }
+
+    /// Renders the app onto a black frame, like a full redraw of the `Renderer`.
+    pub fn render(&mut self) -> &Frame<W, H> {
+        let _ = self.frame.clear(Rgb565::BLACK);
+        self.app.render(&mut self.frame);
+        &self.frame
+    }
+
+    /// Renders the app and compares the frame with the golden image `name`.
+    #[track_caller]
+    pub fn assert_golden(&mut self, name: &str) {
+        self.render();
+        assert_golden(&self.frame, name);
+    }
+}
+
+/// Compares `frame` with the golden image `name`. Panics with a description of the differences,
+/// the actual frame is written to the temporary directory for a closer look.
+#[track_caller]
+pub fn assert_golden<const W: usize, const H: usize>(frame: &Frame<W, H>, name: &str) {
+    let path = golden_dir().join(format!("{name}.ppm"));
+    let actual = frame.to_rgb888_bytes();
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(golden_dir()).unwrap();
+        fs::write(&path, encode_ppm(W, H, &actual)).unwrap();
+        return;
+    }
+
+    let Ok(file) = fs::read(&path) else {
+        panic!(
+            "Golden image {} is missing, run the tests with UPDATE_GOLDEN=1 to create it",
+            path.display()
+        );
+    };
+    let Some(expected) = decode_ppm(W, H, &file) else {
+        panic!("Golden image {} isn't a {W}x{H} PPM file", path.display());
+    };
+    if expected == actual {
+        return;
+    }
+
+    let failure = env::temp_dir().join("displaitor-golden");
+    let _ = fs::create_dir_all(&failure);
+    let failure = failure.join(format!("{name}.actual.ppm"));
+    let _ = fs::write(&failure, encode_ppm(W, H, &actual));
+    panic!(
+        "Frame differs from golden image {}\n{}Actual frame written to {}",
+        path.display(),
+        describe_diff::<W, H>(&expected, &actual),
+        failure.display()
+    );
+}
+
+fn golden_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("golden")
+}
+
+fn encode_ppm(width: usize, height: usize, rgb: &[u8]) -> Vec<u8> {
+    let mut ppm = format!("P6\n{width} {height}\n255\n").into_bytes();
+    ppm.extend_from_slice(rgb);
+    ppm
+}
+
+/// The RGB bytes of a binary PPM file of the given size.
+fn decode_ppm(width: usize, height: usize, ppm: &[u8]) -> Option<Vec<u8>> {
+    let mut rest = ppm;
+    let mut header = Vec::new();
+    while header.len() < 4 {
+        let start = rest.iter().position(|byte| !byte.is_ascii_whitespace())?;
+        let len = rest[start..]
+            .iter()
+            .position(|byte| byte.is_ascii_whitespace())?;
+        header.push(core::str::from_utf8(&rest[start..start + len]).ok()?);
+        // A single whitespace separates the header from the pixels
+        rest = &rest[start + len + 1..];
+    }
+    let size = (header[1].parse().ok()?, header[2].parse().ok()?);
+    if header[0] != "P6" || size != (width, height) || header[3] != "255" {
+        return None;
+    }
+    (rest.len() == width * height * 3).then(|| rest.to_vec())
+}
+
+/// Lists the regions, which differ, and draws each of them as text. `.` is black, `#` any other
+/// colour and `X` a pixel, which differs.
+fn describe_diff<const W: usize, const H: usize>(expected: &[u8], actual: &[u8]) -> String {
+    let to_frame = |rgb: &[u8]| {
+        let mut frame = FrameBuffer::<W, H, Rgb888>::new();
+        for (i, pixel) in rgb.chunks_exact(3).enumerate() {
+            let point = Point::new((i % W) as i32, (i / W) as i32);
+            frame.set_pixel(point, Rgb888::new(pixel[0], pixel[1], pixel[2]));
+        }
+        frame
+    };
+    let (expected, actual) = (to_frame(expected), to_frame(actual));
+    let shade = |color: Option<Rgb888>| match color {
+        Some(Rgb888::BLACK) => '.',
+        _ => '#',
+    };
+
+    let mut description = String::new();
+    for area in expected.diff(&actual).iter() {
+        let _ = writeln!(
+            description,
+            "Region at {:?} of size {:?}, expected | actual | differences:",
+            area.top_left, area.size
+        );
+        for y in area.rows() {
+            let row = |f: &dyn Fn(Point) -> char| -> String {
+                area.columns().map(|x| f(Point::new(x, y))).collect()
+            };
+            let _ = writeln!(
+                description,
+                "  {} | {} | {}",
+                row(&|point| shade(expected.pixel(point))),
+                row(&|point| shade(actual.pixel(point))),
+                row(&|point| match expected.pixel(point) == actual.pixel(point) {
+                    true => '.',
+                    false => 'X',
+                }),
+            );
+        }
+    }
+    description
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ppm_roundtrip() {
+        let rgb = [1, 2, 3, 4, 5, 6];
+        let ppm = encode_ppm(2, 1, &rgb);
+        assert_eq!(decode_ppm(2, 1, &ppm), Some(rgb.to_vec()));
+        assert_eq!(decode_ppm(1, 2, &ppm), None);
+        assert_eq!(decode_ppm(2, 1, &ppm[..ppm.len() - 1]), None);
+    }
+
+    #[test]
+    fn diff_shows_the_changed_pixels() {
+        let expected = [0; 12];
+        let mut actual = expected;
+        actual[3] = 255;
+        let description = describe_diff::<2, 2>(&expected, &actual);
+        assert!(description.contains("  . | # | X\n"), "{description}");
+    }
+}
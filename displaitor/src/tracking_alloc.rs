@@ -0,0 +1,251 @@
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    cell::{Cell, RefCell},
+};
+
+use critical_section::Mutex;
+
+/// Apps tracked separately. Allocations of further apps only count into the total.
+pub const MAX_HEAP_APPS: usize = 16;
+
+/// App the allocations are attributed to, see [`attribute_heap_to`]
+static HEAP_APP: Mutex<Cell<Option<&'static str>>> = Mutex::new(Cell::new(None));
+
+/// Attributes the following allocations and frees to the app named `app`. Returns the app they
+/// were attributed to before, to restore it when the app is closed. The menus call it, when they
+/// open and close their entries, and the [`crate::Navigator`] for its named scenes.
+pub fn attribute_heap_to(app: Option<&'static str>) -> Option<&'static str> {
+    critical_section::with(|cs| HEAP_APP.borrow(cs).replace(app))
+}
+
+/// App the allocations are attributed to
+pub fn heap_app() -> Option<&'static str> {
+    critical_section::with(|cs| HEAP_APP.borrow(cs).get())
+}
+
+/// Usage of the heap in bytes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, defmt::Format)]
+pub struct HeapStats {
+    pub current: usize,
+    pub peak: usize,
+    /// Allocations and reallocations
+    pub allocations: u32,
+    /// Largest single allocation
+    pub largest: usize,
+}
+
+impl HeapStats {
+    fn alloc(&mut self, size: usize) {
+        self.current += size;
+        self.peak = self.peak.max(self.current);
+        self.allocations += 1;
+        self.largest = self.largest.max(size);
+    }
+
+    /// Memory allocated before the tracking started or by another app may be freed, so the usage
+    /// doesn't drop below zero.
+    fn dealloc(&mut self, size: usize) {
+        self.current = self.current.saturating_sub(size);
+    }
+}
+
+/// Usage of the heap attributed to an app.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, defmt::Format)]
+pub struct AppHeap {
+    pub name: &'static str,
+    pub stats: HeapStats,
+}
+
+struct Usage {
+    total: HeapStats,
+    apps: heapless::Vec<AppHeap, MAX_HEAP_APPS>,
+}
+
+impl Usage {
+    fn app(&mut self, name: Option<&'static str>) -> Option<&mut HeapStats> {
+        let name = name?;
+        let index = match self.apps.iter().position(|app| app.name == name) {
+            Some(index) => index,
+            None => {
+                let app = AppHeap {
+                    name,
+                    stats: HeapStats::default(),
+                };
+                self.apps.push(app).ok()?;
+                self.apps.len() - 1
+            }
+        };
+        Some(&mut self.apps[index].stats)
+    }
+
+    fn alloc(&mut self, app: Option<&'static str>, size: usize) {
+        self.total.alloc(size);
+        if let Some(stats) = self.app(app) {
+            stats.alloc(size);
+        }
+    }
+
+    fn dealloc(&mut self, app: Option<&'static str>, size: usize) {
+        self.total.dealloc(size);
+        if let Some(stats) = self.app(app) {
+            stats.dealloc(size);
+        }
+    }
+}
+
+/// Wraps the allocator `A` and keeps track of its usage, in total and for each app, see
+/// [`attribute_heap_to`]. It never allocates itself.
+///
+/// ```ignore
+/// #[global_allocator]
+/// static HEAP: TrackingAlloc<Heap> = TrackingAlloc::new(Heap::empty());
+/// ```
+pub struct TrackingAlloc<A> {
+    inner: A,
+    usage: Mutex<RefCell<Usage>>,
+}
+
+impl<A> TrackingAlloc<A> {
+    pub const fn new(inner: A) -> Self {
+        Self {
+            inner,
+            usage: Mutex::new(RefCell::new(Usage {
+                total: HeapStats {
+                    current: 0,
+                    peak: 0,
+                    allocations: 0,
+                    largest: 0,
+                },
+                apps: heapless::Vec::new(),
+            })),
+        }
+    }
+
+    /// The wrapped allocator, e.g. to initialize it
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Usage since the start
+    pub fn stats(&self) -> HeapStats {
+        critical_section::with(|cs| self.usage.borrow_ref(cs).total)
+    }
+
+    pub fn app(&self, name: &str) -> Option<HeapStats> {
+        critical_section::with(|cs| {
+            let usage = self.usage.borrow_ref(cs);
+            let app = usage.apps.iter().find(|app| app.name == name)?;
+            Some(app.stats)
+        })
+    }
+
+    pub fn apps(&self) -> heapless::Vec<AppHeap, MAX_HEAP_APPS> {
+        critical_section::with(|cs| self.usage.borrow_ref(cs).apps.clone())
+    }
+
+    /// Runs `f` and returns the number of allocations it made. Other threads allocating at the
+    /// same time are counted as well.
+    pub fn allocations_in<R>(&self, f: impl FnOnce() -> R) -> (R, u32) {
+        let before = self.stats().allocations;
+        let result = f();
+        (result, self.stats().allocations - before)
+    }
+
+    fn record(&self, f: impl FnOnce(&mut Usage, Option<&'static str>)) {
+        critical_section::with(|cs| {
+            let app = HEAP_APP.borrow(cs).get();
+            f(&mut self.usage.borrow_ref_mut(cs), app);
+        });
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for TrackingAlloc<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc(layout);
+        if !ptr.is_null() {
+            self.record(|usage, app| usage.alloc(app, layout.size()));
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            self.record(|usage, app| usage.alloc(app, layout.size()));
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.dealloc(ptr, layout);
+        self.record(|usage, app| usage.dealloc(app, layout.size()));
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = self.inner.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            self.record(|usage, app| {
+                usage.dealloc(app, layout.size());
+                usage.alloc(app, new_size);
+            });
+        }
+        new_ptr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::alloc::System;
+
+    use super::*;
+
+    fn layout(size: usize) -> Layout {
+        Layout::from_size_align(size, 4).unwrap()
+    }
+
+    #[test]
+    fn tracks_the_usage() {
+        let heap = TrackingAlloc::new(System);
+        unsafe {
+            let a = heap.alloc(layout(100));
+            let b = heap.alloc(layout(40));
+            let b = heap.realloc(b, layout(40), 60);
+            heap.dealloc(b, layout(60));
+            heap.dealloc(a, layout(100));
+        }
+        let stats = heap.stats();
+        assert_eq!(stats.current, 0);
+        assert_eq!(stats.peak, 160);
+        assert_eq!(stats.allocations, 3);
+        assert_eq!(stats.largest, 100);
+
+        let (_, allocations) = heap.allocations_in(|| unsafe {
+            heap.dealloc(heap.alloc(layout(8)), layout(8));
+        });
+        assert_eq!(allocations, 1);
+    }
+
+    /// The attribution is global, so other tests opening apps in parallel would interfere with
+    /// a [`TrackingAlloc`].
+    #[test]
+    fn attributes_the_usage_to_the_apps() {
+        let mut usage = Usage {
+            total: HeapStats::default(),
+            apps: heapless::Vec::new(),
+        };
+        usage.alloc(None, 100);
+        usage.alloc(Some("pong"), 40);
+        usage.alloc(Some("snake"), 10);
+        usage.dealloc(Some("pong"), 40);
+        // Freed by another app than the one which allocated it
+        usage.dealloc(Some("pong"), 10);
+
+        assert_eq!(usage.total.current, 100);
+        assert_eq!(usage.total.peak, 150);
+        let pong = usage.app(Some("pong")).unwrap();
+        assert_eq!((pong.current, pong.peak, pong.allocations), (0, 40, 1));
+        assert_eq!(usage.apps.len(), 2);
+    }
+}
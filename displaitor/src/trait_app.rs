@@ -1,9 +1,10 @@
 use embedded_graphics::{
     pixelcolor::{Rgb565, Rgb888},
     prelude::*,
+    primitives::Rectangle,
 };
 
-use crate::Controls;
+use crate::{Command, Commands, Controls, DirtyRegions, Navigation, Pacing};
 
 pub trait Color: PixelColor + RgbColor + WebColors + From<Rgb888> + Clone {}
 
@@ -43,33 +44,110 @@ impl AudioID {
     }
 }
 
-#[derive(PartialEq)]
+#[derive(Clone, PartialEq, Debug)]
 pub enum RenderStatus {
+    /// Everything has to be redrawn.
     VisibleChange,
     NoVisibleChange,
+    /// Only the given regions changed. Everything else looks like the previous frame.
+    Dirty(DirtyRegions),
+}
+
+impl RenderStatus {
+    pub fn dirty(area: Rectangle) -> Self {
+        RenderStatus::Dirty(DirtyRegions::from(area))
+    }
+
+    /// Combines the changes of both.
+    pub fn merge(self, other: RenderStatus) -> RenderStatus {
+        use RenderStatus::*;
+        match (self, other) {
+            (VisibleChange, _) | (_, VisibleChange) => VisibleChange,
+            (NoVisibleChange, other) | (other, NoVisibleChange) => other,
+            (Dirty(mut regions), Dirty(other)) => {
+                regions.extend(&other);
+                Dirty(regions)
+            }
+        }
+    }
 }
 
 pub struct UpdateResult {
     pub(crate) render_result: RenderStatus,
-    pub(crate) audio_queue_request: Option<AudioID>,
+    pub(crate) commands: Commands,
+    /// Commands which didn't fit
+    pub(crate) dropped: u8,
 }
 
-impl Into<UpdateResult> for RenderStatus {
-    fn into(self) -> UpdateResult {
+impl From<RenderStatus> for UpdateResult {
+    fn from(render_result: RenderStatus) -> Self {
         UpdateResult {
-            render_result: self,
-            audio_queue_request: None,
+            render_result,
+            commands: Commands::new(),
+            dropped: 0,
         }
     }
 }
 
 impl UpdateResult {
     pub fn visible_changes(&self) -> bool {
-        self.render_result == RenderStatus::VisibleChange
+        match &self.render_result {
+            RenderStatus::VisibleChange => true,
+            RenderStatus::NoVisibleChange => false,
+            RenderStatus::Dirty(regions) => !regions.is_empty(),
+        }
+    }
+
+    pub fn render_status(&self) -> &RenderStatus {
+        &self.render_result
+    }
+
+    /// Commands in the order they were issued. Later commands override earlier ones.
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    /// Appends a command. Returns `false`, if the result is already full and the command was
+    /// dropped. Dropped commands are counted, so the top-level loop can report them.
+    pub fn push_command(&mut self, command: Command) -> bool {
+        let pushed = self.commands.push(command).is_ok();
+        if !pushed {
+            self.dropped = self.dropped.saturating_add(1);
+        }
+        pushed
+    }
+
+    /// Number of commands dropped by [`UpdateResult::push_command`]
+    pub fn dropped_commands(&self) -> u8 {
+        self.dropped
+    }
+
+    pub fn with_command(mut self, command: Command) -> Self {
+        self.push_command(command);
+        self
+    }
+
+    /// Removes all navigation intents and returns them in order.
+    pub fn take_navigation(&mut self) -> heapless::Vec<Navigation, { crate::command::MAX_COMMANDS }> {
+        let mut navigation = heapless::Vec::new();
+        for command in self.commands.iter() {
+            if let Command::Navigate(intent) = command {
+                let _ = navigation.push(*intent);
+            }
+        }
+        self.commands
+            .retain(|command| !matches!(command, Command::Navigate(_)));
+        navigation
     }
 
-    pub fn audio_queue_request(&self) -> Option<AudioID> {
-        self.audio_queue_request
+    /// Combines two results. The commands of `later` are executed after the ones of `self`.
+    pub fn merge(mut self, later: UpdateResult) -> UpdateResult {
+        self.render_result = self.render_result.merge(later.render_result);
+        self.dropped = self.dropped.saturating_add(later.dropped);
+        for command in later.commands {
+            self.push_command(command);
+        }
+        self
     }
 }
 
@@ -82,14 +160,21 @@ pub trait App {
     /// Must always bring the app in a well-defined and re-usable state.
     fn reset_state(&mut self);
 
-    /// Updates the internal state. `true` is returned, if the render would be different from the previous state.
-    /// So `false` indicates, that the previous frame can be re-used.
+    /// Updates the internal state. The `RenderStatus` describes how the render would be different from the previous state.
+    /// So `NoVisibleChange` indicates, that the previous frame can be re-used.
     #[must_use = "Skipping a expensive draw call is mandatory on embedded"]
     fn update(&mut self, dt_us: i64, t_us: i64, controls: &Controls) -> UpdateResult;
 
     /// Draw the current state to the screen.
     fn render(&self, target: &mut Self::Target);
 
+    /// Draw the parts of the current state, which are inside of `area`. The area is already cleared.
+    /// Drawing outside of it is allowed, so the default just draws everything. Apps reporting
+    /// `RenderStatus::Dirty` should only draw what intersects `area` to save time.
+    fn render_region(&self, target: &mut Self::Target, area: &Rectangle) {
+        self.render(target);
+    }
+
     /// Could be called at any time. It is guaranteed, that `reset_sate` is called called before the app is used again.
     fn teardown(&mut self) {}
 
@@ -98,4 +183,58 @@ pub trait App {
     fn close_request(&self) -> bool {
         false
     }
+
+    /// How often the app wants to be updated and rendered by the [`crate::Scheduler`]. Every
+    /// update gets the tick of the pacing as `dt_us`. Containers forward the pacing of the app
+    /// they run.
+    fn pacing(&self) -> Pacing {
+        Pacing::DEFAULT
+    }
+
+    /// Name of the innermost app run by this one, e.g. to attribute frame times to it. `None`, if
+    /// no app is running inside of this one.
+    fn active_app_name(&self) -> Option<&str> {
+        None
+    }
+
+    // Lifecycle hooks. They are driven by the containers (`Menu`, `Navigator`) and the top-level loops and
+    // return an `UpdateResult`, so side effects like starting or stopping audio can be requested.
+    // Order for an app being opened and closed again: `reset_state`, `on_enter`, [`on_pause`, `on_resume`]*,
+    // `on_exit`, `teardown`.
+
+    /// Called after `reset_state`, right before the first `update` of a freshly opened app.
+    fn on_enter(&mut self) -> UpdateResult {
+        RenderStatus::NoVisibleChange.into()
+    }
+
+    /// Called when the app is covered by another app or an overlay. No `update` calls happen until `on_resume`.
+    fn on_pause(&mut self) -> UpdateResult {
+        RenderStatus::NoVisibleChange.into()
+    }
+
+    /// Called when the app is visible again after `on_pause`, e.g. when a child app was closed.
+    fn on_resume(&mut self) -> UpdateResult {
+        RenderStatus::NoVisibleChange.into()
+    }
+
+    /// Called before `teardown` when the app is closed.
+    fn on_exit(&mut self) -> UpdateResult {
+        RenderStatus::NoVisibleChange.into()
+    }
+
+    // Requests of the pause overlay. Containers forward them to the innermost app they run.
+
+    /// Starts the app over, as if it was closed and opened again.
+    fn restart(&mut self) -> UpdateResult {
+        let exited = self.on_exit();
+        self.teardown();
+        self.reset_state();
+        exited.merge(self.on_enter())
+    }
+
+    /// Closes the innermost app run by this one, as if it requested closure. `None`, if no app is
+    /// running inside of this one.
+    fn close_inner(&mut self) -> Option<UpdateResult> {
+        None
+    }
 }
@@ -0,0 +1,400 @@
+use alloc::vec::Vec;
+use core::{
+    cell::{Cell, RefCell},
+    convert::Infallible,
+};
+
+use embedded_graphics::{pixelcolor::Rgb888, prelude::*, primitives::Rectangle};
+
+use crate::{trait_app::Color, AppError};
+
+/// Frame a view is captured into, of the size of the target. A running transition takes two of
+/// them from the heap.
+pub struct TransitionFrame<C> {
+    size: Size,
+    pixels: Vec<C>,
+}
+
+impl<C> TransitionFrame<C>
+where
+    C: PixelColor + RgbColor,
+{
+    /// A black frame, without aborting if there is no room for it.
+    pub fn try_new(size: Size) -> Result<Self, AppError> {
+        let len = size.width as usize * size.height as usize;
+        let mut pixels = Vec::new();
+        if pixels.try_reserve_exact(len).is_err() {
+            return Err(AppError::OutOfMemory);
+        }
+        pixels.resize(len, C::BLACK);
+        Ok(Self { size, pixels })
+    }
+
+    /// `None` outside of the frame.
+    pub fn pixel(&self, point: Point) -> Option<C> {
+        self.index(point).map(|index| self.pixels[index])
+    }
+
+    fn index(&self, point: Point) -> Option<usize> {
+        let (x, y) = (u32::try_from(point.x).ok()?, u32::try_from(point.y).ok()?);
+        let inside = x < self.size.width && y < self.size.height;
+        inside.then(|| (y * self.size.width + x) as usize)
+    }
+}
+
+impl<C> OriginDimensions for TransitionFrame<C> {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+impl<C> DrawTarget for TransitionFrame<C>
+where
+    C: PixelColor + RgbColor,
+{
+    type Color = C;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if let Some(index) = self.index(point) {
+                self.pixels[index] = color;
+            }
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        self.pixels.fill(color);
+        Ok(())
+    }
+}
+
+/// Draw targets, whose drawing can be redirected into a frame. Apps only draw to their target, so
+/// transitions capture the outgoing and the incoming view this way.
+pub trait Capture: DrawTarget + Sized {
+    /// Draws into `frame` from now on instead of the target, or to the target again for `None`.
+    /// Returns the frame drawn into before, so captures can be nested.
+    fn redirect(
+        &mut self,
+        frame: Option<TransitionFrame<Self::Color>>,
+    ) -> Option<TransitionFrame<Self::Color>>;
+}
+
+/// [`Capture::redirect`] of the target
+type Redirect<D, C> = fn(&mut D, Option<TransitionFrame<C>>) -> Option<TransitionFrame<C>>;
+
+/// How the incoming view replaces the outgoing one.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, defmt::Format)]
+pub enum TransitionKind {
+    /// Instant switch, no blending
+    Cut,
+    /// The outgoing view fades to black during the first half, the incoming one fades in after.
+    FadeThroughBlack,
+    /// The incoming view is revealed from left to right.
+    Wipe,
+    /// The incoming view pushes the outgoing one out to the left.
+    Slide,
+    /// The incoming view replaces the outgoing one pixel by pixel in a scattered order.
+    Dissolve,
+}
+
+/// Blends from one view to another over a duration.
+///
+/// The owner requests the transition, when it switches the views. Both views are captured by the
+/// next render, so the apps behind them are not updated while it runs. The frames are taken from
+/// the heap, when the views are captured, in the size of the target, and freed again, when the
+/// transition ends. Without room for them, the views are switched without a transition.
+pub struct Transition<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    kind: TransitionKind,
+    duration_us: i64,
+    elapsed_us: i64,
+    /// Requested and not over yet. Rendering stops it, if the views can't be captured.
+    running: Cell<bool>,
+    /// Outgoing and incoming view, once they are captured
+    frames: RefCell<Option<(TransitionFrame<C>, TransitionFrame<C>)>>,
+    redirect: Redirect<D, C>,
+}
+
+impl<D, C> Transition<D, C>
+where
+    D: DrawTarget<Color = C>,
+    C: Color,
+{
+    pub fn new(kind: TransitionKind, duration_us: i64) -> Self
+    where
+        D: Capture,
+    {
+        Self {
+            kind,
+            duration_us,
+            elapsed_us: 0,
+            running: Cell::new(false),
+            frames: RefCell::new(None),
+            redirect: D::redirect,
+        }
+    }
+
+    pub fn kind(&self) -> TransitionKind {
+        self.kind
+    }
+
+    pub fn duration_us(&self) -> i64 {
+        self.duration_us
+    }
+
+    /// Also while waiting for the views to be captured
+    pub fn is_running(&self) -> bool {
+        self.running.get()
+    }
+
+    /// Whether the views were captured, so the outgoing one can be closed.
+    pub fn is_captured(&self) -> bool {
+        self.is_running() && self.frames.borrow().is_some()
+    }
+
+    /// Starts the transition, the views are captured by the next [`Transition::render`]. Returns
+    /// `false`, if the views are switched right away instead, for a cut.
+    pub fn request(&mut self) -> bool {
+        if self.kind == TransitionKind::Cut || self.duration_us <= 0 {
+            return false;
+        }
+        self.elapsed_us = 0;
+        *self.frames.get_mut() = None;
+        self.running.set(true);
+        true
+    }
+
+    /// Drops the views, so the incoming one is shown right away.
+    pub fn stop(&mut self) {
+        self.running.set(false);
+        *self.frames.get_mut() = None;
+    }
+
+    /// Advances the transition. Returns `true`, while it is running.
+    pub fn update(&mut self, dt_us: i64) -> bool {
+        if !self.is_running() {
+            return false;
+        }
+        // The time only runs once the views are shown
+        if self.frames.get_mut().is_some() {
+            self.elapsed_us += dt_us;
+        }
+        if self.elapsed_us >= self.duration_us {
+            self.stop();
+        }
+        true
+    }
+
+    /// Progress from 0 to 256
+    fn progress(&self) -> u32 {
+        (self.elapsed_us.clamp(0, self.duration_us) * 256 / self.duration_us.max(1)) as u32
+    }
+
+    /// Draws the blended views. The first call after a request captures them with `outgoing` and
+    /// `incoming`. Returns `false` and draws nothing, if the transition isn't running, or stops it,
+    /// if there is no room for the frames.
+    pub fn render(
+        &self,
+        target: &mut D,
+        outgoing: impl FnOnce(&mut D),
+        incoming: impl FnOnce(&mut D),
+    ) -> bool {
+        if !self.is_running() {
+            return false;
+        }
+        let area = target.bounding_box();
+        let mut frames = self.frames.borrow_mut();
+        if frames.is_none() {
+            *frames = self.capture_views(target, area.size, outgoing, incoming);
+        }
+        let Some((outgoing_frame, incoming_frame)) = frames.as_ref() else {
+            self.running.set(false);
+            return false;
+        };
+        self.blend(target, area, outgoing_frame, incoming_frame);
+        true
+    }
+
+    /// Captures both views into frames of `size`. `None`, if there is no room for them.
+    fn capture_views(
+        &self,
+        target: &mut D,
+        size: Size,
+        outgoing: impl FnOnce(&mut D),
+        incoming: impl FnOnce(&mut D),
+    ) -> Option<(TransitionFrame<C>, TransitionFrame<C>)> {
+        // The first frame is freed again, if there is no room for the second one.
+        let outgoing_frame = TransitionFrame::try_new(size).ok()?;
+        let incoming_frame = TransitionFrame::try_new(size).ok()?;
+        let outgoing_frame = self.capture(target, outgoing_frame, outgoing)?;
+        let incoming_frame = self.capture(target, incoming_frame, incoming)?;
+        Some((outgoing_frame, incoming_frame))
+    }
+
+    /// Draws a view with `draw` into `frame`.
+    fn capture(
+        &self,
+        target: &mut D,
+        frame: TransitionFrame<C>,
+        draw: impl FnOnce(&mut D),
+    ) -> Option<TransitionFrame<C>> {
+        let before = (self.redirect)(target, Some(frame));
+        draw(target);
+        (self.redirect)(target, before)
+    }
+
+    fn blend(
+        &self,
+        target: &mut D,
+        area: Rectangle,
+        outgoing: &TransitionFrame<C>,
+        incoming: &TransitionFrame<C>,
+    ) {
+        let progress = self.progress();
+        let width = area.size.width as i32;
+        let pixel = |frame: &TransitionFrame<C>, point| frame.pixel(point).unwrap_or(C::BLACK);
+
+        let colors = area.points().map(|point| {
+            let x = point.x - area.top_left.x;
+            match self.kind {
+                TransitionKind::Cut => pixel(incoming, point),
+                TransitionKind::FadeThroughBlack if progress < 128 => {
+                    scale(pixel(outgoing, point), (128 - progress) * 2)
+                }
+                TransitionKind::FadeThroughBlack => {
+                    scale(pixel(incoming, point), (progress - 128) * 2)
+                }
+                TransitionKind::Wipe if x < width * progress as i32 / 256 => pixel(incoming, point),
+                TransitionKind::Wipe => pixel(outgoing, point),
+                TransitionKind::Slide => {
+                    let offset = width * progress as i32 / 256;
+                    match x < width - offset {
+                        true => pixel(outgoing, point + Point::new(offset, 0)),
+                        false => pixel(incoming, point - Point::new(width - offset, 0)),
+                    }
+                }
+                TransitionKind::Dissolve if dissolve_threshold(point) < progress => {
+                    pixel(incoming, point)
+                }
+                TransitionKind::Dissolve => pixel(outgoing, point),
+            }
+        });
+        let _ = target.fill_contiguous(&area, colors);
+    }
+}
+
+/// Scales the colour by `factor` from 0 (black) to 256 (unchanged).
+fn scale<C: Color>(color: C, factor: u32) -> C {
+    let scale = |value: u8, max: u8| (value as u32 * 255 * factor / (max as u32 * 256)) as u8;
+    C::from(Rgb888::new(
+        scale(color.r(), C::MAX_R),
+        scale(color.g(), C::MAX_G),
+        scale(color.b(), C::MAX_B),
+    ))
+}
+
+/// Scattered, but fixed value from 0 to 255 for every pixel.
+fn dissolve_threshold(point: Point) -> u32 {
+    let mut hash =
+        (point.x as u32).wrapping_mul(0x9E37_79B1) ^ (point.y as u32).wrapping_mul(0x85EB_CA77);
+    hash ^= hash >> 15;
+    hash = hash.wrapping_mul(0x2C1B_3C6D);
+    (hash >> 24) & 0xFF
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::pixelcolor::Rgb565;
+
+    use super::*;
+    use crate::{FrameBuffer, PanelLayout, Panels};
+
+    type Target<const W: usize> = Panels<FrameBuffer<W, 4, Rgb565>>;
+    type TestTransition<const W: usize> = Transition<Target<W>, Rgb565>;
+
+    fn target<const W: usize>() -> Target<W> {
+        Panels::new(FrameBuffer::new(), PanelLayout::single(Size::new(W as u32, 4)))
+    }
+
+    fn fill(color: Rgb565) -> impl FnOnce(&mut Target<8>) {
+        move |target| {
+            let _ = target.clear(color);
+        }
+    }
+
+    /// Runs the transition up to `elapsed_us` and returns the rendered frame.
+    fn frame_at(kind: TransitionKind, elapsed_us: i64) -> FrameBuffer<8, 4, Rgb565> {
+        let mut transition = TestTransition::new(kind, 1000);
+        let mut target = target();
+        assert!(transition.request());
+        assert!(transition.render(&mut target, fill(Rgb565::RED), fill(Rgb565::BLUE)));
+        assert!(transition.update(elapsed_us));
+        // The views are only captured once
+        assert!(transition.render(&mut target, |_| unreachable!(), |_| unreachable!()));
+        target.into_inner()
+    }
+
+    fn count(frame: &FrameBuffer<8, 4, Rgb565>, color: Rgb565) -> usize {
+        frame.pixels().iter().filter(|pixel| **pixel == color).count()
+    }
+
+    #[test]
+    fn blends_from_outgoing_to_incoming() {
+        let start = frame_at(TransitionKind::Wipe, 0);
+        assert_eq!(count(&start, Rgb565::RED), 32);
+        let half = frame_at(TransitionKind::Wipe, 500);
+        assert_eq!(half.pixel(Point::new(3, 0)), Some(Rgb565::BLUE));
+        assert_eq!(half.pixel(Point::new(4, 0)), Some(Rgb565::RED));
+
+        let slide = frame_at(TransitionKind::Slide, 250);
+        assert_eq!(count(&slide, Rgb565::RED), 24);
+        assert_eq!(slide.pixel(Point::new(6, 3)), Some(Rgb565::BLUE));
+
+        let black = frame_at(TransitionKind::FadeThroughBlack, 500);
+        assert_eq!(count(&black, Rgb565::BLACK), 32);
+        let dissolve = frame_at(TransitionKind::Dissolve, 500);
+        assert!((8..24).contains(&count(&dissolve, Rgb565::BLUE)));
+    }
+
+    #[test]
+    fn stops_after_the_duration() {
+        let mut transition = TestTransition::<8>::new(TransitionKind::Dissolve, 1000);
+        assert!(transition.request());
+        // The time only runs once the views are captured
+        assert!(transition.update(600));
+        assert!(transition.update(600));
+        assert!(!transition.is_captured());
+        assert!(transition.render(&mut target(), |_| {}, |_| {}));
+        assert!(transition.is_captured());
+        assert!(transition.update(600));
+        assert!(transition.update(600));
+        assert!(!transition.is_running());
+        assert!(!transition.update(600));
+
+        let mut cut = TestTransition::<8>::new(TransitionKind::Cut, 1000);
+        assert!(!cut.request());
+        assert!(!cut.is_running());
+    }
+
+    #[test]
+    fn frames_have_the_size_of_the_target() {
+        let mut transition = TestTransition::<128>::new(TransitionKind::Wipe, 1000);
+        let mut target = target::<128>();
+        let fill = |color| move |target: &mut Target<128>| target.clear(color).unwrap();
+        assert!(transition.request());
+        assert!(transition.render(&mut target, fill(Rgb565::RED), fill(Rgb565::BLUE)));
+        assert!(transition.update(500));
+        assert!(transition.render(&mut target, |_| {}, |_| {}));
+        let frame = target.into_inner();
+        assert_eq!(frame.pixel(Point::new(63, 3)), Some(Rgb565::BLUE));
+        assert_eq!(frame.pixel(Point::new(64, 3)), Some(Rgb565::RED));
+    }
+}
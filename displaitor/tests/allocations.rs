@@ -0,0 +1,128 @@
+//! The apps have to run on the small heap of the badge, so they allocate when they are opened, but
+//! never while they run. Neither do the menus and the overlay around them. A single test, so no
+//! other test allocates at the same time.
+//!
+//! Submitting a high score adds its table to the shared ones, so A isn't pressed on the game over
+//! screen.
+
+use std::alloc::System;
+
+use displaitor::{
+    apps::{Dummy, DynamicEntry, DynamicMenu, Launcher, MenuEntry, SettingsApp},
+    games::{Pong, Snake},
+    App, AppMeta, Canvas, Command, Controls, Dimmed, DisplayInfo, FrameBuffer, LogStorage,
+    PanelLayout, Panels, RamFlash, Settings, TrackingAlloc,
+};
+use embedded_graphics::{pixelcolor::Rgb565, prelude::*};
+
+#[global_allocator]
+static HEAP: TrackingAlloc<System> = TrackingAlloc::new(System);
+
+type Frame = FrameBuffer<64, 32, Rgb565>;
+
+const FRAME_US: i64 = 20_000;
+
+/// Runs `app` for a few seconds with the `controls` of each frame and returns the allocations it
+/// made while updating and rendering.
+fn allocations_while_running(
+    app: &mut dyn App<Target = Frame, Color = Rgb565>,
+    controls: fn(i64) -> Controls,
+) -> u32 {
+    allocations_while_rendering_to(app, &mut Frame::new(), controls)
+}
+
+fn allocations_while_rendering_to<D: DrawTarget<Color = Rgb565>>(
+    app: &mut dyn App<Target = D, Color = Rgb565>,
+    target: &mut D,
+    controls: fn(i64) -> Controls,
+) -> u32 {
+    app.reset_state();
+    let _ = app.on_enter();
+    let ((), allocations) = HEAP.allocations_in(|| {
+        for i in 0..200 {
+            let _ = app.update(FRAME_US, i * FRAME_US, &controls(i));
+            app.render(target);
+        }
+    });
+    let _ = app.on_exit();
+    app.teardown();
+    allocations
+}
+
+/// The d-pad held up and right now and then
+fn dpad(i: i64) -> Controls {
+    Controls {
+        dpad_up: i % 40 < 10,
+        dpad_right: i % 50 > 30,
+        ..Default::default()
+    }
+}
+
+/// Like [`dpad`], with S pressed now and then to pause and resume
+fn dpad_and_pause(i: i64) -> Controls {
+    Controls {
+        buttons_s: i % 60 == 20,
+        ..dpad(i)
+    }
+}
+
+#[test]
+fn apps_do_not_allocate_while_running() {
+    let display = DisplayInfo::default();
+    let mut pong = Pong::<Frame, Rgb565>::new(display);
+    assert_eq!(allocations_while_running(&mut pong, dpad), 0, "pong");
+    let mut snake = Snake::<64, Frame, Rgb565>::new(display);
+    assert_eq!(allocations_while_running(&mut snake, dpad), 0, "snake");
+
+    let mut menu = DynamicMenu::<Frame, Rgb565>::new()
+        .with_entry(DynamicEntry::header("Games"))
+        .with_entry(DynamicEntry::app("Dummy", Box::new(Dummy::new())))
+        .with_entry(DynamicEntry::command("Mute", Command::stop_music()))
+        .with_entry(DynamicEntry::command("Dim", Command::Brightness(16)));
+    assert_eq!(
+        allocations_while_running(&mut menu, dpad),
+        0,
+        "dynamic menu"
+    );
+    // The heap is attributed to an opened app, until it closes itself
+    menu.reset_state();
+    let pressed = Controls {
+        buttons_a: true,
+        ..Default::default()
+    };
+    let _ = menu.update(FRAME_US, 0, &pressed);
+    let _ = menu.update(FRAME_US, 0, &Controls::default());
+    assert_eq!(displaitor::heap_app(), Some("Dummy"));
+    let _ = menu.update(FRAME_US, 0, &Controls::default());
+    assert_eq!(displaitor::heap_app(), None);
+    let mut launcher = Launcher::<3, Frame, Rgb565>::new(
+        ["Games", "Demos", "Tools"].map(|name| MenuEntry::new(name, Box::new(Dummy::new()))),
+    );
+    assert_eq!(
+        allocations_while_running(&mut launcher, dpad),
+        0,
+        "launcher"
+    );
+    let start_apps = vec![<Pong<Frame, Rgb565> as AppMeta>::INFO];
+    let mut settings = SettingsApp::<Frame, Rgb565>::new(Settings::default(), start_apps);
+    assert_eq!(
+        allocations_while_running(&mut settings, dpad),
+        0,
+        "settings"
+    );
+
+    // The pause overlay around the navigator, which shows the launcher. The transitions capture
+    // the views on the canvas.
+    let mut main_app = displaitor::main_app::<Canvas<Frame>, Rgb565, LogStorage<RamFlash>>(
+        &Settings::default(),
+        None,
+        display,
+    );
+    let layout = PanelLayout::single(Size::new(64, 32));
+    let mut canvas = Dimmed::new(Panels::new(Frame::new(), layout));
+    assert_eq!(
+        allocations_while_rendering_to(&mut main_app, &mut canvas, dpad_and_pause),
+        0,
+        "main app"
+    );
+}
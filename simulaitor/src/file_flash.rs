@@ -0,0 +1,82 @@
+use displaitor::BlockDevice;
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+/// Emulates the flash of the firmware with a file, so the stored values survive a restart.
+pub struct FileFlash {
+    file: File,
+    sector_size: usize,
+    sector_count: usize,
+    page_size: usize,
+}
+
+impl FileFlash {
+    /// Opens or creates the file. A new or shorter file is extended with erased sectors.
+    pub fn open(
+        path: impl AsRef<Path>,
+        sector_size: usize,
+        sector_count: usize,
+        page_size: usize,
+    ) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let mut flash = Self {
+            file,
+            sector_size,
+            sector_count,
+            page_size,
+        };
+        let len = flash.file.metadata()?.len() as usize;
+        for sector in len.div_ceil(sector_size)..sector_count {
+            flash.erase(sector)?;
+        }
+        Ok(flash)
+    }
+}
+
+impl BlockDevice for FileFlash {
+    type Error = io::Error;
+
+    fn sector_size(&self) -> usize {
+        self.sector_size
+    }
+
+    fn sector_count(&self) -> usize {
+        self.sector_count
+    }
+
+    fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    fn read(&mut self, offset: usize, buffer: &mut [u8]) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(offset as u64))?;
+        self.file.read_exact(buffer)
+    }
+
+    fn erase(&mut self, sector: usize) -> io::Result<()> {
+        self.file
+            .seek(SeekFrom::Start((sector * self.sector_size) as u64))?;
+        self.file.write_all(&vec![0xFF; self.sector_size])?;
+        self.file.flush()
+    }
+
+    /// Like the flash, programming only clears bits.
+    fn program(&mut self, offset: usize, data: &[u8]) -> io::Result<()> {
+        let mut current = vec![0; data.len()];
+        self.read(offset, &mut current)?;
+        for (byte, new) in current.iter_mut().zip(data) {
+            *byte &= new;
+        }
+        self.file.seek(SeekFrom::Start(offset as u64))?;
+        self.file.write_all(&current)?;
+        self.file.flush()
+    }
+}
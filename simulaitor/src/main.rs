@@ -1,108 +1,321 @@
-use displaitor::{App, Controls};
-use embedded_graphics::{pixelcolor::Rgb565, prelude::*};
-use embedded_graphics_simulator::{
-    sdl2::Keycode, OutputSettingsBuilder, SimulatorDisplay, SimulatorEvent, Window,
-};
 use std::{
+    cell::RefCell,
+    rc::Rc,
     thread::sleep,
     time::{Duration, Instant},
 };
 
-const SCREEN_HEIGHT: u32 = 32;
-const SCREEN_WIDTH: u32 = 64;
+use displaitor::{
+    AudioID, AudioOut, BufferMode, Clock, Controls, DisplayInfo, Event, FrameProfile, Input,
+    LogStorage, PanelLayout, Runtime, Screen, TimeControl, Timing,
+};
+use embedded_graphics::{
+    pixelcolor::{Rgb565, Rgb888},
+    prelude::*,
+};
+use embedded_graphics_simulator::{
+    sdl2::Keycode, OutputSettingsBuilder, SimulatorDisplay, SimulatorEvent, Window,
+};
+use file_flash::FileFlash;
 
-fn main() -> Result<(), core::convert::Infallible> {
-    let mut app = displaitor::main_app();
-    run_app(&mut app);
-    Ok(())
-}
+mod file_flash;
 
-pub fn run_app<T>(app: &mut T)
-where
-    T: App<Target = SimulatorDisplay<Rgb565>, Color = Rgb565>,
-{
-    let mut display = SimulatorDisplay::<Rgb565>::new(Size::new(SCREEN_WIDTH, SCREEN_HEIGHT));
+/// Stands in for the storage region in the flash of the firmware, with the same geometry
+const STORAGE_FILE: &str = "simulaitor_storage.bin";
+
+type Store = LogStorage<FileFlash>;
+
+fn main() -> Result<(), core::convert::Infallible> {
+    let panel = panel_from_args();
     let output_settings = OutputSettingsBuilder::new()
         // .theme(BinaryColorTheme::OledBlue)
         .scale(4)
         .pixel_spacing(4)
         .build();
-
     let mut window = Window::new("Displaitor Simulator", &output_settings);
+    let display = SimulatorDisplay::<Rgb565>::new(panel.size);
+    // Events can only be read from a window, which was shown
+    window.update(&display);
+    let window = Rc::new(RefCell::new(window));
+
+    let mut runtime: Runtime<_, _, _, _, _, Store> = Runtime::new(
+        display,
+        PanelLayout::single(panel.size),
+        WindowScreen::new(window.clone()),
+        Keyboard::new(window),
+        SystemClock::new(),
+        PrintedAudio::default(),
+    )
+    .with_log(log);
+    if let Some(storage) = open_storage() {
+        runtime = runtime.with_storage(storage);
+    }
+    // After the storage, so it starts with the stored volume
+    runtime = runtime.with_status_bar();
 
-    // Timer for controlling the game loop
-    // let mut timer = SimulatorTimer::new(Duration::from_millis(16)); // ~60 FPS
-
-    // Initialize the app
-    // app.setup();
-
-    // Game loop
-    let mut last_time = Instant::now();
-    let mut elapsed_time = 0; // Elapsed time in milliseconds
-    let mut controls = Controls {
-        buttons_a: false,
-        buttons_b: false,
-        buttons_s: false,
-        dpad_up: false,
-        dpad_down: false,
-        dpad_left: false,
-        dpad_right: false,
+    let settings = runtime.settings().clone();
+    let mut app = displaitor::main_app(&settings, runtime.storage_mut(), panel);
+    match displaitor::startup_app() {
+        Ok(mut startup) => runtime.run(&mut startup, &mut app),
+        Err(error) => {
+            println!("No splash screen: {:?}", error);
+            runtime.run_app(&mut app);
+        }
+    }
+    Ok(())
+}
+
+/// The panel to preview, e.g. `simulaitor 128x32`. Defaults to the 64x32 panel of the badge.
+fn panel_from_args() -> DisplayInfo {
+    let Some(arg) = std::env::args().nth(1) else {
+        return DisplayInfo::default();
     };
+    let size = arg
+        .split_once('x')
+        .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)));
+    match size {
+        Some((width, height)) if width > 0 && height > 0 => DisplayInfo::new(width, height),
+        _ => {
+            println!("Invalid panel size {:?}, expected e.g. 64x32", arg);
+            DisplayInfo::default()
+        }
+    }
+}
+
+fn open_storage() -> Option<Store> {
+    let storage = FileFlash::open(STORAGE_FILE, 4096, 16, 256)
+        .map_err(displaitor::StorageError::Device)
+        .and_then(LogStorage::mount);
+    match storage {
+        Ok(storage) => Some(storage),
+        Err(err) => {
+            println!(
+                "Failed to open {}, nothing is persisted: {:?}",
+                STORAGE_FILE, err
+            );
+            None
+        }
+    }
+}
+
+/// Shows the frames in the window. The panel applies the gamma in hardware, the simulator to a
+/// copy of the frame.
+struct WindowScreen {
+    window: Rc<RefCell<Window>>,
+    gamma_lut: [u8; 256],
+}
 
-    'game_loop: loop {
-        // Calculate elapsed time
-        let now = Instant::now();
-        let dt = now.duration_since(last_time).as_micros() as i64;
-        elapsed_time += dt;
-        last_time = now;
+impl WindowScreen {
+    fn new(window: Rc<RefCell<Window>>) -> Self {
+        Self {
+            window,
+            gamma_lut: core::array::from_fn(|i| i as u8),
+        }
+    }
+}
+
+impl Screen<SimulatorDisplay<Rgb565>> for WindowScreen {
+    fn buffer_mode(&self) -> BufferMode {
+        BufferMode::Persistent
+    }
 
-        // Update the app
-        let update_result = app.update(dt, elapsed_time, &controls);
+    fn present(&mut self, display: &mut SimulatorDisplay<Rgb565>) {
+        let corrected = gamma_corrected(display, &self.gamma_lut);
+        self.window.borrow_mut().update(&corrected);
+    }
 
-        if update_result.visible_changes() {
-            // Clear the display
-            display.clear(Rgb565::BLACK).unwrap();
+    fn set_gamma_tenths(&mut self, gamma_tenths: u8) {
+        let gamma = gamma_tenths as f32 / 10.0;
+        for (i, value) in self.gamma_lut.iter_mut().enumerate() {
+            *value = (255.0 * (i as f32 / 255.0).powf(gamma)).round() as u8;
+        }
+    }
+}
 
-            // Render the app
-            app.render(&mut display);
+/// WASD is the d-pad, space A, Q B and R S. V outlines the redrawn regions, F shows the frame
+/// times and G the debug overlay. P pauses the app, N steps it by a single update, minus and equals slow it down and speed
+/// it up, 0 resets the speed.
+struct Keyboard {
+    window: Rc<RefCell<Window>>,
+    controls: Controls,
+    outline_redraws: bool,
+    show_profile: bool,
+    show_debug_overlay: bool,
+    time_control: Option<TimeControl>,
+}
 
-            // Update the window
-            window.update(&display);
+impl Keyboard {
+    fn new(window: Rc<RefCell<Window>>) -> Self {
+        Self {
+            window,
+            controls: Controls::default(),
+            outline_redraws: false,
+            show_profile: false,
+            show_debug_overlay: false,
+            time_control: None,
         }
+    }
+}
 
-        // Handle events
-        for event in window.events() {
-            match event {
-                SimulatorEvent::Quit => break 'game_loop,
-                SimulatorEvent::KeyUp { keycode, .. } => match keycode {
-                    Keycode::W => controls.dpad_up = false,
-                    Keycode::S => controls.dpad_down = false,
-                    Keycode::A => controls.dpad_left = false,
-                    Keycode::D => controls.dpad_right = false,
-                    Keycode::Space => controls.buttons_a = false,
-                    Keycode::Q => controls.buttons_b = false,
-                    Keycode::R => controls.buttons_s = false,
-                    _ => {}
-                },
-                SimulatorEvent::KeyDown { keycode, .. } => match keycode {
-                    Keycode::W => controls.dpad_up = true,
-                    Keycode::S => controls.dpad_down = true,
-                    Keycode::A => controls.dpad_left = true,
-                    Keycode::D => controls.dpad_right = true,
-                    Keycode::Space => controls.buttons_a = true,
-                    Keycode::Q => controls.buttons_b = true,
-                    Keycode::R => controls.buttons_s = true,
-                    _ => {}
-                },
+impl Input for Keyboard {
+    fn poll(&mut self) -> Option<Controls> {
+        let events: Vec<SimulatorEvent> = self.window.borrow_mut().events().collect();
+        for event in events {
+            let (keycode, pressed) = match event {
+                SimulatorEvent::Quit => return None,
+                SimulatorEvent::KeyDown { keycode, .. } => (keycode, true),
+                SimulatorEvent::KeyUp { keycode, .. } => (keycode, false),
+                _ => continue,
+            };
+            let controls = &mut self.controls;
+            match keycode {
+                Keycode::W => controls.dpad_up = pressed,
+                Keycode::S => controls.dpad_down = pressed,
+                Keycode::A => controls.dpad_left = pressed,
+                Keycode::D => controls.dpad_right = pressed,
+                Keycode::Space => controls.buttons_a = pressed,
+                Keycode::Q => controls.buttons_b = pressed,
+                Keycode::R => controls.buttons_s = pressed,
+                Keycode::V if pressed => self.outline_redraws = !self.outline_redraws,
+                Keycode::F if pressed => self.show_profile = !self.show_profile,
+                Keycode::G if pressed => self.show_debug_overlay = !self.show_debug_overlay,
+                Keycode::P if pressed => self.time_control = Some(TimeControl::TogglePause),
+                Keycode::N if pressed => self.time_control = Some(TimeControl::Step),
+                Keycode::Minus if pressed => self.time_control = Some(TimeControl::Slower),
+                Keycode::Equals if pressed => self.time_control = Some(TimeControl::Faster),
+                Keycode::Num0 if pressed => self.time_control = Some(TimeControl::NormalSpeed),
                 _ => {}
             }
         }
+        Some(self.controls)
+    }
 
-        // Wait for the next frame
-        // timer.wait();
-        sleep(Duration::from_millis(10));
+    fn outline_redraws(&self) -> bool {
+        self.outline_redraws
     }
 
-    // Cleanup
-    app.teardown();
+    fn show_profile(&self) -> bool {
+        self.show_profile
+    }
+
+    fn show_debug_overlay(&self) -> bool {
+        self.show_debug_overlay
+    }
+
+    fn time_control(&mut self) -> Option<TimeControl> {
+        self.time_control.take()
+    }
+}
+
+struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    fn new() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now_us(&mut self) -> u64 {
+        self.start.elapsed().as_micros() as u64
+    }
+
+    fn wait_us(&mut self, us: u64) {
+        sleep(Duration::from_micros(us));
+    }
+}
+
+/// There is no audio in the simulator, so it is only printed. Music is requested every frame by
+/// some apps, so only changes are printed.
+#[derive(Default)]
+struct PrintedAudio {
+    playing: Option<AudioID>,
+    volume: Option<u8>,
+}
+
+impl AudioOut for PrintedAudio {
+    fn play(&mut self, id: AudioID) {
+        if self.playing != Some(id) {
+            println!("Audio: play {:?}", id);
+            self.playing = Some(id);
+        }
+    }
+
+    fn stop(&mut self) {
+        if self.playing.take().is_some() {
+            println!("Audio: stop");
+        }
+    }
+
+    fn set_volume(&mut self, level: u8) {
+        if self.volume != Some(level) {
+            println!("Audio: volume {}", level);
+            self.volume = Some(level);
+        }
+    }
+}
+
+fn log(event: &Event) {
+    match event {
+        Event::Stored { key, len } => println!("Stored {} bytes for key {:#06x}", len, key),
+        Event::StoreFailed { key, error } => {
+            println!("Failed to store key {:#06x}: {:?}", key, error)
+        }
+        Event::Clock(clock) => match clock.is_paused() {
+            true => println!("Paused at {} ms", clock.t_us() / 1000),
+            false => println!("Running at {}% speed", clock.speed_percent()),
+        },
+        Event::Stats(stats) => println!(
+            "{} renders/s, {} updates, {} loops",
+            stats.renders_per_s(),
+            stats.updates,
+            stats.loops
+        ),
+        Event::Profile(profiler) => {
+            print_profile("all", profiler.total());
+            for app in profiler.apps() {
+                print_profile(&app.name, &app.profile);
+            }
+        }
+        other => println!("{:?}", other),
+    }
+}
+
+/// One line per app, times in µs
+fn print_profile(name: &str, profile: &FrameProfile) {
+    let timing =
+        |timing: &Timing| format!("{}/{}/{}", timing.min_us, timing.avg_us(), timing.max_us);
+    println!(
+        "{:>16}: {:4} frames | min/avg/max µs update {} render {} present {} frame {} | histogram {:?}",
+        name,
+        profile.frame.count,
+        timing(&profile.update),
+        timing(&profile.render),
+        timing(&profile.present),
+        timing(&profile.frame),
+        profile.frame.histogram
+    );
+}
+
+/// The panel applies the gamma in hardware, the simulator to a copy of the frame.
+fn gamma_corrected(
+    display: &SimulatorDisplay<Rgb565>,
+    gamma_lut: &[u8; 256],
+) -> SimulatorDisplay<Rgb565> {
+    let mut corrected = SimulatorDisplay::new(display.size());
+    let pixels = display.bounding_box().points().map(|point| {
+        let color = Rgb888::from(display.get_pixel(point));
+        let color = Rgb888::new(
+            gamma_lut[color.r() as usize],
+            gamma_lut[color.g() as usize],
+            gamma_lut[color.b() as usize],
+        );
+        Pixel(point, Rgb565::from(color))
+    });
+    corrected.draw_iter(pixels).unwrap();
+    corrected
 }